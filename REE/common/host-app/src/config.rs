@@ -0,0 +1,633 @@
+//! Configuration of a zkms server
+//!
+//! The configuration is read from a TOML file, and any flag given on the command line
+//! overrides the value in the file
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use host_common::acl::{Acl, Operation};
+use serde::Deserialize;
+use structopt::StructOpt;
+use zkms_common::CryptoAlgo;
+
+/// Port used when none is configured
+pub const DEFAULT_PORT: u16 = 39946;
+/// Port of the JSON-RPC transport when none is configured
+pub const DEFAULT_JSONRPC_PORT: u16 = 39947;
+/// Port of the author transport when none is configured
+pub const DEFAULT_AUTHOR_PORT: u16 = 39948;
+/// Peers connected to the ductile transport at the same time, when not configured
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+/// What processes the requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The TA, thru OPTEE
+    Optee,
+    /// The TA logic running in the host, keys are NOT protected
+    Software,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "optee" => Ok(Self::Optee),
+            "software" => Ok(Self::Software),
+            other => Err(format!("unknown backend {:?}", other)),
+        }
+    }
+}
+
+/// How the requests are received
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Ductile,
+    /// JSON-RPC over HTTP, peers are NOT authenticated
+    Jsonrpc,
+    /// Substrate's `author_*` key methods over WebSocket, peers are NOT authenticated
+    Author,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ductile" => Ok(Self::Ductile),
+            "jsonrpc" => Ok(Self::Jsonrpc),
+            "author" => Ok(Self::Author),
+            other => Err(format!("unknown transport {:?}", other)),
+        }
+    }
+}
+
+/// Encryption of the ductile connections with the peers
+///
+/// This is not TLS: ductile channels don't use certificates, they're encrypted with a pre-shared key
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// File with the hex encoded 32 bytes key shared with the peers
+    pub key: PathBuf,
+}
+
+impl EncryptionConfig {
+    /// Read the key from the configured file
+    pub fn load_key(&self) -> Result<[u8; 32], ConfigError> {
+        read_key(&self.key)
+    }
+}
+
+/// Secret the TA derives the key sealing the exported keys from
+///
+/// Only TAs provisioned with the same secret can import each other's sealed keys
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SealingConfig {
+    /// File with the hex encoded 32 bytes secret
+    pub secret: PathBuf,
+}
+
+impl SealingConfig {
+    /// Read the secret from the configured file
+    pub fn load_secret(&self) -> Result<[u8; 32], ConfigError> {
+        read_key(&self.secret)
+    }
+}
+
+/// A client allowed to connect
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Name the client authenticates with
+    pub name: String,
+    /// File with the hex encoded 32 bytes key of the client
+    pub key: PathBuf,
+    /// Key types the client can use, like "babe", any if missing
+    #[serde(default)]
+    pub key_types: Option<Vec<String>>,
+    /// Operations the client can do (generate, sign, vrf, manage), any if missing
+    #[serde(default)]
+    pub operations: Option<Vec<String>>,
+}
+
+impl ClientConfig {
+    /// Read the key from the configured file
+    pub fn load_key(&self) -> Result<[u8; 32], ConfigError> {
+        read_key(&self.key)
+    }
+
+    /// What the client is allowed to request
+    pub fn acl(&self) -> Result<Acl, ConfigError> {
+        let invalid = |e| ConfigError::Invalid(format!("client {:?}: {}", self.name, e));
+
+        let key_types = self
+            .key_types
+            .as_ref()
+            .map(|key_types| {
+                key_types
+                    .iter()
+                    .map(|key_type| parse_key_type(key_type))
+                    .collect::<Result<_, _>>()
+            })
+            .transpose()
+            .map_err(invalid)?;
+
+        let operations = self
+            .operations
+            .as_ref()
+            .map(|operations| {
+                operations
+                    .iter()
+                    .map(|op| Operation::from_str(op))
+                    .collect::<Result<_, _>>()
+            })
+            .transpose()
+            .map_err(invalid)?;
+
+        Ok(Acl {
+            key_types,
+            operations,
+        })
+    }
+}
+
+impl FromStr for ClientConfig {
+    type Err = String;
+
+    /// Parse `name=key_file`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(key)) if !name.is_empty() && !key.is_empty() => Ok(Self {
+                name: name.to_string(),
+                key: key.into(),
+                key_types: None,
+                operations: None,
+            }),
+            _ => Err(format!("expected name=key_file, got {:?}", s)),
+        }
+    }
+}
+
+fn parse_key_type(key_type: &str) -> Result<[u8; 4], String> {
+    let mut id = [0; 4];
+    if key_type.len() != id.len() {
+        return Err(format!("key type {:?} is not 4 bytes", key_type));
+    }
+    id.copy_from_slice(key_type.as_bytes());
+
+    Ok(id)
+}
+
+fn read_key(path: &Path) -> Result<[u8; 32], ConfigError> {
+    let hex_key = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+
+    let mut key = [0; 32];
+    hex::decode_to_slice(hex_key.trim(), &mut key)
+        .map_err(|e| ConfigError::Invalid(format!("bad key in {:?}: {}", path, e)))?;
+
+    Ok(key)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("unable to read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("malformed configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// Configuration of the server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the ductile transport listens on
+    pub listen: SocketAddr,
+    /// Address the JSON-RPC transport listens on
+    pub jsonrpc_listen: SocketAddr,
+    /// Address the author transport listens on
+    pub author_listen: SocketAddr,
+    /// Key type and algorithm of the keys generated by `author_rotateKeys`, as "type:algo"
+    pub session_keys: Vec<String>,
+    pub backend: Backend,
+    /// Filter for the logger, same syntax as `RUST_LOG`
+    pub log_level: String,
+    /// Number of requests processed at the same time
+    pub workers: usize,
+    /// Peers connected to the ductile transport at the same time, more are rejected
+    pub max_connections: usize,
+    /// UUID of the TA, needed to open sessions with it
+    pub ta_uuid: Option<String>,
+    /// Pre-shared key encrypting the ductile connections, plaintext if missing
+    pub encryption: Option<EncryptionConfig>,
+    /// Secret provisioned to the TA on start, keys can't be exported nor imported without it
+    pub sealing: Option<SealingConfig>,
+    pub transports: Vec<Transport>,
+    /// Clients allowed to connect, any peer is accepted if empty
+    pub clients: Vec<ClientConfig>,
+    /// Accept unauthenticated peers even when not listening on a loopback address
+    pub allow_unauthenticated: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: (Ipv4Addr::LOCALHOST, DEFAULT_PORT).into(),
+            jsonrpc_listen: (Ipv4Addr::LOCALHOST, DEFAULT_JSONRPC_PORT).into(),
+            author_listen: (Ipv4Addr::LOCALHOST, DEFAULT_AUTHOR_PORT).into(),
+            //the session keys of the substrate node template
+            session_keys: vec!["aura:sr25519".to_string(), "gran:ed25519".to_string()],
+            backend: Backend::Optee,
+            log_level: "info".to_string(),
+            workers: crate::DEFAULT_WORKERS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            ta_uuid: None,
+            encryption: None,
+            sealing: None,
+            transports: vec![Transport::Ductile],
+            clients: vec![],
+            allow_unauthenticated: false,
+        }
+    }
+}
+
+/// Command line flags of the server
+#[derive(Debug, Default, StructOpt)]
+#[structopt(
+    name = "zkms-server",
+    about = "Serves keystore requests with keys kept in the TEE"
+)]
+pub struct Args {
+    /// TOML configuration file
+    #[structopt(short, long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[structopt(short, long)]
+    pub listen: Option<SocketAddr>,
+    /// Address the JSON-RPC transport listens on
+    #[structopt(long)]
+    pub jsonrpc_listen: Option<SocketAddr>,
+    /// Address the author transport listens on
+    #[structopt(long)]
+    pub author_listen: Option<SocketAddr>,
+    /// Request processing backend: optee or software
+    #[structopt(short, long)]
+    pub backend: Option<Backend>,
+    /// Log filter, like `RUST_LOG`
+    #[structopt(long)]
+    pub log_level: Option<String>,
+    /// Number of requests processed at the same time
+    #[structopt(long)]
+    pub workers: Option<usize>,
+    /// Peers connected to the ductile transport at the same time
+    #[structopt(long)]
+    pub max_connections: Option<usize>,
+    /// UUID of the TA
+    #[structopt(long)]
+    pub ta_uuid: Option<String>,
+    /// File with the hex encoded key encrypting the ductile connections, shared with the peers
+    #[structopt(long, parse(from_os_str))]
+    pub encryption_key: Option<PathBuf>,
+    /// File with the hex encoded secret provisioned to the TA to seal the exported keys
+    #[structopt(long, parse(from_os_str))]
+    pub sealing_secret: Option<PathBuf>,
+    /// Enabled transport, can be repeated: ductile, jsonrpc or author
+    #[structopt(long = "transport")]
+    pub transports: Vec<Transport>,
+    /// Client allowed to connect, as name=key_file, can be repeated
+    #[structopt(long = "client")]
+    pub clients: Vec<ClientConfig>,
+    /// Accept unauthenticated peers on any address: on the jsonrpc and author transports,
+    /// or on the ductile one when no client is configured
+    #[structopt(long)]
+    pub allow_unauthenticated: bool,
+}
+
+impl Config {
+    /// Parse the configuration from the contents of a TOML file
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        toml::from_str(toml).map_err(Into::into)
+    }
+
+    /// Read the configuration from a TOML file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let toml =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+
+        Self::from_toml(&toml)
+    }
+
+    /// Read the configuration from the command line, and the configuration file if given
+    pub fn from_args() -> Result<Self, ConfigError> {
+        Self::load(Args::from_args())
+    }
+
+    /// Same as `from_args` but with the given flags
+    pub fn load(args: Args) -> Result<Self, ConfigError> {
+        let mut config = match args.config.as_deref() {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(listen) = args.listen {
+            config.listen = listen;
+        }
+        if let Some(jsonrpc_listen) = args.jsonrpc_listen {
+            config.jsonrpc_listen = jsonrpc_listen;
+        }
+        if let Some(author_listen) = args.author_listen {
+            config.author_listen = author_listen;
+        }
+        if let Some(backend) = args.backend {
+            config.backend = backend;
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(workers) = args.workers {
+            config.workers = workers;
+        }
+        if let Some(max_connections) = args.max_connections {
+            config.max_connections = max_connections;
+        }
+        if let Some(ta_uuid) = args.ta_uuid {
+            config.ta_uuid = Some(ta_uuid);
+        }
+        if let Some(key) = args.encryption_key {
+            config.encryption = Some(EncryptionConfig { key });
+        }
+        if let Some(secret) = args.sealing_secret {
+            config.sealing = Some(SealingConfig { secret });
+        }
+        if !args.transports.is_empty() {
+            config.transports = args.transports;
+        }
+        if !args.clients.is_empty() {
+            config.clients = args.clients;
+        }
+        if args.allow_unauthenticated {
+            config.allow_unauthenticated = true;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Key type and algorithm of each session key
+    pub fn parse_session_keys(&self) -> Result<Vec<([u8; 4], CryptoAlgo)>, ConfigError> {
+        self.session_keys
+            .iter()
+            .map(|key| {
+                let mut parts = key.splitn(2, ':');
+                let key_type = parse_key_type(parts.next().unwrap_or_default())?;
+                let algo = match parts.next() {
+                    Some("sr25519") => CryptoAlgo::Sr25519,
+                    Some("ed25519") => CryptoAlgo::Ed25519,
+                    Some("ecdsa") => CryptoAlgo::Ecdsa,
+                    _ => return Err(format!("expected type:algo, got {:?}", key)),
+                };
+
+                Ok((key_type, algo))
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| ConfigError::Invalid(format!("bad session key: {}", e)))
+    }
+
+    /// Check the configuration is consistent and doesn't expose the keys to unauthenticated peers
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers == 0 {
+            return Err(ConfigError::Invalid("at least 1 worker is needed".into()));
+        }
+        if self.max_connections == 0 {
+            return Err(ConfigError::Invalid(
+                "at least 1 connection is needed".into(),
+            ));
+        }
+        if self.transports.is_empty() {
+            return Err(ConfigError::Invalid("no transport enabled".into()));
+        }
+        if !self.clients.is_empty()
+            && self
+                .transports
+                .iter()
+                .any(|t| matches!(t, Transport::Jsonrpc | Transport::Author))
+        {
+            //otherwise anyone could bypass the authentication
+            return Err(ConfigError::Invalid(
+                "the jsonrpc and author transports don't authenticate clients".into(),
+            ));
+        }
+        for transport in &self.transports {
+            let (name, addr) = match transport {
+                Transport::Ductile if self.clients.is_empty() => ("ductile", self.listen),
+                Transport::Jsonrpc => ("jsonrpc", self.jsonrpc_listen),
+                Transport::Author => ("author", self.author_listen),
+                Transport::Ductile => continue,
+            };
+            if !self.allow_unauthenticated && !addr.ip().is_loopback() {
+                //anyone reaching the address could use the keys
+                return Err(ConfigError::Invalid(format!(
+                    "the {} transport listens on {} without authenticating peers, \
+                     set allow_unauthenticated to expose it anyways",
+                    name, addr
+                )));
+            }
+        }
+        self.parse_session_keys()?;
+        for (i, client) in self.clients.iter().enumerate() {
+            if self.clients[..i].iter().any(|c| c.name == client.name) {
+                return Err(ConfigError::Invalid(format!(
+                    "client {:?} configured more than once",
+                    client.name
+                )));
+            }
+            client.acl()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        listen = "127.0.0.1:4000"
+        backend = "software"
+        workers = 2
+
+        [encryption]
+        key = "/etc/zkms/key"
+
+        [sealing]
+        secret = "/etc/zkms/sealing"
+
+        [[clients]]
+        name = "node"
+        key = "/etc/zkms/node"
+        key_types = ["babe"]
+        operations = ["sign", "vrf"]
+    "#;
+
+    #[test]
+    fn parse_toml() {
+        let config = Config::from_toml(TOML).expect("valid configuration");
+
+        assert_eq!(config.listen, "127.0.0.1:4000".parse().unwrap());
+        assert_eq!(config.backend, Backend::Software);
+        assert_eq!(config.workers, 2);
+        assert_eq!(
+            config.encryption.as_ref().unwrap().key,
+            Path::new("/etc/zkms/key")
+        );
+        assert_eq!(
+            config.sealing.as_ref().unwrap().secret,
+            Path::new("/etc/zkms/sealing")
+        );
+        assert_eq!(config.clients.len(), 1);
+        assert_eq!(config.clients[0].name, "node");
+        let acl = config.clients[0].acl().expect("valid acl");
+        assert_eq!(acl.key_types, Some(vec![*b"babe"]));
+        assert_eq!(acl.operations, Some(vec![Operation::Sign, Operation::Vrf]));
+        //missing values are the defaults
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.transports, vec![Transport::Ductile]);
+        assert_eq!(config.max_connections, DEFAULT_MAX_CONNECTIONS);
+        assert_eq!(config.parse_session_keys().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn flags_override_file() {
+        let path = std::env::temp_dir().join("zkms-server-flags_override_file.toml");
+        std::fs::write(&path, TOML).unwrap();
+
+        let args = Args::from_iter(&[
+            "zkms-server".as_ref(),
+            "--config".as_ref(),
+            path.as_os_str(),
+            "--backend".as_ref(),
+            "optee".as_ref(),
+            "--client".as_ref(),
+            "other=/etc/zkms/other".as_ref(),
+        ]);
+        let config = Config::load(args).expect("valid flags");
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(config.backend, Backend::Optee);
+        assert_eq!(config.workers, 2);
+        assert_eq!(config.clients[0].name, "other");
+    }
+
+    #[test]
+    fn unauthenticated_ductile() {
+        //only reachable from the same host
+        assert!(Config::default().validate().is_ok());
+
+        let public = Config::from_toml("listen = \"0.0.0.0:39946\"").unwrap();
+        assert!(public.validate().is_err());
+
+        let args = Args::from_iter(&["zkms-server", "--listen", "0.0.0.0:39946"]);
+        assert!(Config::load(args).is_err());
+
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--listen",
+            "0.0.0.0:39946",
+            "--allow-unauthenticated",
+        ]);
+        assert!(Config::load(args).is_ok());
+
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--listen",
+            "0.0.0.0:39946",
+            "--client",
+            "a=x",
+        ]);
+        assert!(Config::load(args).is_ok());
+
+        //nothing is served on the address with the other transports
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--listen",
+            "0.0.0.0:39946",
+            "--transport",
+            "jsonrpc",
+        ]);
+        assert!(Config::load(args).is_ok());
+    }
+
+    #[test]
+    fn unauthenticated_rpc() {
+        let args = Args::from_iter(&["zkms-server", "--transport", "jsonrpc"]);
+        assert!(Config::load(args).is_ok());
+
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--transport",
+            "author",
+            "--author-listen",
+            "0.0.0.0:39948",
+        ]);
+        assert!(Config::load(args).is_err());
+
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--transport",
+            "jsonrpc",
+            "--jsonrpc-listen",
+            "0.0.0.0:39947",
+        ]);
+        assert!(Config::load(args).is_err());
+
+        let args = Args::from_iter(&[
+            "zkms-server",
+            "--transport",
+            "jsonrpc",
+            "--jsonrpc-listen",
+            "0.0.0.0:39947",
+            "--allow-unauthenticated",
+        ]);
+        assert!(Config::load(args).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert!(Config::from_toml("unknown = 1").is_err());
+        assert!(Config::from_toml("backend = \"sgx\"").is_err());
+        let bad_session_key = Config::from_toml("session_keys = [\"babe\"]").unwrap();
+        assert!(bad_session_key.validate().is_err());
+
+        let args = Args::from_iter(&["zkms-server", "--workers", "0"]);
+        assert!(Config::load(args).is_err());
+
+        let args = Args::from_iter(&["zkms-server", "--client", "a=x", "--client", "a=y"]);
+        assert!(Config::load(args).is_err());
+
+        let args = Args::from_iter(&["zkms-server", "--client", "a=x", "--transport", "jsonrpc"]);
+        assert!(Config::load(args).is_err());
+
+        let acl = Config::from_toml(
+            r#"
+            [[clients]]
+            name = "node"
+            key = "/etc/zkms/node"
+            key_types = ["babe_"]
+        "#,
+        )
+        .unwrap();
+        assert!(acl.validate().is_err());
+    }
+}
@@ -2,6 +2,8 @@
 //! the request into a `zkms-common::HandleRequest``
 //!
 //! It effectively allows dependency injection, both from the service and the request handler
+//!
+//! It also contains the configuration of a zkms server, see `config`
 
 #![deny(
     rust_2018_idioms,
@@ -13,32 +15,68 @@
 #[macro_use]
 extern crate log;
 
+use std::sync::Arc;
+
+pub mod config;
+pub use config::Config;
+
 use futures::stream::StreamExt;
 use host_common::REEService;
 use zkms_common::{HandleRequest, RequestError};
 
+/// Default number of requests processed at the same time
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// Same as `start_service_with_workers` with `DEFAULT_WORKERS`
 pub async fn start_service(
     service: impl REEService<ServiceError = RequestError> + 'static,
     handler: impl HandleRequest + 'static,
 ) {
-    futures::pin_mut!(service);
-    //retrieve next request from service
-    // whilst we could go concurrent it would be useless as the handler is still blocking
-    while let Some(item) = service.next().await {
-        match item {
-            Err(e) => error!("failed to retrieve next item from service: {:?}", e),
-            Ok(request) => {
-                //pass request to handler
-                let response = handler.process_request(request.method.clone());
-
-                debug!(
-                    "processed request={:?}; response={:?}",
-                    request.method, response
-                );
-
-                //reply to request
-                request.reply(response).await
+    start_service_with_workers(service, handler, DEFAULT_WORKERS).await
+}
+
+/// Drive the service, processing up to `workers` requests concurrently
+///
+/// The handler is blocking, so each request is processed on the blocking thread pool.
+/// When all the workers are busy no more requests are pulled from the service,
+/// so the backpressure reaches the service
+pub async fn start_service_with_workers(
+    service: impl REEService<ServiceError = RequestError> + 'static,
+    handler: impl HandleRequest + 'static,
+    workers: usize,
+) {
+    let handler = Arc::new(handler);
+
+    service
+        .for_each_concurrent(workers.max(1), |item| {
+            let handler = handler.clone();
+            async move {
+                match item {
+                    Err(e) => error!("failed to retrieve next item from service: {:?}", e),
+                    Ok(request) => {
+                        //pass request to handler
+                        let method = request.method.clone();
+                        let response =
+                            tokio::task::spawn_blocking(move || handler.process_request(method))
+                                .await
+                                .unwrap_or_else(|e| {
+                                    Err(RequestError::InternalError(format!(
+                                        "request handler failed: {}",
+                                        e
+                                    )))
+                                });
+
+                        debug!(
+                            "processed request={}; response={:?}",
+                            request.method.name(),
+                            response
+                        );
+
+                        //reply to request
+                        request.reply(response).await
+                    }
+                }
             }
-        }
-    }
+        })
+        .await
 }
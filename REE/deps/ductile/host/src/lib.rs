@@ -1,60 +1,117 @@
-use std::net::ToSocketAddrs;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use host_common::{channel, CryptoAlgo, RequestMethod, RequestResponse, ServiceRequest};
-use zkms_ductile::{RemoteKeystore, RemoteKeystoreResponse};
+use host_common::{
+    channel, Acl, CryptoAlgo, CryptoPublicPair, MessagePolicy, RateLimit, RequestError,
+    RequestMethod, RequestResponse, ServiceRequest,
+};
+use tokio::net::{TcpListener, TcpStream};
+use zkms_ductile::{
+    auth,
+    transport::{self, Receiver, Sender},
+    KeystoreError, RemoteKeystore, RemoteKeystoreResponse, Request, Response,
+};
 
 #[macro_use]
 extern crate tracing;
 
+/// A client allowed to connect
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// Key the client proves to know, see [`auth`]
+    pub key: auth::Key,
+    /// What the client is allowed to request
+    pub acl: Acl,
+}
+
+/// Time a peer has to set up its connection and authenticate, before it's dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Will start the ductile service as configured and return a list of incoming service requests
-pub async fn start_service<E: std::fmt::Debug + Send + 'static>(
-    addr: impl ToSocketAddrs + Send + 'static,
+///
+/// Peers talk over a [`transport`] connection: if `enc_key` is given it's encrypted with it,
+/// so peers must use the same key
+///
+/// Peers must authenticate as one of `clients`, by name, within [`HANDSHAKE_TIMEOUT`]
+/// before sending any request; if there are no clients any peer is accepted
+///
+/// Each authenticated peer is served on its own, up to `max_connections` at the same time;
+/// any other peer is disconnected
+pub async fn start_service<E: Into<RequestError> + std::fmt::Debug + Send + 'static>(
+    addr: SocketAddr,
+    enc_key: Option<[u8; 32]>,
+    clients: HashMap<String, Client>,
+    max_connections: usize,
 ) -> impl futures::Stream<Item = Result<ServiceRequest<E>, E>> {
-    use futures::SinkExt;
-
     let (tx, rx) = channel::mpsc::unbounded();
 
-    let _ = tokio::task::spawn_blocking(move || {
-        let listener = ductile::ChannelServer::bind(addr).expect("unable to bind server");
+    if clients.is_empty() {
+        warn!("no clients configured, accepting unauthenticated peers");
+    }
+    let clients = Arc::new(clients);
+
+    let mut listener = TcpListener::bind(addr)
+        .await
+        .expect("unable to bind server");
+
+    tokio::spawn(async move {
+        let peers = Peers::default();
 
         //accept connections
-        for (duct_tx, duct_rx, peer) in listener {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(error) => {
+                    warn!(?error, "unable to accept connection");
+                    continue;
+                }
+            };
+
             let tx = tx.clone();
-            info!(?peer, "NEW connection");
-
-            //for every message from the peer
-            while let Ok(req) = duct_rx.recv() {
-                let duct_tx = duct_tx.clone();
-                let mut tx = tx.clone();
-
-                //prepare request for service
-                debug!(request = ?req);
-                let req = match translate_request(req) {
-                    Err(resp) => {
-                        //if there's an unsupported request we can reply early
-                        // and move on to the next request
-                        let _ = duct_tx.send(resp);
-                        continue;
+            let clients = clients.clone();
+            let peers = peers.clone();
+            tokio::spawn(async move {
+                //a peer only counts once authenticated, so silent peers can't take every slot
+                let handshake = handshake(stream, enc_key.as_ref(), &clients);
+                let (duct_tx, duct_rx, client) =
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(connection)) => connection,
+                        Ok(Err(error)) => {
+                            warn!(?peer, ?error, "REJECTED peer");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!(?peer, "REJECTED peer, handshake timed out");
+                            return;
+                        }
+                    };
+
+                let live = match peers.join(peer, max_connections) {
+                    Some(live) => live,
+                    None => {
+                        warn!(
+                            ?peer,
+                            max_connections, "REJECTED peer, too many connections"
+                        );
+                        return;
                     }
-                    Ok(req) => req,
                 };
+                info!(?peer, ?client, live = peers.len(), "NEW connection");
 
-                let (resp_tx, resp_rx) = channel::oneshot::channel();
-                let service_request = ServiceRequest::new(req.clone(), Some(resp_tx));
+                //unauthenticated peers can do anything
+                let acl = client
+                    .as_ref()
+                    .map(|client| clients[client].acl.clone())
+                    .unwrap_or_default();
+                serve_peer(duct_tx, duct_rx, peer, client, acl, tx).await;
 
-                tokio::task::spawn(async move {
-                    //send request to service
-                    let _ = tx.send(service_request).await;
-
-                    //wait for reply from service
-                    let resp = resp_rx.await.expect("channel will not be canceled");
-                    let resp = translate_response(&req, resp);
-
-                    //send response back to peer
-                    debug!(response = ?resp);
-                    let _ = duct_tx.send(resp);
-                });
-            }
+                drop(live);
+                info!(?peer, "connection CLOSED");
+            });
         }
     });
 
@@ -63,30 +120,170 @@ pub async fn start_service<E: std::fmt::Debug + Send + 'static>(
     rx.then(|s| async { Ok(s) })
 }
 
+/// Addresses of the connected peers
+#[derive(Debug, Clone, Default)]
+struct Peers(Arc<Mutex<HashSet<SocketAddr>>>);
+
+impl Peers {
+    /// Add the peer, unless there are `max` peers already
+    ///
+    /// The peer is removed once the returned guard is dropped
+    fn join(&self, peer: SocketAddr, max: usize) -> Option<LivePeer> {
+        let mut peers = self.0.lock().expect("poisoned peers");
+        if peers.len() >= max {
+            return None;
+        }
+        peers.insert(peer);
+
+        Some(LivePeer {
+            peers: self.clone(),
+            peer,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().expect("poisoned peers").len()
+    }
+}
+
+/// A connected peer, removed from `Peers` on drop
+struct LivePeer {
+    peers: Peers,
+    peer: SocketAddr,
+}
+
+impl Drop for LivePeer {
+    fn drop(&mut self) {
+        if let Ok(mut peers) = self.peers.0.lock() {
+            peers.remove(&self.peer);
+        }
+    }
+}
+
+/// Set up the connection and authenticate the peer, returning the name of its client
+///
+/// No client is returned if there are no clients to authenticate as
+async fn handshake(
+    stream: TcpStream,
+    enc_key: Option<&[u8; 32]>,
+    clients: &HashMap<String, Client>,
+) -> Result<(Sender<Response>, Receiver<Request>, Option<String>), auth::Error> {
+    let (mut duct_tx, mut duct_rx) = transport::accept(stream, enc_key)
+        .await
+        .map_err(auth::Error::Channel)?;
+
+    if clients.is_empty() {
+        return Ok((duct_tx, duct_rx, None));
+    }
+
+    let client = auth::server_handshake(
+        |name| clients.get(name).map(|client| client.key),
+        &mut duct_tx,
+        &mut duct_rx,
+    )
+    .await?;
+
+    Ok((duct_tx, duct_rx, Some(client)))
+}
+
+/// Forward the requests of the peer to the service, until it disconnects
+async fn serve_peer<E: Into<RequestError> + std::fmt::Debug + Send + 'static>(
+    mut duct_tx: Sender<Response>,
+    mut duct_rx: Receiver<Request>,
+    peer: SocketAddr,
+    client: Option<String>,
+    acl: Acl,
+    tx: channel::mpsc::UnboundedSender<ServiceRequest<E>>,
+) {
+    use futures::{SinkExt, StreamExt};
+
+    //responses are sent as soon as they're ready, tagged with the id of their request
+    let (responses, mut ready) = channel::mpsc::unbounded::<Response>();
+    tokio::spawn(async move {
+        while let Some(response) = ready.next().await {
+            if let Err(error) = duct_tx.send(&response).await {
+                warn!(?peer, ?error, "unable to send response");
+                break;
+            }
+        }
+    });
+
+    //for every message from the peer
+    while let Ok(Request { id, request }) = duct_rx.recv().await {
+        let responses = responses.clone();
+        let mut tx = tx.clone();
+
+        //prepare request for service
+        let req = match translate_request(request) {
+            Err(response) => {
+                //if there's an unsupported request we can reply early
+                // and move on to the next request
+                let _ = responses.unbounded_send(Response { id, response });
+                continue;
+            }
+            Ok(req) => req,
+        };
+        debug!(?peer, id, method = req.name());
+
+        if let Err(reason) = acl.check(&req) {
+            warn!(?peer, ?client, id, %reason, "DENIED request");
+            //not `Unavailable`, the client shouldn't take it for an outage
+            let error = KeystoreError::Other(format!("denied: {}", reason));
+            let response = error_response(&req, error);
+            let _ = responses.unbounded_send(Response { id, response });
+            continue;
+        }
+
+        let (resp_tx, resp_rx) = channel::oneshot::channel();
+        let service_request = ServiceRequest::new(req.clone(), Some(resp_tx));
+
+        tokio::spawn(async move {
+            //send request to service
+            let _ = tx.send(service_request).await;
+
+            //wait for reply from service
+            let resp = resp_rx.await.expect("channel will not be canceled");
+            let response = translate_response(&req, resp);
+
+            debug!(id, ?response);
+            let _ = responses.unbounded_send(Response { id, response });
+        });
+    }
+}
+
 fn translate_request(request: RemoteKeystore) -> Result<RequestMethod, RemoteKeystoreResponse> {
     use host_common::HasKeysPair;
     use zkms_ductile::KeystoreError as Error;
 
     match request {
-        RemoteKeystore::Sr25519GenerateNew { id, seed: _ } => Ok(RequestMethod::GenerateNew {
+        RemoteKeystore::Hello { .. } | RemoteKeystore::Authenticate { .. } => {
+            //either authentication is disabled or the peer is already authenticated
+            Err(RemoteKeystoreResponse::Authenticate(Err(Error::Other(
+                "unexpected authentication".to_string(),
+            ))))
+        }
+        RemoteKeystore::Sr25519GenerateNew { id, seed } => Ok(RequestMethod::GenerateNew {
             algo: CryptoAlgo::Sr25519,
             key_type: id.0,
+            seed,
         }),
         RemoteKeystore::Sr25519PublicKeys(id) => Ok(RequestMethod::GetPublicKeys {
             algo: CryptoAlgo::Sr25519,
             key_type: id.0,
         }),
-        RemoteKeystore::Ed25519GenerateNew { id, seed: _ } => Ok(RequestMethod::GenerateNew {
+        RemoteKeystore::Ed25519GenerateNew { id, seed } => Ok(RequestMethod::GenerateNew {
             algo: CryptoAlgo::Ed25519,
             key_type: id.0,
+            seed,
         }),
         RemoteKeystore::Ed25519PublicKeys(id) => Ok(RequestMethod::GetPublicKeys {
             algo: CryptoAlgo::Ed25519,
             key_type: id.0,
         }),
-        RemoteKeystore::EcdsaGenerateNew { id, seed: _ } => Ok(RequestMethod::GenerateNew {
+        RemoteKeystore::EcdsaGenerateNew { id, seed } => Ok(RequestMethod::GenerateNew {
             algo: CryptoAlgo::Ecdsa,
             key_type: id.0,
+            seed,
         }),
         RemoteKeystore::EcdsaPublicKeys(id) => Ok(RequestMethod::GetPublicKeys {
             algo: CryptoAlgo::Ecdsa,
@@ -125,23 +322,137 @@ fn translate_request(request: RemoteKeystore) -> Result<RequestMethod, RemoteKey
             public_key: public,
             transcript_data,
         }),
-        RemoteKeystore::InsertUnknown { .. } => Err(RemoteKeystoreResponse::InsertUnknown(Err(()))),
-        RemoteKeystore::SupportedKeys { .. } => Err(RemoteKeystoreResponse::SupportedKeys(Err(
-            Error::Unavailable,
-        ))),
-        RemoteKeystore::Keys(_) => Err(RemoteKeystoreResponse::Keys(Err(Error::Unavailable))),
-        RemoteKeystore::SignWithAny { .. } => {
-            Err(RemoteKeystoreResponse::SignWithAny(Err(Error::Unavailable)))
-        }
-        RemoteKeystore::SignWithAll { .. } => Err(RemoteKeystoreResponse::SignWithAll(Err(()))),
+        RemoteKeystore::InsertUnknown { id, suri, public } => Ok(RequestMethod::InsertKey {
+            key_type: id.0,
+            suri,
+            public_key: public,
+        }),
+        RemoteKeystore::SupportedKeys { id, keys } => {
+            use std::convert::TryFrom;
+
+            //keys of an unknown crypto type are never supported
+            let keys = keys
+                .into_iter()
+                .filter_map(|pair| CryptoPublicPair::try_from(pair).ok())
+                .collect();
+
+            Ok(RequestMethod::SupportedKeys {
+                key_type: id.0,
+                keys,
+            })
+        }
+        RemoteKeystore::Keys(id) => Ok(RequestMethod::Keys { key_type: id.0 }),
+        RemoteKeystore::SignWithAny { id, keys, msg } => {
+            use std::convert::TryFrom;
+
+            //keys of an unknown crypto type can't be used to sign anyways
+            let keys = keys
+                .into_iter()
+                .filter_map(|pair| CryptoPublicPair::try_from(pair).ok())
+                .collect();
+
+            Ok(RequestMethod::SignWithAny {
+                key_type: id.0,
+                keys,
+                msg,
+            })
+        }
+        RemoteKeystore::SignWithAll { id, keys, msg } => {
+            use std::convert::TryFrom;
+
+            //a result is expected for each key, so we can't skip unknown keys
+            let keys = keys
+                .into_iter()
+                .map(CryptoPublicPair::try_from)
+                .collect::<Result<_, _>>()
+                .map_err(|_| RemoteKeystoreResponse::SignWithAll(Err(())))?;
+
+            Ok(RequestMethod::SignWithAll {
+                key_type: id.0,
+                keys,
+                msg,
+            })
+        }
+        RemoteKeystore::DeleteKey { id, key } => {
+            use std::convert::TryFrom;
+
+            let key = key
+                .map(|key| {
+                    let id = (key.0).0;
+                    CryptoPublicPair::try_from(key)
+                        .map_err(|_| Error::KeyNotSupported(u32::from_le_bytes(id).into()))
+                })
+                .transpose()
+                .map_err(|e| RemoteKeystoreResponse::DeleteKey(Err(e)))?;
+
+            Ok(RequestMethod::DeleteKey {
+                key_type: id.0,
+                key,
+            })
+        }
+        RemoteKeystore::RotateKey { id, key } => {
+            use std::convert::TryFrom;
+
+            let key = key
+                .map(|key| {
+                    let id = (key.0).0;
+                    CryptoPublicPair::try_from(key)
+                        .map_err(|_| Error::KeyNotSupported(u32::from_le_bytes(id).into()))
+                })
+                .transpose()
+                .map_err(|e| RemoteKeystoreResponse::RotateKey(Err(e)))?;
+
+            Ok(RequestMethod::RotateKey {
+                key_type: id.0,
+                key,
+            })
+        }
+        RemoteKeystore::ExportSealed { key } => {
+            use std::convert::TryFrom;
+
+            let key = key
+                .map(|(id, key)| {
+                    let crypto_id = (key.0).0;
+                    CryptoPublicPair::try_from(key)
+                        .map(|key| (id.0, key))
+                        .map_err(|_| Error::KeyNotSupported(u32::from_le_bytes(crypto_id).into()))
+                })
+                .transpose()
+                .map_err(|e| RemoteKeystoreResponse::ExportSealed(Err(e)))?;
+
+            Ok(RequestMethod::ExportSealed { key })
+        }
+        RemoteKeystore::ImportSealed { blob } => Ok(RequestMethod::ImportSealed { blob }),
+        RemoteKeystore::SetPolicy { id, crypto, policy } => {
+            use std::convert::TryFrom;
+
+            let algo = CryptoAlgo::try_from(crypto.0).map_err(|_| {
+                RemoteKeystoreResponse::SetPolicy(Err(Error::KeyNotSupported(
+                    u32::from_le_bytes(crypto.0).into(),
+                )))
+            })?;
+
+            let policy = policy.map(|policy| MessagePolicy {
+                lengths: policy.lengths,
+                prefixes: policy.prefixes,
+                rate_limit: policy
+                    .rate_limit
+                    .map(|(max, period_ms)| RateLimit { max, period_ms }),
+            });
+
+            Ok(RequestMethod::SetPolicy {
+                key_type: id.0,
+                algo,
+                policy,
+            })
+        }
     }
 }
 
-fn translate_response<E: std::fmt::Debug + Send + 'static>(
+fn translate_response<E: Into<RequestError> + std::fmt::Debug + Send + 'static>(
     original_request: &RequestMethod,
     response: Result<RequestResponse, E>,
 ) -> RemoteKeystoreResponse {
-    use zkms_ductile::KeystoreError as Error;
     use zkms_ductile::{crypto::Public, ecdsa, ed25519, sr25519};
 
     match response {
@@ -210,31 +521,181 @@ fn translate_response<E: std::fmt::Debug + Send + 'static>(
             RequestResponse::VrfSign { signature } => {
                 RemoteKeystoreResponse::Sr25519VrfSign(Ok(signature))
             }
-        },
-        Err(err) => match original_request {
-            RequestMethod::GenerateNew { algo, .. } => match algo {
-                CryptoAlgo::Sr25519 => RemoteKeystoreResponse::Sr25519GenerateNew(Err(
-                    Error::Other(format!("{:?}", err)),
-                )),
-                CryptoAlgo::Ed25519 => RemoteKeystoreResponse::Ed25519GenerateNew(Err(
-                    Error::Other(format!("{:?}", err)),
-                )),
-                CryptoAlgo::Ecdsa => RemoteKeystoreResponse::EcdsaGenerateNew(Err(Error::Other(
-                    format!("{:?}", err),
-                ))),
-            },
-            RequestMethod::GetPublicKeys { algo, .. } => match algo {
-                CryptoAlgo::Sr25519 => RemoteKeystoreResponse::Sr25519PublicKeys(vec![]),
-                CryptoAlgo::Ed25519 => RemoteKeystoreResponse::Ed25519PublicKeys(vec![]),
-                CryptoAlgo::Ecdsa => RemoteKeystoreResponse::EcdsaPublicKeys(vec![]),
-            },
-            RequestMethod::HasKeys { .. } => RemoteKeystoreResponse::HasKeys(false),
-            RequestMethod::SignMessage { .. } => {
-                RemoteKeystoreResponse::SignWith(Err(Error::Other(format!("{:?}", err))))
+            RequestResponse::InsertKey { .. } => RemoteKeystoreResponse::InsertUnknown(Ok(())),
+            RequestResponse::Keys { keys } => {
+                let keys = keys.into_iter().map(Into::into).collect();
+
+                RemoteKeystoreResponse::Keys(Ok(keys))
+            }
+            RequestResponse::SupportedKeys { keys } => {
+                let keys = keys.into_iter().map(Into::into).collect();
+
+                RemoteKeystoreResponse::SupportedKeys(Ok(keys))
             }
-            RequestMethod::VrfSign { .. } => {
-                RemoteKeystoreResponse::Sr25519VrfSign(Err(Error::Other(format!("{:?}", err))))
+            RequestResponse::SignWithAny { key, signature } => {
+                RemoteKeystoreResponse::SignWithAny(Ok((key.into(), signature)))
             }
+            RequestResponse::SignWithAll { signatures } => {
+                let signatures = signatures
+                    .into_iter()
+                    .map(|res| res.map_err(|err| keystore_error(original_request, err)))
+                    .collect();
+
+                RemoteKeystoreResponse::SignWithAll(Ok(signatures))
+            }
+            RequestResponse::DeleteKey { deleted } => {
+                RemoteKeystoreResponse::DeleteKey(Ok(deleted))
+            }
+            RequestResponse::RotateKey { keys } => {
+                let keys = keys.into_iter().map(Into::into).collect();
+
+                RemoteKeystoreResponse::RotateKey(Ok(keys))
+            }
+            RequestResponse::ExportSealed { blob } => {
+                RemoteKeystoreResponse::ExportSealed(Ok(blob))
+            }
+            RequestResponse::ImportSealed { imported } => {
+                RemoteKeystoreResponse::ImportSealed(Ok(imported))
+            }
+            RequestResponse::SetPolicy => RemoteKeystoreResponse::SetPolicy(Ok(())),
+        },
+        Err(err) => error_response(
+            original_request,
+            keystore_error(original_request, err.into()),
+        ),
+    }
+}
+
+/// Map the error to the matching keystore error, so the node can tell them apart
+fn keystore_error(original_request: &RequestMethod, error: RequestError) -> KeystoreError {
+    use zkms_ductile::{crypto::KeyTypeId, KeystoreError as Error};
+
+    match error {
+        RequestError::NoKeys(public) => Error::PairNotFound(format!("{:x?}", public)),
+        RequestError::PairNotFound => match original_request {
+            RequestMethod::SignMessage { public_key, .. } => {
+                Error::PairNotFound(format!("{:x?}", public_key))
+            }
+            _ => Error::PairNotFound(error.to_string()),
+        },
+        RequestError::KeyNotSupported => match key_type(original_request) {
+            Some(key_type) => Error::KeyNotSupported(KeyTypeId(key_type)),
+            None => Error::Other(error.to_string()),
+        },
+        RequestError::ValidationError(reason) | RequestError::BadState(reason) => {
+            Error::ValidationError(reason)
+        }
+        RequestError::Unavailable | RequestError::TargetDead => Error::Unavailable,
+        //"denied: ..." like the requests denied by the acl
+        RequestError::Denied(_) | RequestError::Equivocation | RequestError::InternalError(_) => {
+            Error::Other(error.to_string())
+        }
+    }
+}
+
+/// Key type of the request, if it's about a single one
+fn key_type(request: &RequestMethod) -> Option<[u8; 4]> {
+    match request {
+        RequestMethod::GenerateNew { key_type, .. }
+        | RequestMethod::GetPublicKeys { key_type, .. }
+        | RequestMethod::SignMessage { key_type, .. }
+        | RequestMethod::VrfSign { key_type, .. }
+        | RequestMethod::InsertKey { key_type, .. }
+        | RequestMethod::Keys { key_type }
+        | RequestMethod::SupportedKeys { key_type, .. }
+        | RequestMethod::SignWithAny { key_type, .. }
+        | RequestMethod::SignWithAll { key_type, .. }
+        | RequestMethod::DeleteKey { key_type, .. }
+        | RequestMethod::RotateKey { key_type, .. }
+        | RequestMethod::SetPolicy { key_type, .. } => Some(*key_type),
+        RequestMethod::HasKeys { .. }
+        | RequestMethod::ExportSealed { .. }
+        | RequestMethod::ImportSealed { .. } => None,
+    }
+}
+
+/// Reply to the request with the given error
+fn error_response(
+    original_request: &RequestMethod,
+    error: KeystoreError,
+) -> RemoteKeystoreResponse {
+    match original_request {
+        RequestMethod::GenerateNew { algo, .. } => match algo {
+            CryptoAlgo::Sr25519 => RemoteKeystoreResponse::Sr25519GenerateNew(Err(error)),
+            CryptoAlgo::Ed25519 => RemoteKeystoreResponse::Ed25519GenerateNew(Err(error)),
+            CryptoAlgo::Ecdsa => RemoteKeystoreResponse::EcdsaGenerateNew(Err(error)),
         },
+        RequestMethod::GetPublicKeys { algo, .. } => match algo {
+            CryptoAlgo::Sr25519 => RemoteKeystoreResponse::Sr25519PublicKeys(vec![]),
+            CryptoAlgo::Ed25519 => RemoteKeystoreResponse::Ed25519PublicKeys(vec![]),
+            CryptoAlgo::Ecdsa => RemoteKeystoreResponse::EcdsaPublicKeys(vec![]),
+        },
+        RequestMethod::HasKeys { .. } => RemoteKeystoreResponse::HasKeys(false),
+        RequestMethod::SignMessage { .. } => RemoteKeystoreResponse::SignWith(Err(error)),
+        RequestMethod::VrfSign { .. } => RemoteKeystoreResponse::Sr25519VrfSign(Err(error)),
+        RequestMethod::InsertKey { .. } => RemoteKeystoreResponse::InsertUnknown(Err(())),
+        RequestMethod::Keys { .. } => RemoteKeystoreResponse::Keys(Err(error)),
+        RequestMethod::SupportedKeys { .. } => RemoteKeystoreResponse::SupportedKeys(Err(error)),
+        RequestMethod::SignWithAny { .. } => RemoteKeystoreResponse::SignWithAny(Err(error)),
+        RequestMethod::SignWithAll { .. } => RemoteKeystoreResponse::SignWithAll(Err(())),
+        RequestMethod::DeleteKey { .. } => RemoteKeystoreResponse::DeleteKey(Err(error)),
+        RequestMethod::RotateKey { .. } => RemoteKeystoreResponse::RotateKey(Err(error)),
+        RequestMethod::ExportSealed { .. } => RemoteKeystoreResponse::ExportSealed(Err(error)),
+        RequestMethod::ImportSealed { .. } => RemoteKeystoreResponse::ImportSealed(Err(error)),
+        RequestMethod::SetPolicy { .. } => RemoteKeystoreResponse::SetPolicy(Err(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_connections() {
+        let peers = Peers::default();
+        let first = "127.0.0.1:1000".parse().unwrap();
+        let second = "127.0.0.1:1001".parse().unwrap();
+
+        let live = peers.join(first, 1).expect("room for the first peer");
+        assert!(peers.join(second, 1).is_none());
+
+        drop(live);
+        assert_eq!(peers.len(), 0);
+        assert!(peers.join(second, 1).is_some());
+    }
+
+    #[test]
+    fn keystore_errors() {
+        let sign = RequestMethod::SignMessage {
+            algo: CryptoAlgo::Sr25519,
+            key_type: *b"babe",
+            public_key: vec![1, 2, 3],
+            msg: vec![],
+        };
+
+        assert!(matches!(
+            keystore_error(&sign, RequestError::PairNotFound),
+            KeystoreError::PairNotFound(_)
+        ));
+        assert!(matches!(
+            keystore_error(&sign, RequestError::KeyNotSupported),
+            KeystoreError::KeyNotSupported(id) if id.0 == *b"babe"
+        ));
+        assert!(matches!(
+            keystore_error(&sign, RequestError::TargetDead),
+            KeystoreError::Unavailable
+        ));
+        assert!(matches!(
+            keystore_error(&sign, RequestError::InternalError("oops".to_string())),
+            KeystoreError::Other(_)
+        ));
+        assert!(matches!(
+            keystore_error(&sign, RequestError::Denied("rate limit".to_string())),
+            KeystoreError::Other(reason) if reason == "denied: rate limit"
+        ));
+        assert!(matches!(
+            keystore_error(&sign, RequestError::BadState("no claim".to_string())),
+            KeystoreError::ValidationError(_)
+        ));
     }
 }
@@ -0,0 +1,249 @@
+//! Mutual authentication of the peers with a pre-shared key
+//!
+//! Each client has a name and a 32 bytes key, also known by the server.
+//! Before any other message the peers do a challenge-response handshake:
+//!
+//! 1. the client sends [`RemoteKeystore::Hello`] with its name and a random nonce
+//! 2. the server replies with [`RemoteKeystoreResponse::Hello`], with its own nonce
+//!    and the proof of knowing the key of the client
+//! 3. the client checks the proof, and sends [`RemoteKeystore::Authenticate`]
+//!    with the proof of knowing the key itself
+//! 4. the server checks the proof and replies with [`RemoteKeystoreResponse::Authenticate`]
+//!
+//! The handshake messages are sent with request ID 0, over a [`transport`] connection.
+//!
+//! A proof is the HMAC-SHA256 of both nonces, keyed with the key of the client,
+//! so it can't be replayed on other connections nor reflected to the other peer.
+//! The handshake doesn't encrypt the channel, that's up to the transport
+//!
+//! [`transport`]: crate::transport
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::{
+    transport::{Receiver, Sender},
+    KeystoreError, RemoteKeystore, RemoteKeystoreResponse, Request, Response,
+};
+
+pub type Key = [u8; 32];
+pub type Nonce = [u8; 32];
+pub type Proof = [u8; 32];
+
+const CLIENT: &[u8] = b"zkms client";
+const SERVER: &[u8] = b"zkms server";
+
+/// Failure of the handshake
+#[derive(Debug)]
+pub enum Error {
+    /// The channel failed while sending or receiving
+    Channel(std::io::Error),
+    /// The peer sent something other than the expected handshake message
+    Unexpected,
+    /// The peer proved not to know the key, or the server doesn't know the client
+    Rejected,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channel(e) => write!(f, "channel error: {}", e),
+            Self::Unexpected => write!(f, "unexpected message during handshake"),
+            Self::Rejected => write!(f, "authentication rejected"),
+        }
+    }
+}
+
+fn mac(key: &Key, role: &[u8], first: &Nonce, second: &Nonce) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("any key length is valid");
+    mac.update(role);
+    mac.update(first);
+    mac.update(second);
+
+    mac
+}
+
+fn proof(key: &Key, role: &[u8], first: &Nonce, second: &Nonce) -> Proof {
+    let mut proof = [0; 32];
+    proof.copy_from_slice(&mac(key, role, first, second).finalize().into_bytes());
+
+    proof
+}
+
+fn verify(key: &Key, role: &[u8], first: &Nonce, second: &Nonce, proof: &Proof) -> bool {
+    //constant time comparison
+    mac(key, role, first, second).verify(proof).is_ok()
+}
+
+/// Authenticate with the server as `client`, over a new connection
+pub async fn client_handshake(
+    client: &str,
+    key: &Key,
+    tx: &mut Sender<Request>,
+    rx: &mut Receiver<Response>,
+) -> Result<(), Error> {
+    let client_nonce: Nonce = rand::random();
+    send_request(
+        tx,
+        RemoteKeystore::Hello {
+            client: client.to_string(),
+            nonce: client_nonce,
+        },
+    )
+    .await?;
+
+    let server_nonce = match recv_response(rx).await? {
+        RemoteKeystoreResponse::Hello { nonce, proof } => {
+            if !verify(key, SERVER, &client_nonce, &nonce, &proof) {
+                return Err(Error::Rejected);
+            }
+            nonce
+        }
+        RemoteKeystoreResponse::Authenticate(Err(_)) => return Err(Error::Rejected),
+        _ => return Err(Error::Unexpected),
+    };
+
+    send_request(
+        tx,
+        RemoteKeystore::Authenticate {
+            proof: proof(key, CLIENT, &server_nonce, &client_nonce),
+        },
+    )
+    .await?;
+
+    match recv_response(rx).await? {
+        RemoteKeystoreResponse::Authenticate(Ok(())) => Ok(()),
+        RemoteKeystoreResponse::Authenticate(Err(_)) => Err(Error::Rejected),
+        _ => Err(Error::Unexpected),
+    }
+}
+
+async fn send_request(tx: &mut Sender<Request>, request: RemoteKeystore) -> Result<(), Error> {
+    tx.send(&Request { id: 0, request })
+        .await
+        .map_err(Error::Channel)
+}
+
+async fn recv_response(rx: &mut Receiver<Response>) -> Result<RemoteKeystoreResponse, Error> {
+    rx.recv()
+        .await
+        .map(|resp| resp.response)
+        .map_err(Error::Channel)
+}
+
+/// Authenticate a client over a new connection, returning its name
+///
+/// `keys` retrieves the key of a client by its name
+pub async fn server_handshake(
+    keys: impl FnOnce(&str) -> Option<Key>,
+    tx: &mut Sender<Response>,
+    rx: &mut Receiver<Request>,
+) -> Result<String, Error> {
+    let result = server_exchange(keys, tx, rx).await;
+
+    let response = match result {
+        Ok(_) => Ok(()),
+        //can't tell the client anyways
+        Err(Error::Channel(_)) => return result,
+        Err(_) => Err(KeystoreError::Other("authentication failed".to_string())),
+    };
+    let sent = tx
+        .send(&Response {
+            id: 0,
+            response: RemoteKeystoreResponse::Authenticate(response),
+        })
+        .await;
+
+    //the client is only authenticated once it's told so
+    result.and_then(|client| sent.map(|_| client).map_err(Error::Channel))
+}
+
+async fn server_exchange(
+    keys: impl FnOnce(&str) -> Option<Key>,
+    tx: &mut Sender<Response>,
+    rx: &mut Receiver<Request>,
+) -> Result<String, Error> {
+    let (client, client_nonce) = match recv_request(rx).await? {
+        RemoteKeystore::Hello { client, nonce } => (client, nonce),
+        _ => return Err(Error::Unexpected),
+    };
+    let key = keys(&client).ok_or(Error::Rejected)?;
+
+    let server_nonce: Nonce = rand::random();
+    tx.send(&Response {
+        id: 0,
+        response: RemoteKeystoreResponse::Hello {
+            nonce: server_nonce,
+            proof: proof(&key, SERVER, &client_nonce, &server_nonce),
+        },
+    })
+    .await
+    .map_err(Error::Channel)?;
+
+    match recv_request(rx).await? {
+        RemoteKeystore::Authenticate { proof } => {
+            if verify(&key, CLIENT, &server_nonce, &client_nonce, &proof) {
+                Ok(client)
+            } else {
+                Err(Error::Rejected)
+            }
+        }
+        _ => Err(Error::Unexpected),
+    }
+}
+
+async fn recv_request(rx: &mut Receiver<Request>) -> Result<RemoteKeystore, Error> {
+    rx.recv()
+        .await
+        .map(|req| req.request)
+        .map_err(Error::Channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport;
+    use tokio::net::TcpListener;
+
+    const KEY: Key = [42; 32];
+
+    async fn handshake(client_key: Key) -> (Result<(), Error>, Result<String, Error>) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut tx, mut rx) = transport::accept(stream, None).await.unwrap();
+            server_handshake(
+                |client| if client == "node" { Some(KEY) } else { None },
+                &mut tx,
+                &mut rx,
+            )
+            .await
+        });
+
+        let (mut tx, mut rx) = transport::connect(addr, None).await.unwrap();
+        let client = client_handshake("node", &client_key, &mut tx, &mut rx).await;
+        //a rejected client disconnects
+        drop((tx, rx));
+
+        (client, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn same_key() {
+        let (client, server) = handshake(KEY).await;
+
+        assert!(client.is_ok());
+        assert_eq!(server.unwrap(), "node");
+    }
+
+    #[tokio::test]
+    async fn wrong_key() {
+        let (client, server) = handshake([0; 32]).await;
+
+        assert!(matches!(client, Err(Error::Rejected)));
+        //the server never gets the client proof
+        assert!(server.is_err());
+    }
+}
@@ -4,15 +4,41 @@ pub use sp_keystore::{
     Error as KeystoreError,
 };
 
-use sp_core::crypto::{CryptoTypePublicPair, KeyTypeId};
+use sp_core::crypto::{CryptoTypeId, CryptoTypePublicPair, KeyTypeId};
 
 use serde::{Deserialize, Serialize};
 
-#[macro_use]
-extern crate tracing;
+pub mod auth;
+pub mod transport;
 
+/// A request with its ID, which is given back with the response
+///
+/// Responses can arrive in any order, so the ID is how they're matched with the requests
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub request: RemoteKeystore,
+}
+
+/// The response to the request with the same ID
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub id: u64,
+    pub response: RemoteKeystoreResponse,
+}
+
+///Printed without the secrets it might carry, see its `Debug` implementation
+#[derive(Clone, Serialize, Deserialize)]
 pub enum RemoteKeystore {
+    ///First message of the authentication handshake, see [`auth`]
+    Hello {
+        client: String,
+        nonce: auth::Nonce,
+    },
+    ///Last message of the authentication handshake, see [`auth`]
+    Authenticate {
+        proof: auth::Proof,
+    },
     Sr25519PublicKeys(KeyTypeId),
     Sr25519GenerateNew {
         id: KeyTypeId,
@@ -59,10 +85,143 @@ pub enum RemoteKeystore {
         public: sr25519::Public,
         transcript_data: VRFTranscriptData,
     },
+    DeleteKey {
+        id: KeyTypeId,
+        key: Option<CryptoTypePublicPair>,
+    },
+    RotateKey {
+        id: KeyTypeId,
+        key: Option<CryptoTypePublicPair>,
+    },
+    ExportSealed {
+        key: Option<(KeyTypeId, CryptoTypePublicPair)>,
+    },
+    ImportSealed {
+        blob: Vec<u8>,
+    },
+    SetPolicy {
+        id: KeyTypeId,
+        crypto: CryptoTypeId,
+        policy: Option<MessagePolicy>,
+    },
+}
+
+/// Stands in for a secret when printing
+struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+//written by hand so the secrets don't end up in the logs
+impl std::fmt::Debug for RemoteKeystore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hello { client, nonce } => f
+                .debug_struct("Hello")
+                .field("client", client)
+                .field("nonce", nonce)
+                .finish(),
+            Self::Authenticate { proof } => f
+                .debug_struct("Authenticate")
+                .field("proof", proof)
+                .finish(),
+            Self::Sr25519PublicKeys(id) => f.debug_tuple("Sr25519PublicKeys").field(id).finish(),
+            Self::Sr25519GenerateNew { id, seed } => f
+                .debug_struct("Sr25519GenerateNew")
+                .field("id", id)
+                .field("seed", &seed.as_ref().map(|_| Redacted))
+                .finish(),
+            Self::Ed25519PublicKeys(id) => f.debug_tuple("Ed25519PublicKeys").field(id).finish(),
+            Self::Ed25519GenerateNew { id, seed } => f
+                .debug_struct("Ed25519GenerateNew")
+                .field("id", id)
+                .field("seed", &seed.as_ref().map(|_| Redacted))
+                .finish(),
+            Self::EcdsaPublicKeys(id) => f.debug_tuple("EcdsaPublicKeys").field(id).finish(),
+            Self::EcdsaGenerateNew { id, seed } => f
+                .debug_struct("EcdsaGenerateNew")
+                .field("id", id)
+                .field("seed", &seed.as_ref().map(|_| Redacted))
+                .finish(),
+            Self::InsertUnknown {
+                id,
+                suri: _,
+                public,
+            } => f
+                .debug_struct("InsertUnknown")
+                .field("id", id)
+                .field("suri", &Redacted)
+                .field("public", public)
+                .finish(),
+            Self::SupportedKeys { id, keys } => f
+                .debug_struct("SupportedKeys")
+                .field("id", id)
+                .field("keys", keys)
+                .finish(),
+            Self::Keys(id) => f.debug_tuple("Keys").field(id).finish(),
+            Self::HasKeys(pairs) => f.debug_tuple("HasKeys").field(pairs).finish(),
+            Self::SignWith { id, key, msg } => f
+                .debug_struct("SignWith")
+                .field("id", id)
+                .field("key", key)
+                .field("msg", msg)
+                .finish(),
+            Self::SignWithAny { id, keys, msg } => f
+                .debug_struct("SignWithAny")
+                .field("id", id)
+                .field("keys", keys)
+                .field("msg", msg)
+                .finish(),
+            Self::SignWithAll { id, keys, msg } => f
+                .debug_struct("SignWithAll")
+                .field("id", id)
+                .field("keys", keys)
+                .field("msg", msg)
+                .finish(),
+            Self::Sr25519VrfSign {
+                key_type,
+                public,
+                transcript_data,
+            } => f
+                .debug_struct("Sr25519VrfSign")
+                .field("key_type", key_type)
+                .field("public", public)
+                .field("transcript_data", transcript_data)
+                .finish(),
+            Self::DeleteKey { id, key } => f
+                .debug_struct("DeleteKey")
+                .field("id", id)
+                .field("key", key)
+                .finish(),
+            Self::RotateKey { id, key } => f
+                .debug_struct("RotateKey")
+                .field("id", id)
+                .field("key", key)
+                .finish(),
+            Self::ExportSealed { key } => f.debug_struct("ExportSealed").field("key", key).finish(),
+            Self::ImportSealed { blob } => {
+                f.debug_struct("ImportSealed").field("blob", blob).finish()
+            }
+            Self::SetPolicy { id, crypto, policy } => f
+                .debug_struct("SetPolicy")
+                .field("id", id)
+                .field("crypto", crypto)
+                .field("policy", policy)
+                .finish(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum RemoteKeystoreResponse {
+    Hello {
+        nonce: auth::Nonce,
+        proof: auth::Proof,
+    },
+    Authenticate(Result<(), KeystoreError>),
     Sr25519PublicKeys(Vec<sr25519::Public>),
     Sr25519GenerateNew(Result<sr25519::Public, KeystoreError>),
     Ed25519PublicKeys(Vec<ed25519::Public>),
@@ -77,4 +236,47 @@ pub enum RemoteKeystoreResponse {
     SignWithAny(Result<(CryptoTypePublicPair, Vec<u8>), KeystoreError>),
     SignWithAll(Result<Vec<Result<Vec<u8>, KeystoreError>>, ()>),
     Sr25519VrfSign(Result<VRFSignature, KeystoreError>),
+    DeleteKey(Result<usize, KeystoreError>),
+    RotateKey(Result<Vec<CryptoTypePublicPair>, KeystoreError>),
+    ExportSealed(Result<Vec<u8>, KeystoreError>),
+    ImportSealed(Result<usize, KeystoreError>),
+    SetPolicy(Result<(), KeystoreError>),
+}
+
+///Restrictions on the messages signed with the keys of a key type and crypto type
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MessagePolicy {
+    ///Allowed (inclusive) ranges of the message length, any length if empty
+    pub lengths: Vec<(u64, u64)>,
+    ///The message must start with one of these (SCALE encoded) prefixes, any if empty
+    pub prefixes: Vec<Vec<u8>>,
+    ///Maximum number of signatures (first) in a period of milliseconds (second)
+    pub rate_limit: Option<(u32, u64)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_not_printed() {
+        let request = RemoteKeystore::InsertUnknown {
+            id: KeyTypeId(*b"dumm"),
+            suri: "//Alice".to_string(),
+            public: vec![1, 2, 3],
+        };
+
+        let printed = format!("{:?}", request);
+        assert!(!printed.contains("Alice"));
+        assert!(printed.contains("InsertUnknown"));
+
+        let request = RemoteKeystore::Sr25519GenerateNew {
+            id: KeyTypeId(*b"dumm"),
+            seed: Some("//Bob".to_string()),
+        };
+
+        let printed = format!("{:?}", request);
+        assert!(!printed.contains("Bob"));
+        assert!(printed.contains("Some"));
+    }
 }
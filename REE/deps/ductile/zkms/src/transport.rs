@@ -0,0 +1,271 @@
+//! Framed messages over TCP, spoken by both the server and `TEEKeystore`
+//!
+//! Each message is serialized with bincode and sent after its length, as a little endian `u32`.
+//!
+//! With a pre-shared key each peer first sends a random salt, then every frame is sealed
+//! with ChaCha20Poly1305 under a key derived from the pre-shared one and both salts.
+//! So each connection has its own key, and the frame counter can be used as nonce:
+//! frames can't be replayed, reordered nor reflected back to their sender
+
+use std::{io, marker::PhantomData};
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Nonce,
+};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream, ToSocketAddrs,
+    },
+};
+
+/// Longest frame accepted, so a peer can't make us allocate without bound
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const SALT_LEN: usize = 32;
+const SESSION: &[u8] = b"zkms session";
+
+//the direction of a frame, in its nonce
+const FROM_CLIENT: [u8; 4] = *b"clnt";
+const FROM_SERVER: [u8; 4] = *b"srvr";
+
+fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Seals or opens the frames going in one direction
+struct Cipher {
+    aead: ChaCha20Poly1305,
+    direction: [u8; 4],
+    /// Frames sealed or opened so far
+    counter: u64,
+}
+
+impl Cipher {
+    fn new(key: &[u8; 32], direction: [u8; 4]) -> Self {
+        Self {
+            aead: ChaCha20Poly1305::new(&(*key).into()),
+            direction,
+            counter: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> io::Result<Nonce> {
+        let mut nonce = [0; 12];
+        nonce[..4].copy_from_slice(&self.direction);
+        nonce[4..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| invalid("too many frames"))?;
+
+        Ok(nonce.into())
+    }
+
+    fn seal(&mut self, frame: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        self.aead
+            .encrypt(&nonce, frame)
+            .map_err(|_| invalid("unable to seal frame"))
+    }
+
+    fn open(&mut self, frame: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        self.aead
+            .decrypt(&nonce, frame)
+            .map_err(|_| invalid("forged or corrupted frame"))
+    }
+}
+
+/// Key of the connection, derived from the pre-shared one and both salts
+fn session_key(key: &[u8; 32], client_salt: &[u8], server_salt: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("any key length is valid");
+    mac.update(SESSION);
+    mac.update(client_salt);
+    mac.update(server_salt);
+
+    let mut session = [0; 32];
+    session.copy_from_slice(&mac.finalize().into_bytes());
+    session
+}
+
+/// Sending half of a connection
+///
+/// Dropping it shuts down the connection for writing
+pub struct Sender<T> {
+    stream: OwnedWriteHalf,
+    cipher: Option<Cipher>,
+    _message: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize> Sender<T> {
+    pub async fn send(&mut self, message: &T) -> io::Result<()> {
+        let frame = bincode::serialize(message).map_err(invalid)?;
+        let frame = match self.cipher.as_mut() {
+            Some(cipher) => cipher.seal(&frame)?,
+            None => frame,
+        };
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(invalid("frame too long"));
+        }
+
+        let mut buf = Vec::with_capacity(4 + frame.len());
+        buf.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&frame);
+        self.stream.write_all(&buf).await
+    }
+}
+
+/// Receiving half of a connection
+pub struct Receiver<T> {
+    stream: OwnedReadHalf,
+    cipher: Option<Cipher>,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Receiver<T> {
+    /// Wait for the next message
+    ///
+    /// Not cancel safe: once dropped midway, the rest of the frame is taken for a new one
+    pub async fn recv(&mut self) -> io::Result<T> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len).await?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(invalid("frame too long"));
+        }
+
+        let mut frame = vec![0; len];
+        self.stream.read_exact(&mut frame).await?;
+        if let Some(cipher) = self.cipher.as_mut() {
+            frame = cipher.open(&frame)?;
+        }
+
+        bincode::deserialize(&frame).map_err(invalid)
+    }
+}
+
+/// Both halves of a connection, sending `S` and receiving `R`
+pub type Channel<S, R> = (Sender<S>, Receiver<R>);
+
+/// Connect to the server, encrypting the connection with `key` if given
+pub async fn connect<S, R>(
+    addr: impl ToSocketAddrs,
+    key: Option<&[u8; 32]>,
+) -> io::Result<Channel<S, R>> {
+    let stream = TcpStream::connect(addr).await?;
+    channel(stream, key, true).await
+}
+
+/// Set up a connection accepted by the server, with the same `key` as the client
+pub async fn accept<S, R>(stream: TcpStream, key: Option<&[u8; 32]>) -> io::Result<Channel<S, R>> {
+    channel(stream, key, false).await
+}
+
+async fn channel<S, R>(
+    stream: TcpStream,
+    key: Option<&[u8; 32]>,
+    client: bool,
+) -> io::Result<Channel<S, R>> {
+    //requests and responses are small and waited for
+    stream.set_nodelay(true)?;
+    let (mut read, mut write) = stream.into_split();
+
+    let (send_cipher, recv_cipher) = match key {
+        Some(key) => {
+            let salt: [u8; SALT_LEN] = rand::random();
+            write.write_all(&salt).await?;
+            let mut peer_salt = [0; SALT_LEN];
+            read.read_exact(&mut peer_salt).await?;
+
+            let (session, sent, received) = if client {
+                (
+                    session_key(key, &salt, &peer_salt),
+                    FROM_CLIENT,
+                    FROM_SERVER,
+                )
+            } else {
+                (
+                    session_key(key, &peer_salt, &salt),
+                    FROM_SERVER,
+                    FROM_CLIENT,
+                )
+            };
+            (
+                Some(Cipher::new(&session, sent)),
+                Some(Cipher::new(&session, received)),
+            )
+        }
+        None => (None, None),
+    };
+
+    Ok((
+        Sender {
+            stream: write,
+            cipher: send_cipher,
+            _message: PhantomData,
+        },
+        Receiver {
+            stream: read,
+            cipher: recv_cipher,
+            _message: PhantomData,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Connected client and server channels
+    async fn connected(
+        client_key: Option<[u8; 32]>,
+        server_key: Option<[u8; 32]>,
+    ) -> (Channel<String, String>, Channel<String, String>) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            accept(stream, server_key.as_ref()).await.unwrap()
+        });
+        let client = connect(addr, client_key.as_ref()).await.unwrap();
+
+        (client, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn roundtrip() {
+        for key in [None, Some([42; 32])].iter() {
+            let ((mut client_tx, mut client_rx), (mut server_tx, mut server_rx)) =
+                connected(*key, *key).await;
+
+            for i in 0..3 {
+                client_tx.send(&format!("request {}", i)).await.unwrap();
+                assert_eq!(server_rx.recv().await.unwrap(), format!("request {}", i));
+                server_tx.send(&format!("response {}", i)).await.unwrap();
+                assert_eq!(client_rx.recv().await.unwrap(), format!("response {}", i));
+            }
+
+            //the server is gone
+            drop((server_tx, server_rx));
+            assert!(client_rx.recv().await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn wrong_key() {
+        let ((mut client_tx, _), (_, mut server_rx)) =
+            connected(Some([0; 32]), Some([42; 32])).await;
+
+        client_tx.send(&"request".to_string()).await.unwrap();
+        let error = server_rx.recv().await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
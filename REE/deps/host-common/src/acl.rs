@@ -0,0 +1,219 @@
+//! Restrictions on the requests of a client
+//!
+//! Services check the requests of each authenticated client against its `Acl`
+//! before forwarding them to the host application
+
+use std::str::FromStr;
+
+use zkms_common::RequestMethod;
+
+/// Kind of request, besides the read only ones which are allowed to anyone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Generate or insert keys
+    Generate,
+    /// Sign messages
+    Sign,
+    /// Sign VRF transcripts
+    Vrf,
+    /// Delete, rotate, export and import keys, and set the signing policies
+    Manage,
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generate" => Ok(Self::Generate),
+            "sign" => Ok(Self::Sign),
+            "vrf" => Ok(Self::Vrf),
+            "manage" => Ok(Self::Manage),
+            other => Err(format!("unknown operation {:?}", other)),
+        }
+    }
+}
+
+/// What a client is allowed to do
+///
+/// Everything is allowed by default
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    /// Key types the client can use, any if `None`
+    pub key_types: Option<Vec<[u8; 4]>>,
+    /// Operations the client can do, any if `None`
+    pub operations: Option<Vec<Operation>>,
+}
+
+impl Acl {
+    /// Check that the request is allowed, returning the reason if not
+    pub fn check(&self, request: &RequestMethod) -> Result<(), String> {
+        if let (Some(allowed), Some(operation)) = (&self.operations, operation(request)) {
+            if !allowed.contains(&operation) {
+                return Err(format!("operation {:?} not allowed", operation));
+            }
+        }
+
+        if let Some(allowed) = &self.key_types {
+            match key_types(request) {
+                Some(key_types) => {
+                    if let Some(key_type) = key_types.iter().find(|id| !allowed.contains(id)) {
+                        return Err(format!(
+                            "key type {:?} not allowed",
+                            String::from_utf8_lossy(key_type)
+                        ));
+                    }
+                }
+                None => return Err("request spans all key types".to_string()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn operation(request: &RequestMethod) -> Option<Operation> {
+    match request {
+        RequestMethod::GenerateNew { .. } | RequestMethod::InsertKey { .. } => {
+            Some(Operation::Generate)
+        }
+        RequestMethod::SignMessage { .. }
+        | RequestMethod::SignWithAny { .. }
+        | RequestMethod::SignWithAll { .. } => Some(Operation::Sign),
+        RequestMethod::VrfSign { .. } => Some(Operation::Vrf),
+        RequestMethod::DeleteKey { .. }
+        | RequestMethod::RotateKey { .. }
+        | RequestMethod::ExportSealed { .. }
+        | RequestMethod::ImportSealed { .. }
+        | RequestMethod::SetPolicy { .. } => Some(Operation::Manage),
+        RequestMethod::GetPublicKeys { .. }
+        | RequestMethod::HasKeys { .. }
+        | RequestMethod::Keys { .. }
+        | RequestMethod::SupportedKeys { .. } => None,
+    }
+}
+
+/// Key types touched by the request, `None` if it could touch any
+fn key_types(request: &RequestMethod) -> Option<Vec<[u8; 4]>> {
+    match request {
+        RequestMethod::GenerateNew { key_type, .. }
+        | RequestMethod::GetPublicKeys { key_type, .. }
+        | RequestMethod::SignMessage { key_type, .. }
+        | RequestMethod::VrfSign { key_type, .. }
+        | RequestMethod::InsertKey { key_type, .. }
+        | RequestMethod::Keys { key_type }
+        | RequestMethod::SupportedKeys { key_type, .. }
+        | RequestMethod::SignWithAny { key_type, .. }
+        | RequestMethod::SignWithAll { key_type, .. }
+        | RequestMethod::DeleteKey { key_type, .. }
+        | RequestMethod::RotateKey { key_type, .. }
+        | RequestMethod::SetPolicy { key_type, .. } => Some(vec![*key_type]),
+        RequestMethod::HasKeys { pairs } => Some(pairs.iter().map(|pair| pair.key_type).collect()),
+        RequestMethod::ExportSealed { key } => key.as_ref().map(|(key_type, _)| vec![*key_type]),
+        //the key types are only known once the blob is unsealed
+        RequestMethod::ImportSealed { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zkms_common::{CryptoAlgo, CryptoPublicPair};
+
+    fn generate(key_type: &[u8; 4]) -> RequestMethod {
+        RequestMethod::GenerateNew {
+            algo: CryptoAlgo::Sr25519,
+            key_type: *key_type,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn restrict_key_types() {
+        let acl = Acl {
+            key_types: Some(vec![*b"babe"]),
+            operations: None,
+        };
+
+        assert!(acl.check(&generate(b"babe")).is_ok());
+        assert!(acl.check(&generate(b"gran")).is_err());
+        assert!(acl
+            .check(&RequestMethod::ImportSealed { blob: vec![] })
+            .is_err());
+    }
+
+    #[test]
+    fn restrict_operations() {
+        let acl = Acl {
+            key_types: None,
+            operations: Some(vec![Operation::Sign]),
+        };
+
+        assert!(acl.check(&generate(b"babe")).is_err());
+        assert!(acl
+            .check(&RequestMethod::Keys { key_type: *b"babe" })
+            .is_ok());
+        assert!(acl
+            .check(&RequestMethod::SignMessage {
+                algo: CryptoAlgo::Sr25519,
+                key_type: *b"babe",
+                public_key: vec![],
+                msg: vec![],
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn restrict_manage() {
+        let acl = Acl {
+            key_types: None,
+            operations: Some(vec![Operation::Generate, Operation::Sign, Operation::Vrf]),
+        };
+        let manage = Acl {
+            key_types: None,
+            operations: Some(vec![Operation::Manage]),
+        };
+
+        let requests = [
+            RequestMethod::DeleteKey {
+                key_type: *b"babe",
+                key: None,
+            },
+            RequestMethod::ExportSealed { key: None },
+            RequestMethod::ImportSealed { blob: vec![] },
+            RequestMethod::SetPolicy {
+                key_type: *b"babe",
+                algo: CryptoAlgo::Sr25519,
+                policy: None,
+            },
+        ];
+        for request in &requests {
+            assert!(acl.check(request).is_err());
+            assert!(manage.check(request).is_ok());
+        }
+        assert!(manage.check(&generate(b"babe")).is_err());
+    }
+
+    #[test]
+    fn export_all_key_types() {
+        let acl = Acl {
+            key_types: Some(vec![*b"babe"]),
+            operations: None,
+        };
+
+        let export = |key_type: &[u8; 4]| RequestMethod::ExportSealed {
+            key: Some((
+                *key_type,
+                CryptoPublicPair {
+                    algo: CryptoAlgo::Sr25519,
+                    public_key: vec![],
+                },
+            )),
+        };
+
+        let all = acl.check(&RequestMethod::ExportSealed { key: None });
+        assert_eq!(all.unwrap_err(), "request spans all key types");
+        assert!(acl.check(&export(b"babe")).is_ok());
+        assert!(acl.check(&export(b"gran")).is_err());
+    }
+}
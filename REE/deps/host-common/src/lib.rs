@@ -10,10 +10,13 @@ extern crate log;
 
 pub use futures::channel;
 
+pub mod acl;
+pub use acl::Acl;
+
 use channel::oneshot::Sender;
 use futures::stream::Stream;
 
-pub use zkms_common::{self, RequestMethod, RequestResponse, CryptoAlgo, HasKeysPair};
+pub use zkms_common::{self, RequestError, RequestMethod, RequestResponse, CryptoAlgo, CryptoPublicPair, HasKeysPair, MessagePolicy, RateLimit};
 
 /// Type alias for the channel to send the result of the request to
 pub type ResponseSender<E> = Sender<Result<RequestResponse, E>>;
@@ -0,0 +1,204 @@
+//! WebSocket service implementing `zkms-jsonrpc`'s `Author` API
+
+use std::net::ToSocketAddrs;
+
+use futures::stream::{Stream, StreamExt};
+use jsonrpc_ws_server::jsonrpc_core::{
+    BoxFuture, Error as JsonRpcError, IoHandler, Result as RpcResult,
+};
+
+use host_common::{
+    channel::{self, mpsc::UnboundedReceiver as Receiver, mpsc::UnboundedSender as Sender},
+    CryptoAlgo, HasKeysPair, RequestMethod, RequestResponse, ServiceRequest,
+};
+use zkms_jsonrpc::{
+    author::{Author, Bytes},
+    ErrorWrapper as RpcError,
+};
+
+use crate::{allow_only, internal_error, submit, ALLOWED_HOSTS, ALLOWED_ORIGINS};
+
+/// Origin of polkadot-js apps, allowed by Substrate too
+const POLKADOT_JS_APPS: &str = "https://polkadot.js.org";
+
+/// Key type and algorithm of a session key, like `(*b"babe", CryptoAlgo::Sr25519)`
+pub type SessionKey = ([u8; 4], CryptoAlgo);
+
+/// Will start the WebSocket service as configured and return a list of incoming service requests
+///
+/// `author_rotateKeys` generates a key for each of `session_keys`, in order
+///
+/// Like the JSON-RPC service, web pages can connect only from the local origins
+/// (and polkadot-js apps) and only for the local hosts
+pub async fn start_service<E: Send + Into<RpcError> + 'static>(
+    addr: impl ToSocketAddrs,
+    session_keys: Vec<SessionKey>,
+) -> impl Stream<Item = Result<ServiceRequest<E>, E>> {
+    let mut io = IoHandler::new();
+    let (handler, rx) = AuthorHandler::new(session_keys);
+    io.extend_with(handler.to_delegate());
+
+    let addr = addr
+        .to_socket_addrs()
+        .expect("unable construct address list")
+        .next()
+        .expect("no valid address provided");
+
+    let _ = tokio::task::spawn_blocking(move || {
+        let origins = [ALLOWED_ORIGINS, &[POLKADOT_JS_APPS]].concat();
+        let server = jsonrpc_ws_server::ServerBuilder::new(io)
+            .allowed_origins(allow_only(&origins))
+            .allowed_hosts(allow_only(ALLOWED_HOSTS))
+            .start(&addr)
+            .expect("unable to start websocket server");
+
+        info!("starting author server at : ws://{:}", addr);
+        let _ = server.wait();
+    });
+
+    rx.then(|req| async move { Ok(req) })
+}
+
+struct AuthorHandler<E> {
+    request_sender: Sender<ServiceRequest<E>>,
+    session_keys: Vec<SessionKey>,
+}
+
+impl<E> AuthorHandler<E>
+where
+    E: Send,
+{
+    pub fn new(session_keys: Vec<SessionKey>) -> (AuthorHandler<E>, Receiver<ServiceRequest<E>>) {
+        let (tx, rx) = channel::mpsc::unbounded();
+
+        let handler = Self {
+            request_sender: tx,
+            session_keys,
+        };
+        (handler, rx)
+    }
+}
+
+fn parse_key_type(key_type: &str) -> RpcResult<[u8; 4]> {
+    let mut id = [0; 4];
+    if key_type.len() != id.len() {
+        return Err(JsonRpcError::invalid_params(format!(
+            "key type {:?} is not 4 bytes",
+            key_type
+        )));
+    }
+    id.copy_from_slice(key_type.as_bytes());
+
+    Ok(id)
+}
+
+impl<E> Author for AuthorHandler<E>
+where
+    E: Into<RpcError> + Send + 'static,
+{
+    fn insert_key(
+        &self,
+        key_type: String,
+        suri: String,
+        public: Bytes,
+    ) -> BoxFuture<RpcResult<()>> {
+        info!("insert key requested");
+        let sender = self.request_sender.clone();
+
+        Box::pin(async move {
+            let request = RequestMethod::InsertKey {
+                key_type: parse_key_type(&key_type)?,
+                suri,
+                public_key: public.0,
+            };
+
+            match submit(sender, request).await? {
+                RequestResponse::InsertKey { .. } => Ok(()),
+                _ => Err(internal_error("unexpected response")),
+            }
+        })
+    }
+
+    fn rotate_keys(&self) -> BoxFuture<RpcResult<Bytes>> {
+        info!("rotate keys requested");
+        let sender = self.request_sender.clone();
+        let session_keys = self.session_keys.clone();
+
+        Box::pin(async move {
+            if session_keys.is_empty() {
+                return Err(internal_error("no session keys configured"));
+            }
+
+            //the SCALE encoding of the public keys tuple is their concatenation
+            let mut keys = Vec::new();
+            for (key_type, algo) in session_keys {
+                let request = RequestMethod::GenerateNew {
+                    algo,
+                    key_type,
+                    seed: None,
+                };
+
+                match submit(sender.clone(), request).await? {
+                    RequestResponse::GenerateNew { public_key } => keys.extend(public_key),
+                    _ => return Err(internal_error("unexpected response")),
+                }
+            }
+
+            Ok(Bytes(keys))
+        })
+    }
+
+    fn has_key(&self, public_key: Bytes, key_type: String) -> BoxFuture<RpcResult<bool>> {
+        info!("has key requested");
+        let sender = self.request_sender.clone();
+
+        Box::pin(async move {
+            let request = RequestMethod::HasKeys {
+                pairs: vec![HasKeysPair {
+                    key_type: parse_key_type(&key_type)?,
+                    public_key: public_key.0,
+                }],
+            };
+
+            match submit(sender, request).await? {
+                RequestResponse::HasKeys { all } => Ok(all),
+                _ => Err(internal_error("unexpected response")),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use host_common::RequestError;
+
+    #[tokio::test(core_threads = 2)]
+    async fn rotate_keys() {
+        let (handler, rx) = AuthorHandler::new(vec![
+            (*b"aura", CryptoAlgo::Sr25519),
+            (*b"gran", CryptoAlgo::Ed25519),
+        ]);
+        let rpc = jsonrpc_test::Rpc::new(handler.to_delegate());
+
+        tokio::spawn(async move {
+            futures::pin_mut!(rx);
+            while let Some(srv_req) = rx.next().await {
+                let response = match &srv_req.method {
+                    RequestMethod::GenerateNew { key_type, .. } => {
+                        Ok(RequestResponse::GenerateNew {
+                            public_key: key_type.to_vec(),
+                        })
+                    }
+                    _ => Err(RequestError::from("unexpected request".to_string())),
+                };
+                srv_req.reply(response).await
+            }
+        });
+
+        let result = rpc.make_request("author_rotateKeys", &(), jsonrpc_test::Encoding::Compact);
+
+        //"auragran"
+        assert_eq!(result, "\"0x617572616772616e\"");
+    }
+}
@@ -5,21 +5,43 @@ use std::net::ToSocketAddrs;
 #[macro_use]
 extern crate log;
 
+pub mod author;
+
 use futures::{
     stream::{Stream, StreamExt},
-    Future, FutureExt, SinkExt,
+    SinkExt,
+};
+use jsonrpc_http_server::{
+    jsonrpc_core::{BoxFuture, Error as JsonRpcError, IoHandler, Result as RpcResult},
+    DomainsValidation,
 };
-use jsonrpc_http_server::jsonrpc_core::{BoxFuture, IoHandler, Result as RpcResult};
 
 use host_common::{
     channel::{self, mpsc::UnboundedReceiver as Receiver, mpsc::UnboundedSender as Sender},
-    zkms_common::schnorrkel::{PublicKey, Signature},
     RequestMethod, RequestResponse, ServiceRequest,
 };
 use zkms_jsonrpc::{
-    ErrorWrapper as RpcError, PublicKey as ZKMSPublicKey, Signature as ZKMSSignature, ZKMS,
+    sr25519, Bytes, CryptoAlgo, CryptoPublicPair, ErrorWrapper as RpcError, HasKeysPair, KeyType,
+    MessagePolicy, RequestError, VRFSignature, VRFTranscriptData, ZKMS,
 };
 
+/// Origins of the web pages allowed to call the services, the local ones like Substrate does
+const ALLOWED_ORIGINS: &[&str] = &[
+    "http://localhost:*",
+    "http://127.0.0.1:*",
+    "https://localhost:*",
+    "https://127.0.0.1:*",
+];
+
+/// Hosts the requests can be addressed to, besides the listening address,
+/// so pages can't reach the services through DNS rebinding
+const ALLOWED_HOSTS: &[&str] = &["localhost:*", "127.0.0.1:*"];
+
+/// Allow only the given domains, like `ALLOWED_ORIGINS`
+fn allow_only<T: for<'a> From<&'a str>>(domains: &[&str]) -> DomainsValidation<T> {
+    DomainsValidation::AllowOnly(domains.iter().map(|&domain| domain.into()).collect())
+}
+
 /// prepares the IoHandler with the Rpc impl
 fn get_io_handler<E: Into<RpcError> + Send + 'static>() -> (IoHandler, Receiver<ServiceRequest<E>>)
 {
@@ -33,6 +55,8 @@ fn get_io_handler<E: Into<RpcError> + Send + 'static>() -> (IoHandler, Receiver<
 }
 
 /// Will start the JSON-RPC service as configured and return a list of incoming service requests
+///
+/// Requests from web pages are accepted only from `ALLOWED_ORIGINS`, and only for `ALLOWED_HOSTS`
 pub async fn start_service<E: Send + Into<RpcError> + 'static>(
     addr: impl ToSocketAddrs,
 ) -> impl Stream<Item = Result<ServiceRequest<E>, E>> {
@@ -47,7 +71,8 @@ pub async fn start_service<E: Send + Into<RpcError> + 'static>(
 
     let _ = tokio::task::spawn_blocking(move || {
         let server = jsonrpc_http_server::ServerBuilder::new(io)
-            .rest_api(jsonrpc_http_server::RestApi::Unsecure)
+            .cors(allow_only(ALLOWED_ORIGINS))
+            .allowed_hosts(allow_only(ALLOWED_HOSTS))
             .event_loop_executor(tokio::runtime::Handle::current())
             .threads(1)
             .start_http(&addr)
@@ -76,121 +101,296 @@ where
     }
 }
 
+fn internal_error(message: &str) -> JsonRpcError {
+    let mut err = JsonRpcError::internal_error();
+    err.message = message.to_string();
+    err
+}
+
+/// Send the request to the service and wait for its response
+async fn submit<E: Into<RpcError>>(
+    mut sender: Sender<ServiceRequest<E>>,
+    request: RequestMethod,
+) -> RpcResult<RequestResponse> {
+    let (tx, rx) = channel::oneshot::channel();
+
+    let request = ServiceRequest::new(request, tx);
+    sender
+        .send(request)
+        .await
+        .map_err(|_| internal_error("request handler is not running"))?;
+
+    rx.await
+        .map_err(|_| internal_error("request handler dropped the request"))?
+        .map_err(|e| Into::<RpcError>::into(e).into())
+}
+
 impl<E> RpcHandler<E>
 where
-    E: Send + 'static,
+    E: Into<RpcError> + Send + 'static,
 {
-    async fn submit(
-        mut sender: Sender<ServiceRequest<E>>,
+    /// Submit the request to the service, and extract the result from the expected response
+    fn request<T: Send + 'static>(
+        &self,
         request: RequestMethod,
-    ) -> Result<RequestResponse, E> {
-        let (tx, rx) = channel::oneshot::channel();
-
-        let request = ServiceRequest::new(request, tx);
-        sender
-            .send(request)
-            .await
-            .expect("did the handler die? TODO: not panic but error gracefully");
+        extract: fn(RequestResponse) -> Option<T>,
+    ) -> BoxFuture<RpcResult<T>> {
+        debug!("requested {}", request.name());
+        let sender = self.request_sender.clone();
 
-        rx.await
-            .expect("couldn't receive, did the handler drop the tx? TODO: error gracefully")
+        Box::pin(async move {
+            let response = submit(sender, request).await?;
+            extract(response).ok_or_else(|| internal_error("unexpected response"))
+        })
     }
+}
 
-    fn generate_new_impl(
+impl<E> ZKMS for RpcHandler<E>
+where
+    E: Into<RpcError> + Send + 'static,
+{
+    fn generate_new(
         &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
         seed: Option<String>,
-    ) -> impl Future<Output = Result<PublicKey, E>> {
-        let sender = self.request_sender.clone();
+    ) -> BoxFuture<RpcResult<Bytes>> {
+        self.request(
+            RequestMethod::GenerateNew {
+                algo,
+                key_type,
+                seed,
+            },
+            |response| match response {
+                RequestResponse::GenerateNew { public_key } => Some(public_key),
+                _ => None,
+            },
+        )
+    }
 
-        async move {
-            match Self::submit(sender, RequestMethod::GenerateNew { seed }).await? {
-                RequestResponse::GenerateNew { public_key } => Ok(public_key),
-                _ => panic!("expected generatenew response"),
-            }
-        }
+    fn get_public_keys(
+        &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
+    ) -> BoxFuture<RpcResult<Vec<Bytes>>> {
+        self.request(
+            RequestMethod::GetPublicKeys { algo, key_type },
+            |response| match response {
+                RequestResponse::GetPublicKeys { keys } => Some(keys),
+                _ => None,
+            },
+        )
     }
 
-    fn get_public_keys_impl(&self) -> impl Future<Output = Result<Vec<PublicKey>, E>> {
-        let sender = self.request_sender.clone();
+    fn has_keys(&self, pairs: Vec<HasKeysPair>) -> BoxFuture<RpcResult<bool>> {
+        self.request(
+            RequestMethod::HasKeys { pairs },
+            |response| match response {
+                RequestResponse::HasKeys { all } => Some(all),
+                _ => None,
+            },
+        )
+    }
 
-        async move {
-            match Self::submit(sender, RequestMethod::GetPublicKeys).await? {
-                RequestResponse::GetPublicKeys { keys } => Ok(keys),
-                _ => panic!("expected getpublickeys response"),
-            }
-        }
+    fn sign_message(
+        &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
+        public_key: Bytes,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<Bytes>> {
+        self.request(
+            RequestMethod::SignMessage {
+                algo,
+                key_type,
+                public_key,
+                msg,
+            },
+            |response| match response {
+                RequestResponse::SignMessage { signature } => Some(signature),
+                _ => None,
+            },
+        )
     }
 
-    fn sign_message_impl(
+    fn vrf_sign(
         &self,
-        public_key: PublicKey,
-        msg: Vec<u8>,
-    ) -> impl Future<Output = Result<Signature, E>> {
-        let sender = self.request_sender.clone();
+        key_type: KeyType,
+        public_key: sr25519::Public,
+        transcript_data: VRFTranscriptData,
+    ) -> BoxFuture<RpcResult<VRFSignature>> {
+        self.request(
+            RequestMethod::VrfSign {
+                key_type,
+                public_key,
+                transcript_data,
+            },
+            |response| match response {
+                RequestResponse::VrfSign { signature } => Some(signature),
+                _ => None,
+            },
+        )
+    }
 
-        async move {
-            match Self::submit(sender, RequestMethod::SignMessage { public_key, msg }).await? {
-                RequestResponse::SignMessage { signature } => Ok(signature),
-                _ => panic!("expected signmessage response"),
-            }
-        }
+    fn insert_key(
+        &self,
+        key_type: KeyType,
+        suri: String,
+        public_key: Bytes,
+    ) -> BoxFuture<RpcResult<CryptoAlgo>> {
+        self.request(
+            RequestMethod::InsertKey {
+                key_type,
+                suri,
+                public_key,
+            },
+            |response| match response {
+                RequestResponse::InsertKey { algo } => Some(algo),
+                _ => None,
+            },
+        )
     }
-}
 
-impl<E> ZKMS for RpcHandler<E>
-where
-    E: Into<RpcError> + Send + 'static,
-{
-    fn generate_new(&self, seed: Option<String>) -> BoxFuture<RpcResult<ZKMSPublicKey>> {
-        info!("generate new requested");
-        Box::pin(self.generate_new_impl(seed).map(|r| {
-            r.map(|key| key.to_bytes())
-                .map_err(Into::into)
-                .map_err(Into::into)
-        }))
+    fn keys(&self, key_type: KeyType) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>> {
+        self.request(
+            RequestMethod::Keys { key_type },
+            |response| match response {
+                RequestResponse::Keys { keys } => Some(keys),
+                _ => None,
+            },
+        )
     }
 
-    fn get_public_keys(&self) -> BoxFuture<RpcResult<Vec<ZKMSPublicKey>>> {
-        info!("get public keys requested");
-        //keys.into_iter().map(|k| k.to_bytes()).collect()
-        Box::pin(self.get_public_keys_impl().map(|r| {
-            r.map(|ks| ks.into_iter().map(|k| k.to_bytes()).collect())
-                .map_err(Into::into)
-                .map_err(Into::into)
-        }))
+    fn supported_keys(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>> {
+        self.request(
+            RequestMethod::SupportedKeys { key_type, keys },
+            |response| match response {
+                RequestResponse::SupportedKeys { keys } => Some(keys),
+                _ => None,
+            },
+        )
     }
 
-    fn sign_message(
+    fn sign_with_any(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<(CryptoPublicPair, Bytes)>> {
+        self.request(
+            RequestMethod::SignWithAny {
+                key_type,
+                keys,
+                msg,
+            },
+            |response| match response {
+                RequestResponse::SignWithAny { key, signature } => Some((key, signature)),
+                _ => None,
+            },
+        )
+    }
+
+    fn sign_with_all(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<Vec<Result<Bytes, RequestError>>>> {
+        self.request(
+            RequestMethod::SignWithAll {
+                key_type,
+                keys,
+                msg,
+            },
+            |response| match response {
+                RequestResponse::SignWithAll { signatures } => Some(signatures),
+                _ => None,
+            },
+        )
+    }
+
+    fn delete_key(
+        &self,
+        key_type: KeyType,
+        key: Option<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<usize>> {
+        self.request(
+            RequestMethod::DeleteKey { key_type, key },
+            |response| match response {
+                RequestResponse::DeleteKey { deleted } => Some(deleted),
+                _ => None,
+            },
+        )
+    }
+
+    fn rotate_key(
+        &self,
+        key_type: KeyType,
+        key: Option<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>> {
+        self.request(
+            RequestMethod::RotateKey { key_type, key },
+            |response| match response {
+                RequestResponse::RotateKey { keys } => Some(keys),
+                _ => None,
+            },
+        )
+    }
+
+    fn export_sealed(
         &self,
-        public_key: ZKMSPublicKey,
-        msg: Vec<u8>,
-    ) -> BoxFuture<RpcResult<ZKMSSignature>> {
-        info!("sign requested");
-
-        let public_key = PublicKey::from_bytes(&public_key)
-            .map_err(|e| host_common::zkms_common::RequestError::InternalError(e.to_string()))
-            .map_err(|e| RpcError::from(e))
-            .map_err(Into::into);
-
-        match public_key {
-            Ok(public_key) => Box::pin(self.sign_message_impl(public_key, msg).map(|r| {
-                r.map(|s| s.to_bytes().to_vec())
-                    .map_err(Into::into)
-                    .map_err(Into::into)
-            })),
-            Err(e) => Box::pin(async move { return Err(e) }),
-        }
+        key: Option<(KeyType, CryptoPublicPair)>,
+    ) -> BoxFuture<RpcResult<Bytes>> {
+        self.request(
+            RequestMethod::ExportSealed { key },
+            |response| match response {
+                RequestResponse::ExportSealed { blob } => Some(blob),
+                _ => None,
+            },
+        )
+    }
+
+    fn import_sealed(&self, blob: Bytes) -> BoxFuture<RpcResult<usize>> {
+        self.request(
+            RequestMethod::ImportSealed { blob },
+            |response| match response {
+                RequestResponse::ImportSealed { imported } => Some(imported),
+                _ => None,
+            },
+        )
+    }
+
+    fn set_policy(
+        &self,
+        key_type: KeyType,
+        algo: CryptoAlgo,
+        policy: Option<MessagePolicy>,
+    ) -> BoxFuture<RpcResult<()>> {
+        self.request(
+            RequestMethod::SetPolicy {
+                key_type,
+                algo,
+                policy,
+            },
+            |response| match response {
+                RequestResponse::SetPolicy => Some(()),
+                _ => None,
+            },
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use host_common::zkms_common::RequestError;
 
     fn get_test_handler() -> (
         jsonrpc_test::Rpc,
-        impl Stream<Item = Result<ServiceRequest<RpcError>, RpcError>>,
+        impl Stream<Item = Result<ServiceRequest<RequestError>, RequestError>>,
     ) {
         let (handler, rx) = RpcHandler::new();
         let rpc = jsonrpc_test::Rpc::new(handler.to_delegate());
@@ -198,29 +398,56 @@ mod tests {
         (rpc, rx.then(|req| async move { Ok(req) }))
     }
 
+    /// Reply to every request with the result of `reply`
     async fn handle_requests(
-        rx: impl Stream<Item = Result<ServiceRequest<RpcError>, RpcError>> + Send + 'static,
+        rx: impl Stream<Item = Result<ServiceRequest<RequestError>, RequestError>> + Send + 'static,
+        reply: fn(&RequestMethod) -> Result<RequestResponse, RequestError>,
     ) {
         tokio::spawn(async move {
             futures::pin_mut!(rx);
             while let Some(Ok(srv_req)) = rx.next().await {
                 info!("got a request: {:?}", srv_req);
-                let err = RequestError::from("dummy".to_string());
-                srv_req.reply(Err(err.into())).await
+                let response = reply(&srv_req.method);
+                srv_req.reply(response).await
             }
         });
     }
 
     #[tokio::test(core_threads = 2)]
     async fn generate_new() {
-        env_logger::init();
+        let _ = env_logger::try_init();
         let (rpc, rx) = get_test_handler();
 
-        handle_requests(rx).await;
-        // std::mem::drop(rx);
-        let result = rpc.request("generateNew", &());
+        handle_requests(rx, |request| match request {
+            RequestMethod::GenerateNew {
+                algo: CryptoAlgo::Ed25519,
+                key_type,
+                seed: None,
+            } => Ok(RequestResponse::GenerateNew {
+                public_key: key_type.to_vec(),
+            }),
+            _ => Err(RequestError::from("unexpected request".to_string())),
+        })
+        .await;
+
+        let result = rpc.make_request(
+            "generateNew",
+            &("Ed25519", *b"dumm", ()),
+            jsonrpc_test::Encoding::Compact,
+        );
+
+        assert_eq!(result, "[100,117,109,109]");
+    }
+
+    #[tokio::test(core_threads = 2)]
+    async fn error() {
+        let _ = env_logger::try_init();
+        let (rpc, rx) = get_test_handler();
+
+        handle_requests(rx, |_| Err(RequestError::from("dummy".to_string()))).await;
+
+        let result = rpc.request("keys", &(*b"dumm",));
 
-        let result = result.contains("dummy");
-        assert_eq!(result, true);
+        assert!(result.contains("dummy"));
     }
 }
@@ -0,0 +1,27 @@
+//! The key management methods of Substrate's `author` RPC API
+//!
+//! Same names, parameters and results as a Substrate node, so the same tools can be used
+
+use jsonrpc_core::{BoxFuture, Result as RpcResult};
+use jsonrpc_derive::rpc;
+
+/// Hex encoded bytes, like Substrate does
+pub use sp_core::Bytes;
+
+#[cfg_attr(feature = "client", rpc)]
+#[cfg_attr(not(feature = "client"), rpc(server))]
+pub trait Author {
+    /// Inserts the key derived from `suri` for the given key type, like "babe"
+    #[rpc(name = "author_insertKey")]
+    fn insert_key(&self, key_type: String, suri: String, public: Bytes)
+        -> BoxFuture<RpcResult<()>>;
+
+    /// Generates a new key for each session key type,
+    /// returning the concatenation of the public keys
+    #[rpc(name = "author_rotateKeys")]
+    fn rotate_keys(&self) -> BoxFuture<RpcResult<Bytes>>;
+
+    /// Checks if the key is present
+    #[rpc(name = "author_hasKey")]
+    fn has_key(&self, public_key: Bytes, key_type: String) -> BoxFuture<RpcResult<bool>>;
+}
@@ -1,29 +1,138 @@
 //! This crate contains the JSON-RPC definition of the API
+//!
+//! There's a method for each `RequestMethod`, taking its fields as positional parameters
 
 use jsonrpc_core::{BoxFuture, Error as RpcError, Result as RpcResult};
 use jsonrpc_derive::rpc;
 
-use zkms_common::{schnorrkel::PUBLIC_KEY_LENGTH, RequestError};
+pub mod author;
 
-//TEMPORARY
-// see https://github.com/w3f/schnorrkel/issues/66
-pub type PublicKey = [u8; PUBLIC_KEY_LENGTH];
-pub type Signature = Vec<u8>;
+pub use zkms_common::{
+    protocol::{sr25519, VRFSignature, VRFTranscriptData},
+    CryptoAlgo, CryptoPublicPair, HasKeysPair, MessagePolicy, RequestError,
+};
+
+/// Identifier of the key type, like `*b"babe"`
+pub type KeyType = [u8; 4];
+pub type Bytes = Vec<u8>;
 
 #[cfg_attr(feature = "client", rpc)]
 #[cfg_attr(not(feature = "client"), rpc(server))]
 pub trait ZKMS {
+    /// Generates a new key of the given algorithm, returning its public key
     #[rpc(name = "generateNew")]
-    fn generate_new(&self, seed: Option<String>) -> BoxFuture<RpcResult<PublicKey>>;
+    fn generate_new(
+        &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
+        seed: Option<String>,
+    ) -> BoxFuture<RpcResult<Bytes>>;
 
     #[rpc(name = "getPublicKeys")]
-    fn get_public_keys(&self) -> BoxFuture<RpcResult<Vec<PublicKey>>>;
+    fn get_public_keys(
+        &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
+    ) -> BoxFuture<RpcResult<Vec<Bytes>>>;
+
+    /// Checks if all the given keys are present
+    #[rpc(name = "hasKeys")]
+    fn has_keys(&self, pairs: Vec<HasKeysPair>) -> BoxFuture<RpcResult<bool>>;
 
-    #[rpc(name = "signMessage")]
     /// Signs a message with the matching private key to the public key
+    #[rpc(name = "signMessage")]
+    fn sign_message(
+        &self,
+        algo: CryptoAlgo,
+        key_type: KeyType,
+        public_key: Bytes,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<Bytes>>;
+
+    #[rpc(name = "vrfSign")]
+    fn vrf_sign(
+        &self,
+        key_type: KeyType,
+        public_key: sr25519::Public,
+        transcript_data: VRFTranscriptData,
+    ) -> BoxFuture<RpcResult<VRFSignature>>;
+
+    /// Inserts the key derived from `suri`, returning its algorithm
+    #[rpc(name = "insertKey")]
+    fn insert_key(
+        &self,
+        key_type: KeyType,
+        suri: String,
+        public_key: Bytes,
+    ) -> BoxFuture<RpcResult<CryptoAlgo>>;
+
+    #[rpc(name = "keys")]
+    fn keys(&self, key_type: KeyType) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>>;
+
+    /// Filters the given keys, returning only the present ones
+    #[rpc(name = "supportedKeys")]
+    fn supported_keys(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>>;
+
+    /// Signs the message with the first present key
+    #[rpc(name = "signWithAny")]
+    fn sign_with_any(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<(CryptoPublicPair, Bytes)>>;
+
+    /// Signs the message with each key
+    #[rpc(name = "signWithAll")]
+    fn sign_with_all(
+        &self,
+        key_type: KeyType,
+        keys: Vec<CryptoPublicPair>,
+        msg: Bytes,
+    ) -> BoxFuture<RpcResult<Vec<Result<Bytes, RequestError>>>>;
+
+    /// Deletes the given key, or all the keys of the key type, returning how many were deleted
+    #[rpc(name = "deleteKey")]
+    fn delete_key(
+        &self,
+        key_type: KeyType,
+        key: Option<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<usize>>;
+
+    /// Replaces the given key, or all the keys of the key type, returning the new keys
+    #[rpc(name = "rotateKey")]
+    fn rotate_key(
+        &self,
+        key_type: KeyType,
+        key: Option<CryptoPublicPair>,
+    ) -> BoxFuture<RpcResult<Vec<CryptoPublicPair>>>;
+
+    /// Exports the given key, or all the keys, sealed
+    #[rpc(name = "exportSealed")]
+    fn export_sealed(
+        &self,
+        key: Option<(KeyType, CryptoPublicPair)>,
+    ) -> BoxFuture<RpcResult<Bytes>>;
+
+    /// Imports the keys of a sealed blob, returning how many were imported
+    #[rpc(name = "importSealed")]
+    fn import_sealed(&self, blob: Bytes) -> BoxFuture<RpcResult<usize>>;
+
+    /// Sets the signing policy of the key type and algorithm
     ///
-    /// context is "zondax"
-    fn sign_message(&self, public_key: PublicKey, msg: Vec<u8>) -> BoxFuture<RpcResult<Signature>>;
+    /// Once set, a policy can only be replaced by a stricter one: relaxing or removing it
+    /// (with `null`) is denied
+    #[rpc(name = "setPolicy")]
+    fn set_policy(
+        &self,
+        key_type: KeyType,
+        algo: CryptoAlgo,
+        policy: Option<MessagePolicy>,
+    ) -> BoxFuture<RpcResult<()>>;
 }
 
 use derive_more::From;
@@ -42,9 +151,22 @@ impl Into<RpcError> for ErrorWrapper {
                 err
             }
             RequestError::NoKeys(key) => {
-                let hex = hex::encode(&key.to_bytes()[..]);
-                let err = RpcError::invalid_params(format!("key `{}` was not recognized", hex));
-                err
+                let hex = hex::encode(&key);
+                RpcError::invalid_params(format!("key `{}` was not recognized", hex))
+            }
+            err @ RequestError::PairNotFound
+            | err @ RequestError::KeyNotSupported
+            | err @ RequestError::ValidationError(_)
+            | err @ RequestError::BadState(_) => RpcError::invalid_params(err.to_string()),
+            err @ RequestError::Denied(_) => {
+                let mut rpc_err = RpcError::invalid_request();
+                rpc_err.message = err.to_string();
+                rpc_err
+            }
+            err => {
+                let mut rpc_err = RpcError::internal_error();
+                rpc_err.message = err.to_string();
+                rpc_err
             }
         }
     }
@@ -12,4 +12,4 @@
 )]
 
 pub mod protocol;
-pub use protocol::{HandleRequest, RequestError, CryptoAlgo, HasKeysPair, RequestMethod, RequestResponse};
+pub use protocol::{HandleRequest, RequestError, CryptoAlgo, CryptoPublicPair, HasKeysPair, MessagePolicy, RateLimit, RequestMethod, RequestResponse};
@@ -29,12 +29,12 @@ impl std::convert::TryFrom<[u8; 4]> for CryptoAlgo {
     }
 }
 
-impl Into<[u8; 4]> for CryptoAlgo {
-    fn into(self) -> [u8; 4] {
-        match self {
-            Self::Sr25519 => sr25519::CRYPTO_ID.0,
-            Self::Ed25519 => ed25519::CRYPTO_ID.0,
-            Self::Ecdsa => ecdsa::CRYPTO_ID.0,
+impl From<CryptoAlgo> for [u8; 4] {
+    fn from(algo: CryptoAlgo) -> Self {
+        match algo {
+            CryptoAlgo::Sr25519 => sr25519::CRYPTO_ID.0,
+            CryptoAlgo::Ed25519 => ed25519::CRYPTO_ID.0,
+            CryptoAlgo::Ecdsa => ecdsa::CRYPTO_ID.0,
         }
     }
 }
@@ -47,12 +47,63 @@ pub struct HasKeysPair {
     pub public_key: Vec<u8>,
 }
 
+///Represents a public key together with the algorithm it's for
 #[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
 #[derive(Debug, Clone)]
+pub struct CryptoPublicPair {
+    pub algo: CryptoAlgo,
+    pub public_key: Vec<u8>,
+}
+
+impl std::convert::TryFrom<sp_core::crypto::CryptoTypePublicPair> for CryptoPublicPair {
+    type Error = ();
+
+    fn try_from(value: sp_core::crypto::CryptoTypePublicPair) -> Result<Self, Self::Error> {
+        let sp_core::crypto::CryptoTypePublicPair(id, public_key) = value;
+
+        Ok(Self {
+            algo: CryptoAlgo::try_from(id.0)?,
+            public_key,
+        })
+    }
+}
+
+impl From<CryptoPublicPair> for sp_core::crypto::CryptoTypePublicPair {
+    fn from(pair: CryptoPublicPair) -> Self {
+        sp_core::crypto::CryptoTypePublicPair(
+            sp_core::crypto::CryptoTypeId(pair.algo.into()),
+            pair.public_key,
+        )
+    }
+}
+
+///Restrictions on the messages signed with the keys of a key type and algorithm
+#[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct MessagePolicy {
+    ///Allowed (inclusive) ranges of the message length, any length if empty
+    pub lengths: Vec<(u64, u64)>,
+    ///The message must start with one of these (SCALE encoded) prefixes, any if empty
+    pub prefixes: Vec<Vec<u8>>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+///Maximum number of signatures in a period of time
+#[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub max: u32,
+    pub period_ms: u64,
+}
+
+///Printed without the secrets it might carry, see its `Debug` implementation
+#[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Clone)]
 pub enum RequestMethod {
     GenerateNew {
         algo: CryptoAlgo,
         key_type: [u8; 4],
+        seed: Option<String>,
     },
     GetPublicKeys {
         algo: CryptoAlgo,
@@ -72,24 +123,262 @@ pub enum RequestMethod {
         public_key: sr25519::Public,
         transcript_data: VRFTranscriptData,
     },
+    InsertKey {
+        key_type: [u8; 4],
+        suri: String,
+        public_key: Vec<u8>,
+    },
+    Keys {
+        key_type: [u8; 4],
+    },
+    SupportedKeys {
+        key_type: [u8; 4],
+        keys: Vec<CryptoPublicPair>,
+    },
+    SignWithAny {
+        key_type: [u8; 4],
+        keys: Vec<CryptoPublicPair>,
+        msg: Vec<u8>,
+    },
+    SignWithAll {
+        key_type: [u8; 4],
+        keys: Vec<CryptoPublicPair>,
+        msg: Vec<u8>,
+    },
+    DeleteKey {
+        key_type: [u8; 4],
+        key: Option<CryptoPublicPair>,
+    },
+    RotateKey {
+        key_type: [u8; 4],
+        key: Option<CryptoPublicPair>,
+    },
+    ExportSealed {
+        key: Option<([u8; 4], CryptoPublicPair)>,
+    },
+    ImportSealed {
+        blob: Vec<u8>,
+    },
+    SetPolicy {
+        key_type: [u8; 4],
+        algo: CryptoAlgo,
+        policy: Option<MessagePolicy>,
+    },
+}
+
+/// Stands in for a secret when printing
+struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+//written by hand so the secrets don't end up in the logs
+impl std::fmt::Debug for RequestMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GenerateNew {
+                algo,
+                key_type,
+                seed,
+            } => f
+                .debug_struct("GenerateNew")
+                .field("algo", algo)
+                .field("key_type", key_type)
+                .field("seed", &seed.as_ref().map(|_| Redacted))
+                .finish(),
+            Self::GetPublicKeys { algo, key_type } => f
+                .debug_struct("GetPublicKeys")
+                .field("algo", algo)
+                .field("key_type", key_type)
+                .finish(),
+            Self::HasKeys { pairs } => f.debug_struct("HasKeys").field("pairs", pairs).finish(),
+            Self::SignMessage {
+                algo,
+                key_type,
+                public_key,
+                msg,
+            } => f
+                .debug_struct("SignMessage")
+                .field("algo", algo)
+                .field("key_type", key_type)
+                .field("public_key", public_key)
+                .field("msg", msg)
+                .finish(),
+            Self::VrfSign {
+                key_type,
+                public_key,
+                transcript_data,
+            } => f
+                .debug_struct("VrfSign")
+                .field("key_type", key_type)
+                .field("public_key", public_key)
+                .field("transcript_data", transcript_data)
+                .finish(),
+            Self::InsertKey {
+                key_type,
+                suri: _,
+                public_key,
+            } => f
+                .debug_struct("InsertKey")
+                .field("key_type", key_type)
+                .field("suri", &Redacted)
+                .field("public_key", public_key)
+                .finish(),
+            Self::Keys { key_type } => f.debug_struct("Keys").field("key_type", key_type).finish(),
+            Self::SupportedKeys { key_type, keys } => f
+                .debug_struct("SupportedKeys")
+                .field("key_type", key_type)
+                .field("keys", keys)
+                .finish(),
+            Self::SignWithAny {
+                key_type,
+                keys,
+                msg,
+            } => f
+                .debug_struct("SignWithAny")
+                .field("key_type", key_type)
+                .field("keys", keys)
+                .field("msg", msg)
+                .finish(),
+            Self::SignWithAll {
+                key_type,
+                keys,
+                msg,
+            } => f
+                .debug_struct("SignWithAll")
+                .field("key_type", key_type)
+                .field("keys", keys)
+                .field("msg", msg)
+                .finish(),
+            Self::DeleteKey { key_type, key } => f
+                .debug_struct("DeleteKey")
+                .field("key_type", key_type)
+                .field("key", key)
+                .finish(),
+            Self::RotateKey { key_type, key } => f
+                .debug_struct("RotateKey")
+                .field("key_type", key_type)
+                .field("key", key)
+                .finish(),
+            Self::ExportSealed { key } => f.debug_struct("ExportSealed").field("key", key).finish(),
+            Self::ImportSealed { blob } => {
+                f.debug_struct("ImportSealed").field("blob", blob).finish()
+            }
+            Self::SetPolicy {
+                key_type,
+                algo,
+                policy,
+            } => f
+                .debug_struct("SetPolicy")
+                .field("key_type", key_type)
+                .field("algo", algo)
+                .field("policy", policy)
+                .finish(),
+        }
+    }
+}
+
+impl RequestMethod {
+    /// Name of the method, to log requests without their content
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GenerateNew { .. } => "GenerateNew",
+            Self::GetPublicKeys { .. } => "GetPublicKeys",
+            Self::HasKeys { .. } => "HasKeys",
+            Self::SignMessage { .. } => "SignMessage",
+            Self::VrfSign { .. } => "VrfSign",
+            Self::InsertKey { .. } => "InsertKey",
+            Self::Keys { .. } => "Keys",
+            Self::SupportedKeys { .. } => "SupportedKeys",
+            Self::SignWithAny { .. } => "SignWithAny",
+            Self::SignWithAll { .. } => "SignWithAll",
+            Self::DeleteKey { .. } => "DeleteKey",
+            Self::RotateKey { .. } => "RotateKey",
+            Self::ExportSealed { .. } => "ExportSealed",
+            Self::ImportSealed { .. } => "ImportSealed",
+            Self::SetPolicy { .. } => "SetPolicy",
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
 #[derive(Debug)]
 pub enum RequestResponse {
-    GenerateNew { public_key: Vec<u8> },
-    GetPublicKeys { keys: Vec<Vec<u8>> },
-    HasKeys { all: bool },
-    SignMessage { signature: Vec<u8> },
-    VrfSign { signature: VRFSignature },
+    GenerateNew {
+        public_key: Vec<u8>,
+    },
+    GetPublicKeys {
+        keys: Vec<Vec<u8>>,
+    },
+    HasKeys {
+        all: bool,
+    },
+    SignMessage {
+        signature: Vec<u8>,
+    },
+    VrfSign {
+        signature: VRFSignature,
+    },
+    InsertKey {
+        algo: CryptoAlgo,
+    },
+    Keys {
+        keys: Vec<CryptoPublicPair>,
+    },
+    SupportedKeys {
+        keys: Vec<CryptoPublicPair>,
+    },
+    SignWithAny {
+        key: CryptoPublicPair,
+        signature: Vec<u8>,
+    },
+    SignWithAll {
+        signatures: Vec<Result<Vec<u8>, RequestError>>,
+    },
+    DeleteKey {
+        deleted: usize,
+    },
+    RotateKey {
+        keys: Vec<CryptoPublicPair>,
+    },
+    ExportSealed {
+        blob: Vec<u8>,
+    },
+    ImportSealed {
+        imported: usize,
+    },
+    SetPolicy,
 }
 
+#[cfg_attr(feature = "serde_", derive(serde::Deserialize, serde::Serialize))]
 #[derive(thiserror::Error, Debug)]
 pub enum RequestError {
     #[error("internal error caused by: {0}")]
     InternalError(String),
     #[error("no keys match the given key `{0:x?}`")]
     NoKeys(Vec<u8>),
+    #[error("no key pair found for the public key and key type")]
+    PairNotFound,
+    #[error("key not supported")]
+    KeyNotSupported,
+    #[error("validation error: {0}")]
+    ValidationError(String),
+    #[error("keystore unavailable")]
+    Unavailable,
+    /// The TA panicked while processing the request
+    #[error("the TA is dead")]
+    TargetDead,
+    #[error("signing would be an equivocation")]
+    Equivocation,
+    /// Refused by a policy, like a signing policy of the TA
+    #[error("denied: {0}")]
+    Denied(String),
+    /// The request can't be processed in the current state of the TA
+    #[error("not valid in the current state: {0}")]
+    BadState(String),
 }
 
 impl From<String> for RequestError {
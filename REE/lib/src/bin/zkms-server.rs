@@ -0,0 +1,55 @@
+//! Standalone zkms server
+//!
+//! The configuration is read from the command line and an optional TOML file, see `--help`.
+//! Unlike the framework's host application there's no session already opened with the TA,
+//! so the TA uuid has to be configured to use the optee backend
+
+#[macro_use]
+extern crate log;
+
+use host_app::Config;
+
+fn main() {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    env_logger::Builder::new()
+        .parse_filters(&config.log_level)
+        .init();
+    debug!("configuration: {:?}", config);
+
+    //create tokio runtime for the application
+    let mut rt = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()
+        .expect("unable to initialize tokio runtime");
+
+    let code = rt.block_on(async move {
+        let service = match rustee_host::start(config).await {
+            Ok(service) => service,
+            Err(e) => {
+                error!("unable to start the service: {}", e);
+                return 1;
+            }
+        };
+
+        match service.await {
+            Ok(()) => {
+                info!("service stopped");
+                0
+            }
+            Err(e) => {
+                error!("service failed: {}", e);
+                1
+            }
+        }
+    });
+
+    std::process::exit(code)
+}
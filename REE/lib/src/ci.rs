@@ -4,36 +4,102 @@ use futures::future::TryFutureExt;
 mod client;
 use client::Client;
 
+use host_app::config::ClientConfig;
 use zkms_common::CryptoAlgo;
 use zkms_ductile::{
+    auth,
     crypto::{self, Pair as _},
-    ecdsa, ed25519, sr25519,
+    ecdsa, ed25519, sr25519, KeystoreError,
 };
 
-pub async fn execute_tests(addr: impl std::net::ToSocketAddrs) {
+/// Name the testing client authenticates with
+const CLIENT: &str = "ci";
+const CLIENT_KEY: auth::Key = [0x42; 32];
+
+/// Configuration of the testing client, for the server
+pub fn client_config() -> ClientConfig {
+    let key = std::env::temp_dir().join("zkms-ci-client.hex");
+    std::fs::write(&key, hex::encode(CLIENT_KEY)).expect("unable to write the client key");
+
+    ClientConfig {
+        name: CLIENT.to_string(),
+        key,
+        key_types: None,
+        operations: None,
+    }
+}
+
+pub async fn execute_tests(addr: impl tokio::net::ToSocketAddrs + Copy) {
     info!("Connecting testing client...");
-    let client = Client::connect(addr).expect("server not running!");
+    let client = Client::connect(addr, Some((CLIENT, &CLIENT_KEY))).expect("server not running!");
 
     info!("TESTS STARTING");
 
+    Test::new(
+        "authentication 00",
+        "connect with the wrong key",
+        || match Client::connect(addr, Some((CLIENT, &[0; 32]))) {
+            None => Ok(()),
+            Some(_) => Err("connected with the wrong key"),
+        },
+    )
+    .exec();
+
+    Test::new(
+        "authentication 01",
+        "issue a request without authenticating",
+        || {
+            let unauthenticated = Client::connect(addr, None).ok_or("unable to connect")?;
+
+            match unauthenticated.sr25519_generate_new(None) {
+                Err(_) => Ok(()),
+                Ok(_) => Err("request processed without authentication"),
+            }
+        },
+    )
+    .exec();
+
     Test::new(
         "generateNew 00",
         "generate new sr25519 keypair and return a public key; no seed",
         || {
             client
-                .sr25519_generate_new()
+                .sr25519_generate_new(None)
                 .map_err(|e| format!("failed to issue request: {:?}", e))?;
             Ok::<_, String>(())
         },
     )
     .exec();
 
+    Test::new(
+        "generateNew 01",
+        "generate sr25519 keypair from seed and compare the public key",
+        || {
+            const SEED: &str = "//Alice";
+
+            let key = client
+                .sr25519_generate_new(Some(SEED))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            let expected = sr25519::Pair::from_string(SEED, None)
+                .map_err(|e| format!("invalid seed: {:?}", e))?
+                .public();
+
+            if key == expected {
+                Ok(())
+            } else {
+                Err("public key doesn't match the seed".to_string())
+            }
+        },
+    )
+    .exec();
+
     Test::new(
         "signMessage 00",
         "sign a message with sr25519 and verify signature",
         || {
             let key = client
-                .sr25519_generate_new()
+                .sr25519_generate_new(None)
                 .map_err(|e| format!("failed to issue request: {:?}", e))?;
 
             const MSG: &[u8] = "support@zondax.ch".as_bytes();
@@ -71,7 +137,7 @@ pub async fn execute_tests(addr: impl std::net::ToSocketAddrs) {
         "attempt to retrieve sr25519 public keys, min 1",
         || {
             let _ = client
-                .sr25519_generate_new()
+                .sr25519_generate_new(None)
                 .map_err(|e| format!("unable to make request: {:?}", e))?;
 
             let keys = client.sr25519_public_keys();
@@ -107,7 +173,7 @@ pub async fn execute_tests(addr: impl std::net::ToSocketAddrs) {
         "attempt to check presence of freshly generated key",
         || {
             let pk = client
-                .sr25519_generate_new()
+                .sr25519_generate_new(None)
                 .map_err(|e| format!("failed to issue request: {:?}", e))?;
 
             let query = vec![pk.0.to_vec()];
@@ -130,7 +196,7 @@ pub async fn execute_tests(addr: impl std::net::ToSocketAddrs) {
         "attempt to sign vrf with freshly generated key",
         || {
             let pk = client
-                .sr25519_generate_new()
+                .sr25519_generate_new(None)
                 .map_err(|e| format!("failed to issue request: {:?}", e))?;
 
             let vrf = client
@@ -144,6 +210,196 @@ pub async fn execute_tests(addr: impl std::net::ToSocketAddrs) {
     )
     .exec();
 
+    Test::new(
+        "insertUnknown 00",
+        "import sr25519 keypair from suri and check it's present",
+        || {
+            const SURI: &str = "//Alice";
+
+            let public = sr25519::Pair::from_string(SURI, None)
+                .map_err(|e| format!("invalid suri: {:?}", e))?
+                .public();
+
+            client
+                .insert_unknown(SURI, &public.0)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            if client.has_keys(vec![public.0.to_vec()]) {
+                Ok(())
+            } else {
+                Err("imported key not found".to_string())
+            }
+        },
+    )
+    .exec();
+
+    Test::new(
+        "keys 00",
+        "retrieve all keys and check supported keys with a freshly generated ed25519 key",
+        || {
+            let pk = client
+                .ed25519_generate_new(None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            let pair = crypto::CryptoTypePublicPair(ed25519::CRYPTO_ID, pk.0.to_vec());
+
+            let keys = client
+                .keys()
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            debug!("keys={:x?}", keys);
+
+            if !keys.contains(&pair) {
+                return Err("generated key not listed".to_string());
+            }
+
+            let unknown = crypto::CryptoTypePublicPair(ed25519::CRYPTO_ID, vec![0; 32]);
+            let supported = client
+                .supported_keys(vec![pair.clone(), unknown])
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            if supported == vec![pair] {
+                Ok(())
+            } else {
+                Err(format!("unexpected supported keys: {:x?}", supported))
+            }
+        },
+    )
+    .exec();
+
+    Test::new(
+        "signWithAll 00",
+        "sign a message with a known and an unknown ecdsa key",
+        || {
+            let pk = client
+                .ecdsa_generate_new(None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            let pair = crypto::CryptoTypePublicPair(ecdsa::CRYPTO_ID, pk.0.to_vec());
+            let unknown = crypto::CryptoTypePublicPair(ecdsa::CRYPTO_ID, vec![0; 33]);
+
+            const MSG: &[u8] = "support@zondax.ch".as_bytes();
+
+            let (signer, sign) = client
+                .sign_with_any(vec![unknown.clone(), pair.clone()], MSG)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            if signer != pair
+                || !ecdsa::Pair::verify(&ecdsa::Signature::from_slice(&sign[..]), MSG, &pk)
+            {
+                return Err("sign with any didn't use the known key".to_string());
+            }
+
+            let results = client
+                .sign_with_all(vec![pair, unknown], MSG)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            match &results[..] {
+                //the unknown key must be reported as such
+                [Ok(sign), Err(KeystoreError::PairNotFound(_))]
+                    if ecdsa::Pair::verify(&ecdsa::Signature::from_slice(&sign[..]), MSG, &pk) =>
+                {
+                    Ok(())
+                }
+                _ => Err(format!("unexpected results: {:x?}", results)),
+            }
+        },
+    )
+    .exec();
+
+    Test::new(
+        "rotateKey 00",
+        "rotate a freshly generated ed25519 key, then delete the new one",
+        || {
+            let pk = client
+                .ed25519_generate_new(None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            let old = crypto::CryptoTypePublicPair(ed25519::CRYPTO_ID, pk.0.to_vec());
+
+            let rotated = client
+                .rotate_key(Some(old.clone()))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            let new = match &rotated[..] {
+                [new] if new.0 == ed25519::CRYPTO_ID && new != &old => new.clone(),
+                _ => return Err(format!("unexpected rotated keys: {:x?}", rotated)),
+            };
+
+            if client.has_keys(vec![old.1]) {
+                return Err("old key still present after rotation".to_string());
+            }
+
+            let deleted = client
+                .delete_key(Some(new.clone()))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            if deleted == 1 && !client.has_keys(vec![new.1]) {
+                Ok(())
+            } else {
+                Err("new key not deleted".to_string())
+            }
+        },
+    )
+    .exec();
+
+    Test::new(
+        "sealed 00",
+        "export a sealed sr25519 key, delete it and import it back",
+        || {
+            let pk = client
+                .sr25519_generate_new(None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+            let pair = crypto::CryptoTypePublicPair(sr25519::CRYPTO_ID, pk.0.to_vec());
+
+            let blob = client
+                .export_sealed(Some(pair.clone()))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            client
+                .delete_key(Some(pair))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            let imported = client
+                .import_sealed(blob)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            if imported == 1 && client.has_keys(vec![pk.0.to_vec()]) {
+                Ok(())
+            } else {
+                Err("sealed key not imported back".to_string())
+            }
+        },
+    )
+    .exec();
+
+    Test::new(
+        "policy 00",
+        "restrict the length of the messages signed by ed25519 keys",
+        || {
+            let pk = client
+                .ed25519_generate_new(None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            let policy = zkms_ductile::MessagePolicy {
+                lengths: vec![(0, 4)],
+                ..Default::default()
+            };
+            client
+                .set_policy(CryptoAlgo::Ed25519, Some(policy))
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            let short = client.sign_with(CryptoAlgo::Ed25519, pk.0.to_vec(), b"zkms");
+            let long = client.sign_with(CryptoAlgo::Ed25519, pk.0.to_vec(), b"support@zondax.ch");
+
+            //don't leave the policy around for the other tests
+            client
+                .set_policy(CryptoAlgo::Ed25519, None)
+                .map_err(|e| format!("failed to issue request: {:?}", e))?;
+
+            match (short, long) {
+                (Ok(_), Err(_)) => Ok(()),
+                (short, long) => Err(format!("unexpected results: {:?} {:?}", short, long)),
+            }
+        },
+    )
+    .exec();
+
     info!("TESTS FINISHED");
 }
 
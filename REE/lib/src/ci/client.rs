@@ -1,36 +1,73 @@
 use std::borrow::Cow;
-use std::net::ToSocketAddrs;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
 
-use ductile::{ChannelReceiver, ChannelSender};
+use tokio::net::ToSocketAddrs;
 use zkms_common::CryptoAlgo;
 use zkms_ductile::{
-    crypto::{self, KeyTypeId},
-    ecdsa, ed25519, sr25519, KeystoreError, RemoteKeystore, RemoteKeystoreResponse, VRFSignature,
-    VRFTranscriptData, VRFTranscriptValue,
+    auth,
+    crypto::{self, CryptoTypePublicPair, KeyTypeId},
+    ecdsa, ed25519, sr25519,
+    transport::{self, Receiver, Sender},
+    KeystoreError, MessagePolicy, RemoteKeystore, RemoteKeystoreResponse, Request, Response,
+    VRFSignature, VRFTranscriptData, VRFTranscriptValue,
 };
 
 pub struct Client {
-    tx: ChannelSender<RemoteKeystore>,
-    rx: ChannelReceiver<RemoteKeystoreResponse>,
+    tx: RefCell<Sender<Request>>,
+    rx: RefCell<Receiver<Response>>,
+    /// ID of the last request sent
+    id: Cell<u64>,
 }
 
 const KEY_TYPE: [u8; 4] = *b"dumm";
 
+/// Wait for the future, letting the runtime drive the connection meanwhile
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+    tokio::task::block_in_place(|| futures::executor::block_on(future))
+}
+
 impl Client {
-    pub fn connect(addr: impl ToSocketAddrs) -> Option<Self> {
-        let (tx, rx) = ductile::connect_channel(addr).ok()?;
+    /// Connect to the server, authenticating as the given client if any
+    pub fn connect(addr: impl ToSocketAddrs, auth: Option<(&str, &auth::Key)>) -> Option<Self> {
+        block_on(async {
+            let (mut tx, mut rx) = transport::connect(addr, None).await.ok()?;
+
+            if let Some((client, key)) = auth {
+                auth::client_handshake(client, key, &mut tx, &mut rx)
+                    .await
+                    .ok()?;
+            }
+
+            Some(Self {
+                tx: RefCell::new(tx),
+                rx: RefCell::new(rx),
+                id: Cell::new(0),
+            })
+        })
+    }
+
+    /// Send the request with the next ID
+    fn send(&self, request: RemoteKeystore) -> Result<(), impl std::fmt::Debug> {
+        self.id.set(self.id.get() + 1);
+        block_on(self.tx.borrow_mut().send(&Request {
+            id: self.id.get(),
+            request,
+        }))
+    }
 
-        Some(Self { tx, rx })
+    /// Receive the response to the last request, which must have its ID
+    fn recv(&self) -> Result<RemoteKeystoreResponse, impl std::fmt::Debug> {
+        block_on(self.rx.borrow_mut().recv()).map(|Response { id, response }| {
+            assert_eq!(id, self.id.get(), "response to another request");
+            response
+        })
     }
 
     pub fn sr25519_public_keys(&self) -> Vec<sr25519::Public> {
-        match self
-            .tx
-            .send(RemoteKeystore::Sr25519PublicKeys(KeyTypeId(KEY_TYPE)))
-        {
+        match self.send(RemoteKeystore::Sr25519PublicKeys(KeyTypeId(KEY_TYPE))) {
             Err(_) => vec![],
             Ok(_) => self
-                .rx
                 .recv()
                 .and_then(|resp| {
                     if let RemoteKeystoreResponse::Sr25519PublicKeys(resp) = resp {
@@ -45,14 +82,16 @@ impl Client {
         }
     }
 
-    pub fn sr25519_generate_new(&self) -> Result<sr25519::Public, KeystoreError> {
-        match self.tx.send(RemoteKeystore::Sr25519GenerateNew {
+    pub fn sr25519_generate_new(
+        &self,
+        seed: Option<&str>,
+    ) -> Result<sr25519::Public, KeystoreError> {
+        match self.send(RemoteKeystore::Sr25519GenerateNew {
             id: KeyTypeId(KEY_TYPE),
-            seed: None,
+            seed: seed.map(ToString::to_string),
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -67,13 +106,9 @@ impl Client {
     }
 
     pub fn ed25519_public_keys(&self) -> Vec<ed25519::Public> {
-        match self
-            .tx
-            .send(RemoteKeystore::Ed25519PublicKeys(KeyTypeId(KEY_TYPE)))
-        {
+        match self.send(RemoteKeystore::Ed25519PublicKeys(KeyTypeId(KEY_TYPE))) {
             Err(_) => vec![],
             Ok(_) => self
-                .rx
                 .recv()
                 .and_then(|resp| {
                     if let RemoteKeystoreResponse::Ed25519PublicKeys(resp) = resp {
@@ -88,14 +123,16 @@ impl Client {
         }
     }
 
-    pub fn ed25519_generate_new(&self) -> Result<ed25519::Public, KeystoreError> {
-        match self.tx.send(RemoteKeystore::Ed25519GenerateNew {
+    pub fn ed25519_generate_new(
+        &self,
+        seed: Option<&str>,
+    ) -> Result<ed25519::Public, KeystoreError> {
+        match self.send(RemoteKeystore::Ed25519GenerateNew {
             id: KeyTypeId(KEY_TYPE),
-            seed: None,
+            seed: seed.map(ToString::to_string),
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -110,13 +147,9 @@ impl Client {
     }
 
     pub fn ecdsa_public_keys(&self) -> Vec<ecdsa::Public> {
-        match self
-            .tx
-            .send(RemoteKeystore::EcdsaPublicKeys(KeyTypeId(KEY_TYPE)))
-        {
+        match self.send(RemoteKeystore::EcdsaPublicKeys(KeyTypeId(KEY_TYPE))) {
             Err(_) => vec![],
             Ok(_) => self
-                .rx
                 .recv()
                 .and_then(|resp| {
                     if let RemoteKeystoreResponse::EcdsaPublicKeys(resp) = resp {
@@ -131,14 +164,13 @@ impl Client {
         }
     }
 
-    pub fn ecdsa_generate_new(&self) -> Result<ecdsa::Public, KeystoreError> {
-        match self.tx.send(RemoteKeystore::EcdsaGenerateNew {
+    pub fn ecdsa_generate_new(&self, seed: Option<&str>) -> Result<ecdsa::Public, KeystoreError> {
+        match self.send(RemoteKeystore::EcdsaGenerateNew {
             id: KeyTypeId(KEY_TYPE),
-            seed: None,
+            seed: seed.map(ToString::to_string),
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -158,10 +190,9 @@ impl Client {
             .map(|v| (v, KeyTypeId(KEY_TYPE)))
             .collect();
 
-        match self.tx.send(RemoteKeystore::HasKeys(pairs)) {
+        match self.send(RemoteKeystore::HasKeys(pairs)) {
             Err(_) => false,
             Ok(_) => self
-                .rx
                 .recv()
                 .and_then(|resp| {
                     if let RemoteKeystoreResponse::HasKeys(resp) = resp {
@@ -182,14 +213,13 @@ impl Client {
         key: Vec<u8>,
         msg: &[u8],
     ) -> Result<Vec<u8>, KeystoreError> {
-        match self.tx.send(RemoteKeystore::SignWith {
+        match self.send(RemoteKeystore::SignWith {
             id: KeyTypeId(KEY_TYPE),
             key: crypto::CryptoTypePublicPair(crypto::CryptoTypeId(algo.into()), key),
             msg: msg.to_vec(),
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -218,14 +248,13 @@ impl Client {
             ],
         };
 
-        match self.tx.send(RemoteKeystore::Sr25519VrfSign {
+        match self.send(RemoteKeystore::Sr25519VrfSign {
             key_type: KeyTypeId(KEY_TYPE),
             public: *public,
             transcript_data,
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -238,23 +267,77 @@ impl Client {
                 }),
         }
     }
-}
 
-/*
-impl Client {
+    pub fn insert_unknown(&self, suri: &str, public: &[u8]) -> Result<(), ()> {
+        match self.send(RemoteKeystore::InsertUnknown {
+            id: KeyTypeId(KEY_TYPE),
+            suri: suri.to_string(),
+            public: Vec::from(public),
+        }) {
+            Err(_) => Err(()),
+            Ok(_) => self.recv().map_err(|_| ()).and_then(|resp| {
+                if let RemoteKeystoreResponse::InsertUnknown(resp) = resp {
+                    resp
+                } else {
+                    //unreachable!()
+                    Err(())
+                }
+            }),
+        }
+    }
+
+    pub fn supported_keys(
+        &self,
+        keys: Vec<CryptoTypePublicPair>,
+    ) -> Result<Vec<CryptoTypePublicPair>, KeystoreError> {
+        match self.send(RemoteKeystore::SupportedKeys {
+            id: KeyTypeId(KEY_TYPE),
+            keys,
+        }) {
+            Err(_) => Err(KeystoreError::Unavailable),
+            Ok(_) => self
+                .recv()
+                .map_err(|_| KeystoreError::Unavailable)
+                .and_then(|resp| {
+                    if let RemoteKeystoreResponse::SupportedKeys(resp) = resp {
+                        resp
+                    } else {
+                        //unreachable!()
+                        Err(KeystoreError::Unavailable)
+                    }
+                }),
+        }
+    }
+
+    pub fn keys(&self) -> Result<Vec<CryptoTypePublicPair>, KeystoreError> {
+        match self.send(RemoteKeystore::Keys(KeyTypeId(KEY_TYPE))) {
+            Err(_) => Err(KeystoreError::Unavailable),
+            Ok(_) => self
+                .recv()
+                .map_err(|_| KeystoreError::Unavailable)
+                .and_then(|resp| {
+                    if let RemoteKeystoreResponse::Keys(resp) = resp {
+                        resp
+                    } else {
+                        //unreachable!()
+                        Err(KeystoreError::Unavailable)
+                    }
+                }),
+        }
+    }
+
     pub fn sign_with_any(
         &self,
         keys: Vec<CryptoTypePublicPair>,
         msg: &[u8],
     ) -> Result<(CryptoTypePublicPair, Vec<u8>), KeystoreError> {
-        match self.tx.send(RemoteKeystore::SignWithAny {
+        match self.send(RemoteKeystore::SignWithAny {
             id: KeyTypeId(KEY_TYPE),
             keys,
             msg: msg.to_vec(),
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
@@ -273,13 +356,13 @@ impl Client {
         keys: Vec<CryptoTypePublicPair>,
         msg: &[u8],
     ) -> Result<Vec<Result<Vec<u8>, KeystoreError>>, ()> {
-        match self.tx.send(RemoteKeystore::SignWithAll {
+        match self.send(RemoteKeystore::SignWithAll {
             id: KeyTypeId(KEY_TYPE),
             keys,
             msg: msg.to_vec(),
         }) {
             Err(_) => Err(()),
-            Ok(_) => self.rx.recv().map_err(|_| ()).and_then(|resp| {
+            Ok(_) => self.recv().map_err(|_| ()).and_then(|resp| {
                 if let RemoteKeystoreResponse::SignWithAll(resp) = resp {
                     resp
                 } else {
@@ -290,39 +373,40 @@ impl Client {
         }
     }
 
-    pub fn insert_unknown(&self, suri: &str, public: &[u8]) -> Result<(), ()> {
-        match self.tx.send(RemoteKeystore::InsertUnknown {
+    pub fn delete_key(&self, key: Option<CryptoTypePublicPair>) -> Result<usize, KeystoreError> {
+        match self.send(RemoteKeystore::DeleteKey {
             id: KeyTypeId(KEY_TYPE),
-            suri: suri.to_string(),
-            public: Vec::from(public),
+            key,
         }) {
-            Err(_) => Err(()),
-            Ok(_) => self.rx.recv().map_err(|_| ()).and_then(|resp| {
-                if let RemoteKeystoreResponse::InsertUnknown(resp) = resp {
-                    resp
-                } else {
-                    //unreachable!()
-                    Err(())
-                }
-            }),
+            Err(_) => Err(KeystoreError::Unavailable),
+            Ok(_) => self
+                .recv()
+                .map_err(|_| KeystoreError::Unavailable)
+                .and_then(|resp| {
+                    if let RemoteKeystoreResponse::DeleteKey(resp) = resp {
+                        resp
+                    } else {
+                        //unreachable!()
+                        Err(KeystoreError::Unavailable)
+                    }
+                }),
         }
     }
 
-    pub fn supported_keys(
+    pub fn rotate_key(
         &self,
-        keys: Vec<CryptoTypePublicPair>,
+        key: Option<CryptoTypePublicPair>,
     ) -> Result<Vec<CryptoTypePublicPair>, KeystoreError> {
-        match self.tx.send(RemoteKeystore::SupportedKeys {
+        match self.send(RemoteKeystore::RotateKey {
             id: KeyTypeId(KEY_TYPE),
-            keys,
+            key,
         }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
-                    if let RemoteKeystoreResponse::SupportedKeys(resp) = resp {
+                    if let RemoteKeystoreResponse::RotateKey(resp) = resp {
                         resp
                     } else {
                         //unreachable!()
@@ -332,15 +416,61 @@ impl Client {
         }
     }
 
-    pub fn keys(&self) -> Result<Vec<CryptoTypePublicPair>, KeystoreError> {
-        match self.tx.send(RemoteKeystore::Keys(KeyTypeId(KEY_TYPE))) {
+    pub fn export_sealed(
+        &self,
+        key: Option<CryptoTypePublicPair>,
+    ) -> Result<Vec<u8>, KeystoreError> {
+        match self.send(RemoteKeystore::ExportSealed {
+            key: key.map(|key| (KeyTypeId(KEY_TYPE), key)),
+        }) {
             Err(_) => Err(KeystoreError::Unavailable),
             Ok(_) => self
-                .rx
                 .recv()
                 .map_err(|_| KeystoreError::Unavailable)
                 .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Keys(resp) = resp {
+                    if let RemoteKeystoreResponse::ExportSealed(resp) = resp {
+                        resp
+                    } else {
+                        //unreachable!()
+                        Err(KeystoreError::Unavailable)
+                    }
+                }),
+        }
+    }
+
+    pub fn import_sealed(&self, blob: Vec<u8>) -> Result<usize, KeystoreError> {
+        match self.send(RemoteKeystore::ImportSealed { blob }) {
+            Err(_) => Err(KeystoreError::Unavailable),
+            Ok(_) => self
+                .recv()
+                .map_err(|_| KeystoreError::Unavailable)
+                .and_then(|resp| {
+                    if let RemoteKeystoreResponse::ImportSealed(resp) = resp {
+                        resp
+                    } else {
+                        //unreachable!()
+                        Err(KeystoreError::Unavailable)
+                    }
+                }),
+        }
+    }
+
+    pub fn set_policy(
+        &self,
+        algo: CryptoAlgo,
+        policy: Option<MessagePolicy>,
+    ) -> Result<(), KeystoreError> {
+        match self.send(RemoteKeystore::SetPolicy {
+            id: KeyTypeId(KEY_TYPE),
+            crypto: crypto::CryptoTypeId(algo.into()),
+            policy,
+        }) {
+            Err(_) => Err(KeystoreError::Unavailable),
+            Ok(_) => self
+                .recv()
+                .map_err(|_| KeystoreError::Unavailable)
+                .and_then(|resp| {
+                    if let RemoteKeystoreResponse::SetPolicy(resp) = resp {
                         resp
                     } else {
                         //unreachable!()
@@ -350,4 +480,3 @@ impl Client {
         }
     }
 }
-*/
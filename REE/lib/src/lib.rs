@@ -2,47 +2,189 @@
 //!
 //! It starts the chosen service (dependency and code) and feeds it to the logic in `host_app`, aswell as the
 //! default handler for the requests
+//!
+//! It's either linked in the framework's host application, which calls `run`,
+//! or used by the standalone `zkms-server` binary
 
 #![no_builtins]
 
 #[macro_use]
 extern crate log;
 
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use host_app::config::{Backend, Config, Transport};
+use zkms_common::RequestError;
+
 mod optee_handler;
+mod session;
+#[cfg(feature = "software")]
+mod software_handler;
+
+/// Start the configured transports, forwarding their requests to the configured backend
+///
+/// Returns the task processing the requests, which runs as long as the transports do
+pub async fn start(config: Config) -> Result<tokio::task::JoinHandle<()>, String> {
+    //the framework's configuration isn't loaded, so it wasn't checked yet
+    config.validate().map_err(|e| e.to_string())?;
+
+    let enc_key = match config.encryption.as_ref() {
+        Some(encryption) => Some(encryption.load_key().map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    let sealing_secret = match config.sealing.as_ref() {
+        Some(sealing) => Some(sealing.load_secret().map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    let clients: HashMap<_, _> = config
+        .clients
+        .iter()
+        .map(|client| {
+            let key = client.load_key().map_err(|e| e.to_string())?;
+            let acl = client.acl().map_err(|e| e.to_string())?;
+            Ok((client.name.clone(), host_ductile::Client { key, acl }))
+        })
+        .collect::<Result<_, String>>()?;
+
+    //start the services
+    let mut services = Vec::new();
+    for transport in &config.transports {
+        match transport {
+            Transport::Ductile => {
+                info!("starting ductile service on {}...", config.listen);
+                let service = host_ductile::start_service::<RequestError>(
+                    config.listen,
+                    enc_key,
+                    clients.clone(),
+                    config.max_connections,
+                )
+                .await;
+                services.push(service.boxed());
+            }
+            Transport::Jsonrpc => {
+                info!("starting JSON-RPC service on {}...", config.jsonrpc_listen);
+                warn!("JSON-RPC peers are NOT authenticated");
+                let service =
+                    host_jsonrpc::start_service::<RequestError>(config.jsonrpc_listen).await;
+                services.push(service.boxed());
+            }
+            Transport::Author => {
+                info!("starting author service on {}...", config.author_listen);
+                warn!("author peers are NOT authenticated");
+                let session_keys = config.parse_session_keys().map_err(|e| e.to_string())?;
+                let service = host_jsonrpc::author::start_service::<RequestError>(
+                    config.author_listen,
+                    session_keys,
+                )
+                .await;
+                services.push(service.boxed());
+            }
+        }
+    }
+    if services.is_empty() {
+        return Err("no transport enabled".to_string());
+    }
+    let service = futures::stream::select_all(services);
+    info!("services started! forwarding to handler...");
+
+    //spawn the host service that retrieves requests and handles them with the appropriate handler
+    let task = match config.backend {
+        #[cfg(feature = "software")]
+        Backend::Software => {
+            warn!("using the software backend, keys are NOT protected by the TEE");
+            let handler = software_handler::SoftwareHandler::default();
+            if let Some(secret) = sealing_secret.as_ref() {
+                handler.provision_sealing(secret).map_err(sealing_error)?;
+                info!("sealing secret provisioned");
+            }
+
+            tokio::spawn(host_app::start_service_with_workers(
+                service,
+                handler,
+                config.workers,
+            ))
+        }
+        #[cfg(not(feature = "software"))]
+        Backend::Software => {
+            return Err(
+                "software backend not available, build with the `software` feature".to_string(),
+            )
+        }
+        Backend::Optee => {
+            let handler = optee_handler::Handler::new(config.ta_uuid.as_deref(), config.workers)?;
+            if let Some(secret) = sealing_secret.as_ref() {
+                handler.provision_sealing(secret).map_err(sealing_error)?;
+                info!("sealing secret provisioned");
+            }
 
-use optee_common::{CommandId, TeeError};
-use zondee_teec::wrapper::{raw, Operation, Param};
+            tokio::spawn(host_app::start_service_with_workers(
+                service,
+                handler,
+                config.workers,
+            ))
+        }
+    };
 
-extern "C" {
-    fn invoke_optee_command(command_id: u32, op: *mut raw::TEEC_Operation) -> u32;
-    fn recover_panic();
+    Ok(task)
 }
 
-pub(crate) fn invoke_command<A: Param, B: Param, C: Param, D: Param>(
-    id: CommandId,
-    op: &mut Operation<A, B, C, D>,
-) -> Result<(), TeeError> {
-    let res = unsafe { invoke_optee_command(id as u32, op.as_mut_ptr()) };
-    if res == 0 {
-        Ok(())
-    } else {
-        let err = TeeError::from_raw_error(res);
-        if let optee_common::TeeErrorCode::TargetDead = err.kind() {
-            unsafe {
-                recover_panic();
-            }
+/// Explain why the sealing secret couldn't be provisioned
+fn sealing_error(error: RequestError) -> String {
+    match error {
+        RequestError::BadState(_) => {
+            "the TA was provisioned with another sealing secret".to_string()
         }
-        error!("An error occured when invoking command: {}", err.message());
-        Err(err)
+        error => format!("unable to provision the sealing secret: {}", error),
     }
 }
 
+/// Configuration of the framework's host application
+///
+/// Read from the TOML file at `RUSTEE_CONFIG` if set, like the one of `zkms-server`.
+/// Otherwise the ductile transport listens on all the addresses and accepts any peer,
+/// since the node connects from outside (like from the host of the QEMU guest).
+/// The TA uuid can be set with `RUSTEE_TA_UUID` in both cases
+#[cfg(feature = "framework")]
+fn framework_config() -> Result<Config, host_app::config::ConfigError> {
+    use host_app::config::DEFAULT_PORT;
+    use std::net::Ipv4Addr;
+
+    let mut config = match std::env::var_os("RUSTEE_CONFIG") {
+        Some(path) => Config::from_file(path.as_ref())?,
+        None => Config {
+            listen: (Ipv4Addr::UNSPECIFIED, DEFAULT_PORT).into(),
+            allow_unauthenticated: true,
+            ..Config::default()
+        },
+    };
+    if let Ok(ta_uuid) = std::env::var("RUSTEE_TA_UUID") {
+        config.ta_uuid = Some(ta_uuid);
+    }
+    #[cfg(feature = "ci")]
+    config.clients.push(ci::client_config());
+
+    Ok(config)
+}
+
+/// Entry point for the framework's host application
+///
+/// See `framework_config` for the configuration
+#[cfg(feature = "framework")]
 #[no_mangle]
 pub extern "C" fn run() -> u32 {
-    const PORT: u16 = 39946;
-
     env_logger::init();
 
+    let config = match framework_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("unable to load the configuration: {}", e);
+            return 1;
+        }
+    };
+
     let mut rt_builder = tokio::runtime::Builder::new();
     rt_builder.enable_all();
 
@@ -59,26 +201,26 @@ pub extern "C" fn run() -> u32 {
         .build()
         .expect("unable to initialize tokio runtime");
 
+    #[cfg(feature = "ci")]
+    let ci_addr = ("localhost", config.listen.port());
+
     rt.block_on(async move {
-        info!("starting ductile service...");
-        //start the service
-        let service = host_ductile::start_service(("0.0.0.0", PORT)).await;
-        info!("ductile service started! forwarding to handler...");
+        let service = match start(config).await {
+            Ok(service) => service,
+            Err(e) => {
+                error!("unable to start the service: {}", e);
+                return 1;
+            }
+        };
 
         cfg_if::cfg_if! {
             if #[cfg(feature = "ci")] {
-                let maybe_ci = ci::execute_tests(("localhost", PORT));
+                let maybe_ci = ci::execute_tests(ci_addr);
             } else {
                 let maybe_ci = futures::future::pending::<()>();
             }
         }
 
-        //spawn the host service that retrieves requests and handles them with the appropriate handler
-        let service = tokio::spawn(host_app::start_service(
-            service,
-            optee_handler::Handler::default(),
-        ));
-
         futures::pin_mut!(maybe_ci);
         futures::pin_mut!(service);
 
@@ -9,168 +9,600 @@ use zkms_common::{
 
 use optee_common::{
     CommandId, Deserialize, DeserializeOwned, DeserializeVariable, Serialize, SerializeFixed,
+    TeeError, TeeErrorCode,
 };
 
-use crate::invoke_command;
+use crate::session::{self, Sessions};
+
+/// Executes TA commands, with the input and output buffers of the command
+pub(crate) trait InvokeCommand {
+    /// On error, the size of the output reported by the TA is given too,
+    /// which after `ShortBuffer` is the size the output should have
+    fn invoke_reporting_size(
+        &mut self,
+        cmd: CommandId,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), (TeeError, usize)>;
+
+    fn invoke_command(
+        &mut self,
+        cmd: CommandId,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), TeeError> {
+        self.invoke_reporting_size(cmd, input, output)
+            .map_err(|(error, _)| error)
+    }
+}
 
-use zondee_teec::wrapper::{Operation, ParamNone, ParamTmpRef};
+#[cfg_attr(feature = "framework", derive(Default))]
+pub struct Handler {
+    sessions: Sessions,
+}
 
-#[derive(Default)]
-pub struct Handler {}
+impl Handler {
+    /// Create a handler with `n` sessions with the TA identified by `uuid`,
+    /// so up to `n` requests can be processed at the same time
+    ///
+    /// When linked in the framework's host application,
+    /// falls back to the framework's session if there's no uuid or the sessions can't be opened
+    pub fn new(uuid: Option<&str>, n: usize) -> Result<Self, String> {
+        let sessions = uuid
+            .ok_or_else(|| "the TA uuid is needed to open sessions".to_string())
+            .and_then(|uuid| {
+                session::parse_uuid(uuid).ok_or_else(|| format!("invalid TA uuid {:?}", uuid))
+            })
+            .and_then(|uuid| {
+                Sessions::open(uuid, n).map_err(|e| format!("unable to open sessions: {}", e))
+            });
+
+        match sessions {
+            Ok(sessions) => {
+                info!("opened {} sessions with the TA", n);
+                Ok(Self { sessions })
+            }
+            #[cfg(feature = "framework")]
+            Err(e) => {
+                if uuid.is_some() {
+                    error!("{}, using a single session", e);
+                }
+                Ok(Self::default())
+            }
+            #[cfg(not(feature = "framework"))]
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Handler {
+    /// Provision the secret the TA seals the exported keys with, see [`provision_sealing`]
+    pub fn provision_sealing(&self, secret: &[u8; 32]) -> Result<(), RequestError> {
+        let mut session = self.sessions.acquire();
+
+        provision_sealing(&mut *session, secret)
+    }
+}
 
 impl HandleRequest for Handler {
     fn process_request(&self, request: RequestMethod) -> Result<RequestResponse, RequestError> {
-        //convert items from RequestMethod
-        // to something that optee_common understands
-        // and `invoke_command`
-        match request {
-            RequestMethod::GenerateNew { algo, key_type } => {
-                //pass the algo and key type
-                // prepare output for public key
+        //the session is held for the whole request, so commands of a request aren't interleaved
+        let mut session = self.sessions.acquire();
 
-                let algo = crate::utils::convert_crypto_algo_to_optee(algo);
-                let mut out = vec![0u8; algo.pubkey_len()];
-
-                let p0 = {
-                    let mut v = vec![0; 1 + 4];
-                    algo.serialize_fixed(&mut v[..1]).unwrap();
-                    key_type.serialize_fixed(&mut v[1..]).unwrap();
-                    v
-                };
-                let p0 = ParamTmpRef::new_input(p0.as_slice());
+        process_request(&mut *session, request)
+    }
+}
 
-                let p1 = ParamTmpRef::new_output(&mut out[..]);
+/// Process the request with the given backend
+///
+/// Shared by all the handlers, as they all talk to the TA app
+pub(crate) fn process_request(
+    backend: &mut impl InvokeCommand,
+    request: RequestMethod,
+) -> Result<RequestResponse, RequestError> {
+    //convert items from RequestMethod
+    // to something that optee_common understands
+    // and invoke it on the backend
+    match request {
+        RequestMethod::GenerateNew {
+            algo,
+            key_type,
+            seed,
+        } => {
+            //pass the algo and key type
+            // and the seed (with length prepended) only if present
+            // prepare output for public key
+
+            let algo = crate::utils::convert_crypto_algo_to_optee(algo);
+            let mut out = vec![0u8; algo.pubkey_len()];
+
+            let p0 = {
+                let mut v = vec![0; 1 + 4];
+                algo.serialize_fixed(&mut v[..1]).unwrap();
+                key_type.serialize_fixed(&mut v[1..]).unwrap();
+                if let Some(seed) = seed {
+                    v.append(&mut (&seed.as_str()).serialize().unwrap());
+                }
+                v
+            };
+
+            backend
+                .invoke_command(CommandId::GenerateNew, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            Ok(RequestResponse::GenerateNew { public_key: out })
+        }
+        RequestMethod::GetPublicKeys { algo, key_type } => {
+            //we can't know beforehand how many keys there are,
+            // so we start with room for 5 keys and let the TA
+            // tell us how big the buffer should be if that's not enough
+            let algo = crate::utils::convert_crypto_algo_to_optee(algo);
 
-                let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+            let mut out = vec![0; 8 + (8 + algo.pubkey_len()) * 5];
 
-                invoke_command(CommandId::GenerateNew.into(), &mut op)
-                    .map_err(|e| e.to_string())?;
+            let p0 = {
+                let mut v = vec![0; 1 + 4];
+                algo.serialize_fixed(&mut v[..1]).unwrap();
+                key_type.serialize_fixed(&mut v[1..]).unwrap();
+                v
+            };
 
-                Ok(RequestResponse::GenerateNew { public_key: out })
-            }
-            RequestMethod::GetPublicKeys { algo, key_type } => {
-                //ok this prepare just the command id basically
-                // but for the return we might need something else since we can't pass N keys back
-                // because we need to preallocate the out buffer...
-                // UNLESS we can share memory then we can _allocate the memory in the TA_ (what am I thinking?)
-                // pass the pointer in the out slice
-                // reinterpret it and extract the keys from there...
-                // ig we can make 2 request? 1 to retrieve number of public keys
-                // and the other one to actually get the data
-                //
-                // turns out the spec agrees with me, a bit. the size paramenter of the memref
-                // if bigger should be interpreted as a request for a bigger buffer, so in this case
-                // we'd reallocate a bigger buffer of the specified size and pass it again, kinda like asking
-                // how many keys we have..
-
-                // Yeah this for now only supports 5 keys, until I manage to increase the size
-                // out the output dynamically
-                let algo = crate::utils::convert_crypto_algo_to_optee(algo);
+            invoke_with_output(backend, CommandId::GetKeys, &p0, &mut out)
+                .map_err(request_error)?;
 
-                let mut out = vec![0; 8 + (8 + algo.pubkey_len()) * 5]; //just prepare for 5 keys for now
+            let (_, keys) = DeserializeVariable::deserialize_variable(&out)
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
 
-                let p0 = {
-                    let mut v = vec![0; 1 + 4];
-                    algo.serialize_fixed(&mut v[..1]).unwrap();
-                    key_type.serialize_fixed(&mut v[1..]).unwrap();
-                    v
-                };
-                let p0 = ParamTmpRef::new_input(p0.as_slice());
+            Ok(RequestResponse::GetPublicKeys { keys })
+        }
+        RequestMethod::SignMessage {
+            algo,
+            key_type,
+            public_key,
+            msg,
+        } => {
+            //send algo and key type
+            // key might have different size so prepend length
+            // and also for msg
+
+            let algo = crate::utils::convert_crypto_algo_to_optee(algo);
+            let mut out = vec![0u8; algo.signature_len()];
+
+            let vec = {
+                let mut vec = vec![0u8; 1 + 4];
+                algo.serialize_fixed(&mut vec[..1]).unwrap();
+                key_type.serialize_fixed(&mut vec[1..]).unwrap();
+                vec.append(&mut (&public_key.as_slice()).serialize().unwrap());
+                vec.append(&mut (&msg.as_slice()).serialize().unwrap());
+                vec
+            };
+
+            backend
+                .invoke_command(CommandId::SignMessage, &vec, &mut out[..])
+                .map_err(request_error)?;
+
+            Ok(RequestResponse::SignMessage { signature: out })
+        }
+        RequestMethod::HasKeys { pairs } => {
+            //prepend lenght of entire set
+            // each pair has key type (fixed size) and public key, which might have varying lenght
+            let p0 = pairs
+                .into_iter()
+                .map(crate::utils::convert_haskeys_to_optee)
+                .collect::<Vec<_>>()
+                .serialize()
+                .unwrap();
+
+            //prepare output for a simple boolean
+            // 0 = false
+            // 1 = true
+
+            let mut out = [0];
+
+            backend
+                .invoke_command(CommandId::HasKeys, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            Ok(RequestResponse::HasKeys { all: out[0] == 1 })
+        }
+        RequestMethod::VrfSign {
+            key_type,
+            public_key,
+            transcript_data,
+        } => {
+            let p0 = {
+                let mut v = vec![0; 4 + 32];
+                key_type.serialize_fixed(&mut v[..4]).unwrap();
+                public_key.0.serialize_fixed(&mut v[4..]).unwrap();
+
+                //serialize transcript data
+                v.append(&mut transcript_data.serialize().unwrap());
+                v
+            };
+
+            let mut out = vec![0; VRFSignature::len()];
+
+            backend
+                .invoke_command(CommandId::VrfSign, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            let signature = VRFSignature::deserialize_owned(&out).unwrap();
+
+            Ok(RequestResponse::VrfSign { signature })
+        }
+        RequestMethod::InsertKey {
+            key_type,
+            suri,
+            public_key,
+        } => {
+            //send key type, then suri and public key with their length prepended
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..4]).unwrap();
+                v.append(&mut (&suri.as_str()).serialize().unwrap());
+                v.append(&mut (&public_key.as_slice()).serialize().unwrap());
+                v
+            };
+
+            //the TA tells us which algo the key was for
+            let mut out = [0; 1];
+
+            backend
+                .invoke_command(CommandId::ImportKey, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            let algo = optee_common::CryptoAlgo::deserialize_owned(&out)
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+
+            Ok(RequestResponse::InsertKey {
+                algo: crate::utils::convert_crypto_algo_to_zkms(algo),
+            })
+        }
+        RequestMethod::Keys { key_type } => {
+            //same as GetPublicKeys, start with room for 5 keys per curve
+            let mut out = vec![0; 8 + (1 + 8 + 33) * 5 * 3];
 
-                let p1 = ParamTmpRef::new_output(&mut out[..]);
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                v
+            };
 
-                let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+            invoke_with_output(backend, CommandId::Keys, &p0, &mut out).map_err(request_error)?;
 
-                invoke_command(CommandId::GetKeys.into(), &mut op).map_err(|e| e.to_string())?;
+            let (_, keys): (_, Vec<optee_common::CryptoPublicPair>) =
+                DeserializeVariable::deserialize_variable(&out)
+                    .map_err(|e| format!("malformed output from ta: {:?}", e))?;
 
-                let (_, keys) = DeserializeVariable::deserialize_variable(&out)
+            Ok(RequestResponse::Keys {
+                keys: keys
+                    .into_iter()
+                    .map(crate::utils::convert_cryptopair_to_zkms)
+                    .collect(),
+            })
+        }
+        RequestMethod::SupportedKeys { key_type, keys } => {
+            //send key type and the list of pairs
+            let keys = keys
+                .into_iter()
+                .map(crate::utils::convert_cryptopair_to_optee)
+                .collect::<Vec<_>>()
+                .serialize()
+                .unwrap();
+
+            //at most all of the given keys are supported
+            let mut out = vec![0; keys.len()];
+
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                v.extend_from_slice(&keys);
+                v
+            };
+
+            backend
+                .invoke_command(CommandId::SupportedKeys, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            let (_, keys): (_, Vec<optee_common::CryptoPublicPair>) =
+                DeserializeVariable::deserialize_variable(&out)
                     .map_err(|e| format!("malformed output from ta: {:?}", e))?;
 
-                Ok(RequestResponse::GetPublicKeys { keys })
+            Ok(RequestResponse::SupportedKeys {
+                keys: keys
+                    .into_iter()
+                    .map(crate::utils::convert_cryptopair_to_zkms)
+                    .collect(),
+            })
+        }
+        RequestMethod::SignWithAny {
+            key_type,
+            keys,
+            msg,
+        } => {
+            let keys = keys
+                .into_iter()
+                .map(crate::utils::convert_cryptopair_to_optee)
+                .collect::<Vec<_>>();
+
+            //enough for the biggest pair and its signature
+            let out_len = keys
+                .iter()
+                .map(|pair| 1 + 8 + pair.public_key.len() + 8 + pair.algo.signature_len())
+                .max()
+                .unwrap_or(0);
+            let mut out = vec![0; out_len];
+
+            //send key type, the list of pairs and the message with its length prepended
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                v.append(&mut keys.serialize().unwrap());
+                v.append(&mut (&msg.as_slice()).serialize().unwrap());
+                v
+            };
+
+            backend
+                .invoke_command(CommandId::SignWithAny, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            let (size, key) = optee_common::CryptoPublicPair::deserialize_variable(&out)
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+            let signature: &[u8] = Deserialize::deserialize(&out[size..])
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+
+            Ok(RequestResponse::SignWithAny {
+                key: crate::utils::convert_cryptopair_to_zkms(key),
+                signature: signature.to_vec(),
+            })
+        }
+        RequestMethod::SignWithAll {
+            key_type,
+            keys,
+            msg,
+        } => {
+            let keys = keys
+                .into_iter()
+                .map(crate::utils::convert_cryptopair_to_optee)
+                .collect::<Vec<_>>();
+
+            //n of results, then for each key the result code and the signature
+            let out_len = 8 + keys
+                .iter()
+                .map(|pair| 4 + 8 + pair.algo.signature_len())
+                .sum::<usize>();
+            let mut out = vec![0; out_len];
+
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                v.append(&mut keys.serialize().unwrap());
+                v.append(&mut (&msg.as_slice()).serialize().unwrap());
+                v
+            };
+
+            backend
+                .invoke_command(CommandId::SignWithAll, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            let n: [u8; 8] = DeserializeOwned::deserialize_owned(&out)
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+            let n = u64::from_le_bytes(n) as usize;
+            if n != keys.len() {
+                return Err(format!(
+                    "malformed output from ta: expected {} results, got {}",
+                    keys.len(),
+                    n
+                )
+                .into());
             }
-            RequestMethod::SignMessage {
-                algo,
-                key_type,
-                public_key,
-                msg,
-            } => {
-                //send algo and key type
-                // key might have different size so prepend length
-                // and also for msg
 
-                let algo = crate::utils::convert_crypto_algo_to_optee(algo);
-                let mut out = vec![0u8; algo.signature_len()];
-
-                let vec = {
-                    let mut vec = vec![0u8; 1 + 4];
-                    algo.serialize_fixed(&mut vec[..1]).unwrap();
-                    key_type.serialize_fixed(&mut vec[1..]).unwrap();
-                    vec.append(&mut (&public_key.as_slice()).serialize().unwrap());
-                    vec.append(&mut (&msg.as_slice()).serialize().unwrap());
-                    vec
-                };
-                let p0 = ParamTmpRef::new_input(&vec);
-
-                let p1 = ParamTmpRef::new_output(&mut out[..]);
+            let mut cursor = &out[8..];
+            let mut signatures = Vec::with_capacity(n);
+            for pair in keys {
+                let code: [u8; 4] = DeserializeOwned::deserialize_owned(cursor)
+                    .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+                let code = u32::from_le_bytes(code);
 
-                let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+                let signature: &[u8] = Deserialize::deserialize(&cursor[4..])
+                    .map_err(|e| format!("malformed output from ta: {:?}", e))?;
+                cursor = &cursor[4 + 8 + signature.len()..];
+
+                signatures.push(match code {
+                    0 => Ok(signature.to_vec()),
+                    code => match TeeError::from_raw_error(code) {
+                        err if err.kind() == TeeErrorCode::PairNotFound => {
+                            Err(RequestError::NoKeys(pair.public_key))
+                        }
+                        err => Err(request_error(err)),
+                    },
+                });
+            }
 
-                invoke_command(CommandId::SignMessage.into(), &mut op)
-                    .map_err(|e| e.to_string())?;
+            Ok(RequestResponse::SignWithAll { signatures })
+        }
+        RequestMethod::DeleteKey { key_type, key } => {
+            //send key type and the pair, if any
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                if let Some(key) = key {
+                    let key = crate::utils::convert_cryptopair_to_optee(key);
+                    v.append(&mut key.serialize().unwrap());
+                }
+                v
+            };
+
+            //the TA tells us how many keys were deleted
+            let mut out = [0; 8];
+
+            backend
+                .invoke_command(CommandId::DeleteKey, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            Ok(RequestResponse::DeleteKey {
+                deleted: u64::from_le_bytes(out) as usize,
+            })
+        }
+        RequestMethod::RotateKey { key_type, key } => {
+            //room for a single key, or 5 keys per curve like Keys,
+            // the TA will tell us if it's not enough
+            let mut out = match key {
+                Some(_) => vec![0; 8 + 1 + 8 + 33],
+                None => vec![0; 8 + (1 + 8 + 33) * 5 * 3],
+            };
+
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                if let Some(key) = key {
+                    let key = crate::utils::convert_cryptopair_to_optee(key);
+                    v.append(&mut key.serialize().unwrap());
+                }
+                v
+            };
+
+            invoke_with_output(backend, CommandId::RotateKey, &p0, &mut out)
+                .map_err(request_error)?;
+
+            let (_, keys): (_, Vec<optee_common::CryptoPublicPair>) =
+                DeserializeVariable::deserialize_variable(&out)
+                    .map_err(|e| format!("malformed output from ta: {:?}", e))?;
 
-                Ok(RequestResponse::SignMessage { signature: out })
-            }
-            RequestMethod::HasKeys { pairs } => {
-                //prepend lenght of entire set
-                // each pair has key type (fixed size) and public key, which might have varying lenght
-                let p0 = pairs
+            Ok(RequestResponse::RotateKey {
+                keys: keys
                     .into_iter()
-                    .map(crate::utils::convert_haskeys_to_optee)
-                    .collect::<Vec<_>>()
-                    .serialize()
-                    .unwrap();
-                let p0 = ParamTmpRef::new_input(&p0);
+                    .map(crate::utils::convert_cryptopair_to_zkms)
+                    .collect(),
+            })
+        }
+        RequestMethod::ExportSealed { key } => {
+            //send the key type and pair, nothing to export the whole key set
+            let p0 = match key {
+                Some((key_type, key)) => {
+                    let mut v = vec![0; 4];
+                    key_type.serialize_fixed(&mut v[..]).unwrap();
+                    let key = crate::utils::convert_cryptopair_to_optee(key);
+                    v.append(&mut key.serialize().unwrap());
+                    v
+                }
+                None => vec![],
+            };
 
-                //prepare output for a simple boolean
-                // 0 = false
-                // 1 = true
+            //enough for a few keys, the TA will tell us if it's not
+            let mut out = vec![0; 1024];
 
-                let mut out = [0];
-                let p1 = ParamTmpRef::new_output(&mut out[..]);
+            invoke_with_output(backend, CommandId::ExportSealed, &p0, &mut out)
+                .map_err(request_error)?;
 
-                let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+            let blob: &[u8] = Deserialize::deserialize(&out)
+                .map_err(|e| format!("malformed output from ta: {:?}", e))?;
 
-                invoke_command(CommandId::HasKeys.into(), &mut op).map_err(|e| e.to_string())?;
+            Ok(RequestResponse::ExportSealed {
+                blob: blob.to_vec(),
+            })
+        }
+        RequestMethod::ImportSealed { blob } => {
+            //send the blob with its length prepended
+            let p0 = (&blob.as_slice()).serialize().unwrap();
 
-                Ok(RequestResponse::HasKeys { all: out[0] == 1 })
-            }
-            RequestMethod::VrfSign {
-                key_type,
-                public_key,
-                transcript_data,
-            } => {
-                let p0 = {
-                    let mut v = vec![0; 4 + 32];
-                    key_type.serialize_fixed(&mut v[..4]).unwrap();
-                    public_key.0.serialize_fixed(&mut v[4..]).unwrap();
-
-                    //serialize transcript data
-                    v.append(&mut transcript_data.serialize().unwrap());
-                    v
-                };
-                let p0 = ParamTmpRef::new_input(&p0);
+            //the TA tells us how many keys were imported
+            let mut out = [0; 8];
 
-                let mut out = vec![0; VRFSignature::len()];
-                let p1 = ParamTmpRef::new_output(&mut out[..]);
+            backend
+                .invoke_command(CommandId::ImportSealed, &p0, &mut out[..])
+                .map_err(request_error)?;
 
-                let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+            Ok(RequestResponse::ImportSealed {
+                imported: u64::from_le_bytes(out) as usize,
+            })
+        }
+        RequestMethod::SetPolicy {
+            key_type,
+            algo,
+            policy,
+        } => {
+            //send key type, algo and the policy, if any
+            let p0 = {
+                let mut v = vec![0; 4];
+                key_type.serialize_fixed(&mut v[..]).unwrap();
+                let algo = crate::utils::convert_crypto_algo_to_optee(algo);
+                v.append(&mut algo.serialize().unwrap());
+                if let Some(policy) = policy {
+                    let policy = crate::utils::convert_policy_to_optee(policy);
+                    v.append(&mut policy.serialize().unwrap());
+                }
+                v
+            };
+
+            //nothing is written back, but the TA always expects an output
+            let mut out = [0; 1];
+
+            backend
+                .invoke_command(CommandId::SetPolicy, &p0, &mut out[..])
+                .map_err(request_error)?;
+
+            Ok(RequestResponse::SetPolicy)
+        }
+    }
+}
 
-                invoke_command(CommandId::VrfSign.into(), &mut op).map_err(|e| e.to_string())?;
+/// Provision the secret the TA derives the sealing key from
+///
+/// The TA keeps it in its secure storage and refuses to replace it afterwards,
+/// so provisioning it again is fine only with the same secret
+pub(crate) fn provision_sealing(
+    backend: &mut impl InvokeCommand,
+    secret: &[u8; 32],
+) -> Result<(), RequestError> {
+    //nothing is written back, but the TA always expects an output
+    let mut out = [0; 1];
+
+    backend
+        .invoke_command(CommandId::ProvisionSealing, secret, &mut out[..])
+        .map_err(request_error)
+}
 
-                let signature = VRFSignature::deserialize_owned(&out).unwrap();
+/// Keep the errors of the TA that callers can act on, like a missing key,
+/// instead of flattening everything into an internal error
+fn request_error(error: TeeError) -> RequestError {
+    match error.kind() {
+        TeeErrorCode::PairNotFound => RequestError::PairNotFound,
+        TeeErrorCode::KeyNotSupported => RequestError::KeyNotSupported,
+        TeeErrorCode::ValidationError => RequestError::ValidationError(error.to_string()),
+        TeeErrorCode::Unavailable | TeeErrorCode::Busy => RequestError::Unavailable,
+        TeeErrorCode::TargetDead => RequestError::TargetDead,
+        TeeErrorCode::Equivocation => RequestError::Equivocation,
+        TeeErrorCode::AccessDenied => RequestError::Denied(error.to_string()),
+        TeeErrorCode::BadState => RequestError::BadState(error.to_string()),
+        //the only security fault is a sealed blob failing to unseal
+        TeeErrorCode::Security => RequestError::ValidationError(error.to_string()),
+        _ => RequestError::InternalError(error.to_string()),
+    }
+}
 
-                Ok(RequestResponse::VrfSign { signature })
-            }
+/// Invoke the command, growing `out` as long as the TA reports it's too small
+///
+/// On `ShortBuffer` the TA updates the size of the output memref to the required size,
+/// so we can retry with a buffer of that size
+fn invoke_with_output(
+    backend: &mut impl InvokeCommand,
+    cmd: CommandId,
+    input: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), TeeError> {
+    loop {
+        let required = match backend.invoke_reporting_size(cmd, input, &mut out[..]) {
+            Err((e, required)) if e.kind() == TeeErrorCode::ShortBuffer => required,
+            result => return result.map_err(|(error, _)| error),
+        };
+
+        //the TA should never ask for a smaller buffer, don't loop forever if it does
+        if required <= out.len() {
+            return Err(TeeError::new(TeeErrorCode::ShortBuffer));
         }
+
+        debug!("output buffer too small, retrying with {} bytes", required);
+        out.resize(required, 0);
     }
 }
@@ -0,0 +1,245 @@
+//! Pool of TEEC sessions with the TA
+//!
+//! Each session can only process one command at a time, so having more than one
+//! lets the handler invoke commands concurrently
+
+use std::ops::{Deref, DerefMut};
+
+use optee_common::{CommandId, TeeError, TeeErrorCode};
+use parking_lot::{Condvar, Mutex};
+use zondee_teec::wrapper::{raw, Operation, Param, ParamNone, ParamTmpRef};
+
+use crate::optee_handler::InvokeCommand;
+
+#[cfg(feature = "framework")]
+extern "C" {
+    fn invoke_optee_command(command_id: u32, op: *mut raw::TEEC_Operation) -> u32;
+    fn recover_panic();
+}
+
+/// A session with the TA
+pub(crate) enum Session {
+    /// The session opened by the framework before `run`
+    #[cfg(feature = "framework")]
+    Framework,
+    /// A session opened on its own context
+    Owned {
+        context: Box<raw::TEEC_Context>,
+        session: Box<raw::TEEC_Session>,
+        uuid: raw::TEEC_UUID,
+    },
+}
+
+//the context and session are only ever used by the worker holding the session
+unsafe impl Send for Session {}
+
+impl Session {
+    /// Open a new session with the TA identified by `uuid`
+    pub fn open(uuid: raw::TEEC_UUID) -> Result<Self, TeeError> {
+        let mut context: Box<raw::TEEC_Context> = Box::new(unsafe { std::mem::zeroed() });
+        let mut session: Box<raw::TEEC_Session> = Box::new(unsafe { std::mem::zeroed() });
+
+        let res = unsafe { raw::TEEC_InitializeContext(std::ptr::null(), context.as_mut()) };
+        if res != 0 {
+            return Err(TeeError::from_raw_error(res));
+        }
+
+        let mut origin = 0;
+        let res = unsafe {
+            raw::TEEC_OpenSession(
+                context.as_mut(),
+                session.as_mut(),
+                &uuid,
+                raw::TEEC_LOGIN_PUBLIC,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                &mut origin,
+            )
+        };
+        if res != 0 {
+            unsafe { raw::TEEC_FinalizeContext(context.as_mut()) };
+            return Err(TeeError::from_raw_error(res));
+        }
+
+        Ok(Self::Owned {
+            context,
+            session,
+            uuid,
+        })
+    }
+
+    /// Invoke the given command on the TA with the given operation
+    fn invoke<A: Param, B: Param, C: Param, D: Param>(
+        &mut self,
+        id: CommandId,
+        op: &mut Operation<A, B, C, D>,
+    ) -> Result<(), TeeError> {
+        let res = match self {
+            #[cfg(feature = "framework")]
+            Self::Framework => unsafe { invoke_optee_command(id as u32, op.as_mut_ptr()) },
+            Self::Owned { session, .. } => {
+                let mut origin = 0;
+                unsafe {
+                    raw::TEEC_InvokeCommand(
+                        session.as_mut(),
+                        id as u32,
+                        op.as_mut_ptr(),
+                        &mut origin,
+                    )
+                }
+            }
+        };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            let err = TeeError::from_raw_error(res);
+            if let TeeErrorCode::TargetDead = err.kind() {
+                self.recover();
+            }
+            error!("An error occured when invoking command: {}", err.message());
+            Err(err)
+        }
+    }
+
+    /// Reopen the session after the TA panicked
+    fn recover(&mut self) {
+        match self {
+            #[cfg(feature = "framework")]
+            Self::Framework => unsafe { recover_panic() },
+            Self::Owned { uuid, .. } => match Self::open(*uuid) {
+                Ok(session) => *self = session,
+                Err(e) => error!("unable to reopen session: {}", e.message()),
+            },
+        }
+    }
+}
+
+impl InvokeCommand for Session {
+    fn invoke_reporting_size(
+        &mut self,
+        cmd: CommandId,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), (TeeError, usize)> {
+        let p0 = ParamTmpRef::new_input(input);
+        let p1 = ParamTmpRef::new_output(output);
+
+        let mut op = Operation::new(p0, p1, ParamNone, ParamNone);
+
+        self.invoke(cmd, &mut op).map_err(|error| {
+            //the driver copies back the size of the memref as updated by the TA
+            let (_, output, _, _) = op.parameters();
+            (error, output.updated_size())
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        match self {
+            //the framework closes its own session
+            #[cfg(feature = "framework")]
+            Self::Framework => {}
+            Self::Owned {
+                context, session, ..
+            } => unsafe {
+                raw::TEEC_CloseSession(session.as_mut());
+                raw::TEEC_FinalizeContext(context.as_mut());
+            },
+        }
+    }
+}
+
+/// Parse a TA UUID in the canonical `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form
+pub(crate) fn parse_uuid(uuid: &str) -> Option<raw::TEEC_UUID> {
+    let bytes = hex::decode(uuid.replace('-', "")).ok()?;
+    if bytes.len() != 16 || uuid.len() != 36 {
+        return None;
+    }
+
+    let mut clock_seq_and_node = [0; 8];
+    clock_seq_and_node.copy_from_slice(&bytes[8..]);
+
+    Some(raw::TEEC_UUID {
+        timeLow: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        timeMid: u16::from_be_bytes([bytes[4], bytes[5]]),
+        timeHiAndVersion: u16::from_be_bytes([bytes[6], bytes[7]]),
+        clockSeqAndNode: clock_seq_and_node,
+    })
+}
+
+/// Pool of sessions, handed out to one caller at a time
+pub(crate) struct Sessions {
+    idle: Mutex<Vec<Session>>,
+    available: Condvar,
+}
+
+#[cfg(feature = "framework")]
+impl Default for Sessions {
+    /// Only the framework session
+    fn default() -> Self {
+        Self::new(vec![Session::Framework])
+    }
+}
+
+impl Sessions {
+    pub fn new(sessions: Vec<Session>) -> Self {
+        Self {
+            idle: Mutex::new(sessions),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Open `n` sessions with the TA identified by `uuid`
+    pub fn open(uuid: raw::TEEC_UUID, n: usize) -> Result<Self, TeeError> {
+        let sessions = (0..n.max(1))
+            .map(|_| Session::open(uuid))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self::new(sessions))
+    }
+
+    /// Take an idle session, blocking until one is available
+    pub fn acquire(&self) -> SessionGuard<'_> {
+        let mut idle = self.idle.lock();
+        loop {
+            if let Some(session) = idle.pop() {
+                return SessionGuard {
+                    pool: self,
+                    session: Some(session),
+                };
+            }
+            self.available.wait(&mut idle);
+        }
+    }
+}
+
+/// Session taken from the pool, returned to it on drop
+pub(crate) struct SessionGuard<'p> {
+    pool: &'p Sessions,
+    session: Option<Session>,
+}
+
+impl Deref for SessionGuard<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        self.session.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for SessionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.session.as_mut().unwrap()
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.idle.lock().push(session);
+            self.pool.available.notify_one();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Handler that implements the HandleRequest trait without a TEE
+//!
+//! The TA app is embedded and executed directly in the host, so the keys are not protected in any way.
+//! This is only meant for development and testing on machines without OPTEE,
+//! and the keys are kept in memory only, so they are lost when the handler is dropped
+use std::time::Instant;
+
+use optee_common::{CommandId, HandleTaCommand, TeeError};
+use parking_lot::Mutex;
+use ta_app::TaApp;
+use ta_common::{Clock, MemoryStorage};
+use zkms_common::{HandleRequest, RequestError, RequestMethod, RequestResponse};
+
+use crate::optee_handler::{self, InvokeCommand};
+
+/// Milliseconds since the clock was created
+struct SystemClock(Instant);
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        self.0.elapsed().as_millis() as u64
+    }
+}
+
+impl InvokeCommand for TaApp<'_> {
+    fn invoke_reporting_size(
+        &mut self,
+        cmd: CommandId,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), (TeeError, usize)> {
+        self.process_command(cmd, input, output).map_err(|error| {
+            let size = self.required_output_size().unwrap_or_else(|| output.len());
+            (TeeError::new(error), size)
+        })
+    }
+}
+
+pub struct SoftwareHandler {
+    //serialized like the TA serializes its commands
+    app: Mutex<TaApp<'static>>,
+}
+
+// This is safe because the rng, storage and clock of the app are `Send + Sync`,
+// and they are only reachable thru the app, which is behind the mutex
+unsafe impl Send for SoftwareHandler {}
+unsafe impl Sync for SoftwareHandler {}
+
+impl Default for SoftwareHandler {
+    fn default() -> Self {
+        //the app needs them for its whole lifetime, which is the lifetime of the process anyways
+        let rng = Box::leak(Box::new(rand::rngs::OsRng));
+        let storage = Box::leak(Box::new(MemoryStorage::new()));
+        let clock = Box::leak(Box::new(SystemClock(Instant::now())));
+
+        let app = TaApp::new(rng, storage).with_clock(clock);
+        #[cfg(feature = "slashing-protection")]
+        let app = app.with_slashing_protection();
+
+        Self {
+            app: Mutex::new(app),
+        }
+    }
+}
+
+impl SoftwareHandler {
+    /// Provision the secret the app seals the exported keys with
+    pub fn provision_sealing(&self, secret: &[u8; 32]) -> Result<(), RequestError> {
+        optee_handler::provision_sealing(&mut *self.app.lock(), secret)
+    }
+}
+
+impl HandleRequest for SoftwareHandler {
+    fn process_request(&self, request: RequestMethod) -> Result<RequestResponse, RequestError> {
+        let mut app = self.app.lock();
+
+        optee_handler::process_request(&mut *app, request)
+    }
+}
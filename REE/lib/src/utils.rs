@@ -1,5 +1,8 @@
-use optee_common::{CryptoAlgo as CryptoAlgo2, HasKeysPair as HasKeysPair2};
-use zkms_common::{CryptoAlgo, HasKeysPair};
+use optee_common::{
+    CryptoAlgo as CryptoAlgo2, CryptoPublicPair as CryptoPublicPair2, HasKeysPair as HasKeysPair2,
+    MessagePolicy as MessagePolicy2, RateLimit as RateLimit2,
+};
+use zkms_common::{CryptoAlgo, CryptoPublicPair, HasKeysPair, MessagePolicy, RateLimit};
 
 pub(crate) fn convert_crypto_algo_to_optee(from: CryptoAlgo) -> CryptoAlgo2 {
     match from {
@@ -40,3 +43,35 @@ pub(crate) fn convert_haskeys_to_zkms(
         public_key,
     }
 }
+
+pub(crate) fn convert_cryptopair_to_optee(
+    CryptoPublicPair { algo, public_key }: CryptoPublicPair,
+) -> CryptoPublicPair2 {
+    CryptoPublicPair2 {
+        algo: convert_crypto_algo_to_optee(algo),
+        public_key,
+    }
+}
+
+pub(crate) fn convert_cryptopair_to_zkms(
+    CryptoPublicPair2 { algo, public_key }: CryptoPublicPair2,
+) -> CryptoPublicPair {
+    CryptoPublicPair {
+        algo: convert_crypto_algo_to_zkms(algo),
+        public_key,
+    }
+}
+
+pub(crate) fn convert_policy_to_optee(
+    MessagePolicy {
+        lengths,
+        prefixes,
+        rate_limit,
+    }: MessagePolicy,
+) -> MessagePolicy2 {
+    MessagePolicy2 {
+        lengths,
+        prefixes,
+        rate_limit: rate_limit.map(|RateLimit { max, period_ms }| RateLimit2 { max, period_ms }),
+    }
+}
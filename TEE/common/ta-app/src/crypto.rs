@@ -2,12 +2,17 @@ use super::*;
 
 use crate::util::CSPRNG;
 use optee_common::CryptoAlgo;
+use zeroize::Zeroizing;
 
 mod ecdsa;
 mod ed25519;
 mod sr25519;
 pub use sr25519::VRFData;
 
+mod bip39;
+pub mod sealing;
+mod suri;
+
 /// Will contain secrets set during compilation (for initial provisioning)
 ///
 /// Temporary
@@ -34,7 +39,7 @@ fn populate_set(
     algo: CryptoAlgo,
     map: &mut HashMap<[u8; 4], Vec<Keypair>, crate::util::hasher::Builder>,
 ) {
-    if let Ok(seed) = hex::decode(&seed[2..]) {
+    if let Ok(seed) = hex::decode(&seed[2..]).map(Zeroizing::new) {
         for kt in key_types.split_whitespace() {
             let kt = str_to_key_type(kt);
 
@@ -76,7 +81,9 @@ pub fn default_set() -> HashMap<[u8; 4], Vec<Keypair>, crate::util::hasher::Buil
 }
 
 ///Handles all the diffrent supported curves with a unified interface
-#[derive(Clone)]
+///
+/// Not `Clone` on purpose, to avoid spreading copies of the secret around;
+/// the secret of each curve is zeroized on drop
 pub enum Keypair {
     Sr25519(sr25519::Keypair),
     Ed25519(ed25519::Keypair),
@@ -100,6 +107,13 @@ impl Keypair {
         }
     }
 
+    /// Derive the keypair from a secret URI (ie: `//Alice`), as `sp_core::crypto::Pair::from_string`
+    pub fn from_suri<C: CSPRNG>(rng: &mut C, suri: &str, algo: CryptoAlgo) -> Option<Self> {
+        let secret = suri::Suri::parse(suri)?.secret(rng, algo)?;
+
+        Self::from_bytes(&secret, algo)
+    }
+
     pub fn public_bytes(&self) -> &[u8] {
         match self {
             Self::Sr25519(kp) => kp.public(),
@@ -108,6 +122,23 @@ impl Keypair {
         }
     }
 
+    /// Retrieve the secret in a format accepted by `from_bytes`
+    pub fn secret_bytes(&self) -> Zeroizing<Vec<u8>> {
+        match self {
+            Self::Sr25519(kp) => kp.secret(),
+            Self::Ed25519(kp) => kp.secret(),
+            Self::Ecdsa(kp) => kp.secret(),
+        }
+    }
+
+    pub fn algo(&self) -> CryptoAlgo {
+        match self {
+            Self::Sr25519(_) => CryptoAlgo::Sr25519,
+            Self::Ed25519(_) => CryptoAlgo::Ed25519,
+            Self::Ecdsa(_) => CryptoAlgo::Ecdsa,
+        }
+    }
+
     pub fn sign<C: CSPRNG>(&self, rng: &mut C, msg: &[u8]) -> Vec<u8> {
         match self {
             Self::Sr25519(kp) => kp.sign(rng, msg).to_vec(),
@@ -127,6 +158,7 @@ impl Keypair {
         }
     }
 
+    #[cfg(test)]
     pub fn to_public_key(&self) -> PublicKey {
         self.into()
     }
@@ -134,15 +166,16 @@ impl Keypair {
 
 impl std::cmp::PartialEq<CryptoAlgo> for Keypair {
     fn eq(&self, other: &CryptoAlgo) -> bool {
-        match (self, other) {
-            (Self::Sr25519(_), CryptoAlgo::Sr25519) => true,
-            (Self::Ed25519(_), CryptoAlgo::Ed25519) => true,
-            (Self::Ecdsa(_), CryptoAlgo::Ecdsa) => true,
-            _ => false,
-        }
+        matches!(
+            (self, other),
+            (Self::Sr25519(_), CryptoAlgo::Sr25519)
+                | (Self::Ed25519(_), CryptoAlgo::Ed25519)
+                | (Self::Ecdsa(_), CryptoAlgo::Ecdsa)
+        )
     }
 }
 
+#[cfg(test)]
 #[derive(Debug)]
 pub enum PublicKey {
     Sr25519(sr25519::PublicKey),
@@ -150,6 +183,7 @@ pub enum PublicKey {
     Ecdsa(ecdsa::PublicKey),
 }
 
+#[cfg(test)]
 impl PublicKey {
     pub fn from_bytes(algo: CryptoAlgo, key: &[u8]) -> Result<Self, ()> {
         match algo {
@@ -193,6 +227,7 @@ impl PublicKey {
     }
 }
 
+#[cfg(test)]
 impl From<&Keypair> for PublicKey {
     fn from(kp: &Keypair) -> Self {
         match kp {
@@ -0,0 +1,80 @@
+//! Minimal BIP39 support, compatible with `substrate-bip39`
+//!
+//! Only the english wordlist is supported
+
+use crate::Vec;
+
+use hmac::Hmac;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroize;
+
+const WORDLIST: &str = include_str!("bip39/english.txt");
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+/// Retrieve the entropy encoded in the mnemonic phrase
+///
+/// Returns `None` if the phrase is not a valid mnemonic
+pub fn phrase_to_entropy(phrase: &str) -> Option<Vec<u8>> {
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+
+    match words.len() {
+        12 | 15 | 18 | 21 | 24 => {}
+        _ => return None,
+    }
+
+    //each word encodes 11 bits, the last ones are the checksum
+    let total_bits = words.len() * 11;
+    let checksum_bits = total_bits / 33;
+    let entropy_bits = total_bits - checksum_bits;
+
+    let mut bits = crate::vec![0u8; total_bits.div_ceil(8)];
+    for (i, word) in words.into_iter().enumerate() {
+        let index = WORDLIST.lines().position(|w| w == word)?;
+
+        for bit in 0..11 {
+            if index & (1 << (10 - bit)) != 0 {
+                let pos = i * 11 + bit;
+                bits[pos / 8] |= 1 << (7 - pos % 8);
+            }
+        }
+    }
+
+    let entropy = bits[..entropy_bits / 8].to_vec();
+
+    //the checksum is the first bits of the sha256 of the entropy
+    let hash = Sha256::digest(&entropy);
+    let checksum = bits[entropy_bits / 8] >> (8 - checksum_bits);
+    if hash[0] >> (8 - checksum_bits) != checksum {
+        return None;
+    }
+
+    Some(entropy)
+}
+
+/// Derive the mini secret key from the mnemonic entropy, as `substrate_bip39::mini_secret_from_entropy`
+pub fn seed_from_entropy(entropy: &[u8], password: &str) -> [u8; 32] {
+    let salt = [b"mnemonic", password.as_bytes()].concat();
+
+    let mut seed = [0; 64];
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(entropy, &salt, PBKDF2_ROUNDS, &mut seed);
+
+    let mut out = [0; 32];
+    out.copy_from_slice(&seed[..32]);
+    seed.zeroize();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_checksum() {
+        //last word changed from `walk`
+        let phrase = "bottom drive obey lake curtain smoke basket hold race lonely fit wall";
+
+        assert!(phrase_to_entropy(phrase).is_none());
+    }
+}
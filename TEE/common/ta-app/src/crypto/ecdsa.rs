@@ -1,28 +1,19 @@
+use k256::ecdsa::{recoverable::Signature, signature::DigestSigner, SigningKey};
+#[cfg(test)]
 use k256::{
-    ecdsa::{
-        recoverable::Signature,
-        signature::{DigestSigner, DigestVerifier},
-        Error, SigningKey, VerifyingKey,
-    },
+    ecdsa::{signature::DigestVerifier, Error, VerifyingKey},
     EncodedPoint,
 };
 
 use crate::util::CSPRNG;
+use zeroize::{Zeroize, Zeroizing};
 
+/// The secret is zeroized on drop by `k256`
 pub struct Keypair {
     secret: SigningKey,
     public: [u8; 33],
 }
 
-impl Clone for Keypair {
-    fn clone(&self) -> Self {
-        let secret = self.secret.to_bytes();
-        let secret = SigningKey::from_bytes(&secret).unwrap();
-
-        secret.into()
-    }
-}
-
 impl Keypair {
     pub fn generate<C: CSPRNG>(rng: &mut C) -> Self {
         let mut seed: [u8; 32] = Default::default();
@@ -30,6 +21,7 @@ impl Keypair {
 
         //not gonna error since length is ok
         let secret = SigningKey::from_bytes(&seed).unwrap();
+        seed.zeroize();
 
         secret.into()
     }
@@ -44,6 +36,14 @@ impl Keypair {
         &self.public
     }
 
+    pub fn secret(&self) -> Zeroizing<crate::Vec<u8>> {
+        let mut secret = self.secret.to_bytes();
+        let out = Zeroizing::new(secret.to_vec());
+        secret[..].zeroize();
+
+        out
+    }
+
     fn prehash_message(msg: &[u8]) -> blake2::Blake2s {
         use blake2::{Blake2s, Digest};
         let mut blake2 = Blake2s::new();
@@ -64,9 +64,11 @@ impl Keypair {
     }
 }
 
+#[cfg(test)]
 #[derive(Debug)]
 pub struct PublicKey(VerifyingKey);
 
+#[cfg(test)]
 impl PublicKey {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
         let point = EncodedPoint::from_bytes(bytes).map_err(|_| Error::new())?;
@@ -88,6 +90,7 @@ impl PublicKey {
     }
 }
 
+#[cfg(test)]
 impl From<&Keypair> for PublicKey {
     fn from(pair: &Keypair) -> Self {
         Self(pair.secret.verify_key())
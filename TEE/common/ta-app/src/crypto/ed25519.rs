@@ -1,18 +1,13 @@
-use ed25519_dalek::{SecretKey, Signature, SignatureError, SECRET_KEY_LENGTH};
+use ed25519_dalek::{SecretKey, SECRET_KEY_LENGTH};
+#[cfg(test)]
+use ed25519_dalek::{Signature, SignatureError};
 
 use crate::util::CSPRNG;
+use zeroize::{Zeroize, Zeroizing};
 
+/// The secret is zeroized on drop by `ed25519_dalek`
 pub struct Keypair(ed25519_dalek::Keypair);
 
-impl Clone for Keypair {
-    fn clone(&self) -> Self {
-        let secret = self.0.secret.as_bytes();
-        let secret = SecretKey::from_bytes(&secret[..]).unwrap();
-
-        secret.into()
-    }
-}
-
 impl Keypair {
     pub fn generate<C: CSPRNG>(rng: &mut C) -> Self {
         let mut seed: [u8; SECRET_KEY_LENGTH] = Default::default();
@@ -20,6 +15,7 @@ impl Keypair {
 
         //not gonna error since length is ok
         let secret = SecretKey::from_bytes(&seed).unwrap();
+        seed.zeroize();
 
         secret.into()
     }
@@ -34,15 +30,21 @@ impl Keypair {
         self.0.public.as_ref()
     }
 
+    pub fn secret(&self) -> Zeroizing<crate::Vec<u8>> {
+        Zeroizing::new(self.0.secret.as_bytes().to_vec())
+    }
+
     pub fn sign(&self, msg: &[u8]) -> [u8; 64] {
         use ed25519_dalek::Signer;
         self.0.sign(msg).to_bytes()
     }
 }
 
+#[cfg(test)]
 #[derive(Debug)]
 pub struct PublicKey(ed25519_dalek::PublicKey);
 
+#[cfg(test)]
 impl PublicKey {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, SignatureError> {
         ed25519_dalek::PublicKey::from_bytes(bytes).map(Self)
@@ -56,6 +58,7 @@ impl PublicKey {
     }
 }
 
+#[cfg(test)]
 impl From<&Keypair> for PublicKey {
     fn from(pair: &Keypair) -> Self {
         Self(pair.0.public)
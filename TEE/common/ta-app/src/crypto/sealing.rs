@@ -0,0 +1,236 @@
+//! Sealing of keys, so they can be exported from a TA instance and imported in another one
+//!
+//! The sealing key is derived from a secret provisioned at runtime (`CommandId::ProvisionSealing`)
+//! and kept in the secure storage of the TA, so only TAs provisioned with the same secret
+//! can open each other's blobs. Nothing in the TA binary helps opening them.
+//! There's no fallback: until the secret is provisioned, keys can't be sealed nor unsealed
+
+use core::convert::TryFrom;
+
+use crate::storage::StoredKey;
+use crate::util::CSPRNG;
+use crate::Vec;
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac::{Hmac, Mac, NewMac};
+use optee_common::{DeserializeOwned, Serialize, SerializeFixed};
+use sha2::Sha256;
+use ta_common::Object;
+use zeroize::{Zeroize, Zeroizing};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+pub const SECRET_LEN: usize = 32;
+
+//used to distinguish the secret from other objects in the same storage
+const TAG: u8 = b'S';
+
+/// Storage ID of the secret, there's only one
+pub type SecretId = [u8; 1];
+pub const SECRET_ID: SecretId = [TAG];
+
+/// Size of the blob sealing `n` keys
+pub fn sealed_len(n: usize) -> usize {
+    NONCE_LEN + n * StoredKey::len() + TAG_LEN
+}
+
+/// Secret the sealing key is derived from, zeroized on drop
+pub struct SealingSecret(Zeroizing<[u8; SECRET_LEN]>);
+
+impl SealingSecret {
+    /// `None` unless the secret is 32 bytes long
+    pub fn new(secret: &[u8]) -> Option<Self> {
+        let secret = <&[u8; SECRET_LEN]>::try_from(secret).ok()?;
+
+        Some(Self(Zeroizing::new(*secret)))
+    }
+
+    /// Whether the secrets are the same, in constant time
+    pub fn matches(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+
+    /// Derive the key sealing the blobs
+    pub fn sealing_key(&self) -> SealingKey {
+        let mut mac = Hmac::<Sha256>::new_varkey(&*self.0).unwrap();
+        mac.update(b"zondee sealing key");
+        let mut derived = mac.finalize().into_bytes();
+
+        let mut key = Zeroizing::new([0; 32]);
+        key.copy_from_slice(&derived);
+        derived[..].zeroize();
+
+        SealingKey(key)
+    }
+}
+
+impl SerializeFixed for SealingSecret {
+    type ErrorFixed = usize;
+
+    fn len() -> usize {
+        //tag + secret
+        1 + SECRET_LEN
+    }
+
+    fn serialize_fixed(&self, dest: &mut [u8]) -> Result<(), Self::ErrorFixed> {
+        if dest.len() < Self::len() {
+            return Err(Self::len());
+        }
+
+        dest[0] = TAG;
+        dest[1..Self::len()].copy_from_slice(&*self.0);
+
+        Ok(())
+    }
+}
+
+impl DeserializeOwned for SealingSecret {
+    type ErrorOwned = usize;
+
+    fn deserialize_owned(input: &[u8]) -> Result<Self, Self::ErrorOwned> {
+        if input.len() < Self::len() || input[0] != TAG {
+            return Err(Self::len());
+        }
+
+        Self::new(&input[1..Self::len()]).ok_or(Self::len())
+    }
+}
+
+/// The returned buffer holds the secret, so it should be zeroized once used
+impl Serialize for SealingSecret {
+    type Error = usize;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut v = crate::vec![0; Self::len()];
+        self.serialize_fixed(&mut v)?;
+
+        Ok(v)
+    }
+}
+
+impl Object for SealingSecret {
+    type ID = SecretId;
+
+    fn id(&self) -> Self::ID {
+        SECRET_ID
+    }
+}
+
+/// Key used to seal the blobs, zeroized on drop
+pub struct SealingKey(Zeroizing<[u8; 32]>);
+
+impl SealingKey {
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(<&Key>::from(&*self.0))
+    }
+}
+
+/// Encrypt and authenticate the given keys
+pub fn seal<C: CSPRNG>(sealing: &SealingKey, rng: &mut C, keys: &[StoredKey]) -> Vec<u8> {
+    let mut plaintext = Zeroizing::new(crate::vec![0; keys.len() * StoredKey::len()]);
+    for (key, dest) in keys
+        .iter()
+        .zip(plaintext.chunks_exact_mut(StoredKey::len()))
+    {
+        //not gonna error since the size is right
+        key.serialize_fixed(dest).unwrap();
+    }
+
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let ciphertext = sealing
+        .cipher()
+        .encrypt(<&Nonce>::from(&nonce), plaintext.as_slice())
+        .expect("buffer is big enough");
+
+    [&nonce[..], &ciphertext[..]].concat()
+}
+
+/// Authenticate and decrypt the keys in the blob
+///
+/// Returns `None` if the blob was not sealed by [`seal`] with the same sealing key
+pub fn unseal(sealing: &SealingKey, blob: &[u8]) -> Option<Vec<StoredKey>> {
+    if blob.len() < sealed_len(0) {
+        return None;
+    }
+    let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+    let nonce = <&[u8; NONCE_LEN]>::try_from(nonce).ok()?;
+
+    let plaintext = sealing
+        .cipher()
+        .decrypt(<&Nonce>::from(nonce), ciphertext)
+        .map(Zeroizing::new)
+        .ok()?;
+
+    if plaintext.len() % StoredKey::len() != 0 {
+        return None;
+    }
+
+    plaintext
+        .chunks_exact(StoredKey::len())
+        .map(|key| StoredKey::deserialize_owned(key).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Keypair;
+    use optee_common::CryptoAlgo;
+
+    const SECRET: [u8; SECRET_LEN] = [0x4e; SECRET_LEN];
+
+    fn sealing_key(secret: &[u8]) -> SealingKey {
+        SealingSecret::new(secret).unwrap().sealing_key()
+    }
+
+    #[test]
+    fn tampered_blob() {
+        let sealing = sealing_key(&SECRET);
+        let keypair = Keypair::generate_new(&mut rand::thread_rng(), CryptoAlgo::Ed25519);
+        let key = StoredKey::new(*b"dumm", &keypair);
+
+        let mut blob = seal(&sealing, &mut rand::thread_rng(), &[key]);
+        assert_eq!(blob.len(), sealed_len(1));
+        assert!(unseal(&sealing, &blob).is_some());
+
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+        assert!(unseal(&sealing, &blob).is_none());
+    }
+
+    #[test]
+    fn other_secret() {
+        let keypair = Keypair::generate_new(&mut rand::thread_rng(), CryptoAlgo::Ed25519);
+        let key = StoredKey::new(*b"dumm", &keypair);
+
+        let blob = seal(&sealing_key(&SECRET), &mut rand::thread_rng(), &[key]);
+
+        let mut other = SECRET;
+        other[0] = 0;
+        assert!(unseal(&sealing_key(&other), &blob).is_none());
+    }
+
+    #[test]
+    fn secret_roundtrip() {
+        assert!(SealingSecret::new(&SECRET[1..]).is_none());
+        assert!(SealingSecret::new(&[]).is_none());
+
+        let secret = SealingSecret::new(&SECRET).unwrap();
+        let restored =
+            SealingSecret::deserialize_owned(&secret.serialize().unwrap()).expect("valid secret");
+        assert!(restored.matches(&secret));
+
+        let mut other = SECRET;
+        other[31] = 0;
+        assert!(!SealingSecret::new(&other).unwrap().matches(&secret));
+    }
+}
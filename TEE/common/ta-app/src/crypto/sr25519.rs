@@ -1,12 +1,14 @@
+use schnorrkel::keys;
+#[cfg(test)]
 use schnorrkel::{
-    keys,
     vrf::{VRFPreOut, VRFProof},
     Signature, SignatureError,
 };
 
 use crate::util::CSPRNG;
+use zeroize::Zeroizing;
 
-#[derive(Clone)]
+/// The secret is zeroized on drop by `schnorrkel`
 pub struct Keypair(keys::Keypair);
 
 impl Keypair {
@@ -30,6 +32,10 @@ impl Keypair {
         self.0.public.as_ref()
     }
 
+    pub fn secret(&self) -> Zeroizing<crate::Vec<u8>> {
+        Zeroizing::new(self.0.secret.to_bytes().to_vec())
+    }
+
     fn get_transcript(msg: &[u8]) -> merlin::Transcript {
         let mut t = merlin::Transcript::new(b"SigningContext");
         t.append_message(b"", b"substrate"); //ctx
@@ -67,9 +73,11 @@ impl Keypair {
     }
 }
 
+#[cfg(test)]
 #[derive(Debug)]
 pub struct PublicKey(keys::PublicKey);
 
+#[cfg(test)]
 impl PublicKey {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, SignatureError> {
         keys::PublicKey::from_bytes(bytes).map(Self)
@@ -116,6 +124,7 @@ impl PublicKey {
     }
 }
 
+#[cfg(test)]
 impl From<&Keypair> for PublicKey {
     fn from(pair: &Keypair) -> Self {
         Self(pair.0.public)
@@ -141,6 +150,20 @@ mod vrf {
         items: crate::Vec<(&'de [u8], VRFValue<'de>)>,
     }
 
+    impl<'de> VRFData<'de> {
+        pub fn label(&self) -> &'de [u8] {
+            self.label
+        }
+
+        /// Retrieve the value of the first u64 item with the given label
+        pub fn get_u64(&self, label: &[u8]) -> Option<u64> {
+            self.items.iter().find_map(|(l, value)| match value {
+                VRFValue::U64(val) if *l == label => Some(*val),
+                _ => None,
+            })
+        }
+    }
+
     pub fn make_transcript(data: VRFData<'_>) -> Transcript {
         let mut transcript = Transcript::new(*StaticGuard::from_slice(data.label));
 
@@ -148,7 +171,7 @@ mod vrf {
             let label = StaticGuard::from_slice(label);
             match value {
                 VRFValue::Bytes(bytes) => {
-                    transcript.append_message(*label, &bytes);
+                    transcript.append_message(*label, bytes);
                 }
                 VRFValue::U64(val) => {
                     transcript.append_u64(*label, val);
@@ -170,7 +193,7 @@ mod vrf {
     impl<T: ?Sized> Drop for StaticGuard<T> {
         fn drop(&mut self) {
             unsafe {
-                crate::Box::from_raw(self.0 as *const T as *mut T);
+                drop(crate::Box::from_raw(self.0 as *const T as *mut T));
             }
         }
     }
@@ -190,6 +213,8 @@ mod vrf {
         }
     }
 
+    //the fields are only there to be printed
+    #[allow(dead_code)]
     #[derive(Debug, Clone)]
     pub enum VRFError {
         Length(usize),
@@ -214,12 +239,12 @@ mod vrf {
         type Error = VRFError;
 
         fn deserialize(input: &'de [u8]) -> Result<Self, Self::Error> {
-            let variant = input.get(0).cloned().ok_or(VRFError::Length(1))?;
+            let variant = input.first().cloned().ok_or(VRFError::Length(1))?;
 
             match variant {
                 0 => {
                     let bytes: &[u8] =
-                        Deserialize::deserialize(&input[1..]).map_err(|e| VRFError::Length(e))?;
+                        Deserialize::deserialize(&input[1..]).map_err(VRFError::Length)?;
                     Ok(Self::Bytes(bytes))
                 }
                 1 => {
@@ -0,0 +1,242 @@
+//! Parsing of secret URIs, compatible with `sp_core::crypto::Pair::from_string`
+//!
+//! A SURI has the form `<phrase or 0x seed>//hard/soft///password`, where every part is optional
+
+use crate::Vec;
+
+use blake2::{
+    digest::{Update, VariableOutput},
+    VarBlake2b,
+};
+use optee_common::CryptoAlgo;
+use schnorrkel::{
+    derive::{ChainCode, Derivation},
+    keys::{ExpansionMode, MiniSecretKey},
+};
+
+use super::bip39;
+use crate::util::CSPRNG;
+use zeroize::Zeroizing;
+
+/// Phrase used when the SURI doesn't specify one, same as `sp_core::crypto::DEV_PHRASE`
+pub const DEV_PHRASE: &str =
+    "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+
+const JUNCTION_ID_LEN: usize = 32;
+
+/// A single step of a derivation path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Junction {
+    Soft([u8; JUNCTION_ID_LEN]),
+    Hard([u8; JUNCTION_ID_LEN]),
+}
+
+impl Junction {
+    /// Parse the junction code, as done by `sp_core::crypto::DeriveJunction`
+    fn new(code: &str, hard: bool) -> Self {
+        let mut encoded = Vec::new();
+        match code.parse::<u64>() {
+            Ok(n) => encoded.extend_from_slice(&n.to_le_bytes()),
+            //SCALE encoded string
+            Err(_) => {
+                compact_len(code.len(), &mut encoded);
+                encoded.extend_from_slice(code.as_bytes());
+            }
+        }
+
+        let mut cc = [0; JUNCTION_ID_LEN];
+        if encoded.len() > JUNCTION_ID_LEN {
+            cc = blake2_256(&encoded);
+        } else {
+            cc[..encoded.len()].copy_from_slice(&encoded);
+        }
+
+        if hard {
+            Self::Hard(cc)
+        } else {
+            Self::Soft(cc)
+        }
+    }
+}
+
+/// A parsed secret URI
+#[derive(Debug)]
+pub struct Suri<'s> {
+    phrase: &'s str,
+    path: Vec<Junction>,
+    password: Option<&'s str>,
+}
+
+impl<'s> Suri<'s> {
+    pub fn parse(suri: &'s str) -> Option<Self> {
+        //the password is everything after the first `///`
+        let (rest, password) = match suri.find("///") {
+            Some(idx) => (&suri[..idx], Some(&suri[idx + 3..])),
+            None => (suri, None),
+        };
+
+        let (phrase, mut path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+
+        let phrase = match phrase {
+            "" => DEV_PHRASE,
+            phrase
+                if phrase
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == ' ') =>
+            {
+                phrase
+            }
+            _ => return None,
+        };
+
+        let mut junctions = Vec::new();
+        while !path.is_empty() {
+            //always starts with `/` here
+            path = &path[1..];
+
+            let hard = path.starts_with('/');
+            if hard {
+                path = &path[1..];
+            }
+
+            let end = path.find('/').unwrap_or(path.len());
+            let (code, rest) = path.split_at(end);
+            if code.is_empty() {
+                return None;
+            }
+
+            junctions.push(Junction::new(code, hard));
+            path = rest;
+        }
+
+        Some(Self {
+            phrase,
+            path: junctions,
+            password,
+        })
+    }
+
+    /// Retrieve the seed of the root key
+    fn seed(&self) -> Option<[u8; 32]> {
+        if let Some(hex) = self.phrase.strip_prefix("0x") {
+            let mut seed = [0; 32];
+            hex::decode_to_slice(hex, &mut seed).ok()?;
+
+            return Some(seed);
+        }
+
+        let entropy = Zeroizing::new(bip39::phrase_to_entropy(self.phrase)?);
+        Some(bip39::seed_from_entropy(
+            &entropy,
+            self.password.unwrap_or(""),
+        ))
+    }
+
+    /// Derive the secret key for the given algorithm, in a format accepted by `Keypair::from_bytes`
+    ///
+    /// Returns `None` if the SURI is invalid or the path can't be used with the algorithm
+    pub fn secret<C: CSPRNG>(&self, rng: &mut C, algo: CryptoAlgo) -> Option<Zeroizing<Vec<u8>>> {
+        let seed = Zeroizing::new(self.seed()?);
+
+        match algo {
+            CryptoAlgo::Sr25519 => {
+                let mut secret = MiniSecretKey::from_bytes(&seed[..])
+                    .ok()?
+                    .expand(ExpansionMode::Ed25519);
+
+                for junction in self.path.iter() {
+                    secret = match junction {
+                        Junction::Soft(cc) => {
+                            secret
+                                .derived_key_simple_rng(ChainCode(*cc), b"", &mut *rng)
+                                .0
+                        }
+                        Junction::Hard(cc) => secret
+                            .hard_derive_mini_secret_key(Some(ChainCode(*cc)), b"")
+                            .0
+                            .expand(ExpansionMode::Ed25519),
+                    };
+                }
+
+                Some(Zeroizing::new(secret.to_bytes().to_vec()))
+            }
+            CryptoAlgo::Ed25519 => derive_hard_only(*seed, &self.path, "Ed25519HDKD"),
+            CryptoAlgo::Ecdsa => derive_hard_only(*seed, &self.path, "Secp256k1HDKD"),
+        }
+    }
+}
+
+/// Derivation for the algorithms that only support hard junctions
+fn derive_hard_only(seed: [u8; 32], path: &[Junction], id: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let mut seed = Zeroizing::new(seed);
+
+    for junction in path {
+        let cc = match junction {
+            Junction::Hard(cc) => cc,
+            Junction::Soft(_) => return None,
+        };
+
+        //SCALE encoded (id, seed, cc)
+        let mut encoded = Zeroizing::new(Vec::new());
+        compact_len(id.len(), &mut encoded);
+        encoded.extend_from_slice(id.as_bytes());
+        encoded.extend_from_slice(&seed[..]);
+        encoded.extend_from_slice(cc);
+
+        *seed = blake2_256(&encoded);
+    }
+
+    Some(Zeroizing::new(seed.to_vec()))
+}
+
+fn blake2_256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = VarBlake2b::new(32).unwrap();
+    hasher.update(data);
+
+    let mut out = [0; 32];
+    hasher.finalize_variable(|hash| out.copy_from_slice(hash));
+    out
+}
+
+/// SCALE compact encoding of a length
+fn compact_len(len: usize, out: &mut Vec<u8>) {
+    let len = len as u64;
+
+    match len {
+        0..=0x3f => out.push((len as u8) << 2),
+        0x40..=0x3fff => out.extend_from_slice(&((len as u16) << 2 | 0b01).to_le_bytes()),
+        0x4000..=0x3fff_ffff => out.extend_from_slice(&((len as u32) << 2 | 0b10).to_le_bytes()),
+        _ => {
+            let bytes = 8 - len.leading_zeros() as usize / 8;
+            out.push(((bytes - 4) as u8) << 2 | 0b11);
+            out.extend_from_slice(&len.to_le_bytes()[..bytes]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_path() {
+        let suri = Suri::parse("//Alice/1///pass").expect("valid suri");
+
+        assert_eq!(suri.phrase, DEV_PHRASE);
+        assert_eq!(suri.password, Some("pass"));
+        assert_eq!(
+            suri.path,
+            [Junction::new("Alice", true), Junction::new("1", false)]
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Suri::parse("//").is_none());
+        assert!(Suri::parse("not a phrase!").is_none());
+        assert!(Suri::parse("0xnothex").unwrap().seed().is_none());
+    }
+}
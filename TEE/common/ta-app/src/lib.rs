@@ -4,10 +4,11 @@ extern crate no_std_compat as std;
 use std::prelude::v1::*;
 
 use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
 
 use optee_common::{
-    CommandId, CryptoAlgo, Deserialize, DeserializeOwned, DeserializeVariable, HandleTaCommand,
-    HasKeysPair, Serialize, SerializeFixed, TeeErrorCode as Error,
+    CommandId, CryptoAlgo, CryptoPublicPair, Deserialize, DeserializeOwned, DeserializeVariable,
+    HandleTaCommand, HasKeysPair, MessagePolicy, Serialize, SerializeFixed, TeeErrorCode as Error,
 };
 use rand_core::{CryptoRng, RngCore};
 
@@ -19,28 +20,101 @@ use util::CSPRNG;
 mod crypto;
 use crypto::Keypair;
 
+mod storage;
+use storage::KeyStorage;
+
+mod policy;
+mod slashing;
+use ta_common::{Clock, Storage};
+
 #[macro_use]
 extern crate log;
 
 pub struct TaApp<'r> {
     keys: HashMap<[u8; 4], Vec<Keypair>, util::hasher::Builder>,
-    rng: &'r mut dyn CSPRNG, //the rng provider
+    rng: &'r mut dyn CSPRNG,         //the rng provider
+    storage: &'r mut dyn KeyStorage, //where keys are persisted
+    clock: Option<&'r dyn Clock>,    //needed for rate limited policies
+    policies: policy::Policies,
+    slashing: Option<slashing::Protection>,
+    sealing: Option<crypto::sealing::SealingKey>, //keys can't be exported without it
+    required_output: Option<usize>, //set when the output of the last command didn't fit
 }
 
 // This is safe because all request are serialized by the TA framework
 unsafe impl<'r> Sync for TaApp<'r> {}
 
-type InnerHandler<T> = RefCell<Option<T>>;
+type InnerHandler<T> = RefCell<T>;
 
 /// Main TA request handler which wrapps any type that implements the HandleTaCommand Trait
 struct TaHandler<T>(InnerHandler<T>);
 
 // This is safe because the ta framework serializes all of the incoming requests so that only one is
 // processed at time
-unsafe impl<T: HandleTaCommand + Sync> Sync for TaHandler<T> {}
+unsafe impl<T> Sync for TaHandler<T> {}
 
 // The privite handler for processing client commands
-static TA_HANDLER: TaHandler<TaApp<'static>> = TaHandler(RefCell::new(None));
+static TA_HANDLER: TaHandler<Sessions<TaApp<'static>>> = TaHandler(RefCell::new(Sessions::new()));
+
+/// Identifies an open session, never 0
+pub type SessionId = usize;
+
+/// Handler of a single session, all the sessions share the same app (and so the same keys)
+pub struct Session<T> {
+    id: SessionId,
+    app: Rc<RefCell<T>>,
+}
+
+impl<T: HandleTaCommand> HandleTaCommand for Session<T> {
+    fn process_command(
+        &mut self,
+        cmd_id: CommandId,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        trace!("Session {} processing CMD {:?}", self.id, cmd_id);
+        self.app.borrow_mut().process_command(cmd_id, input, output)
+    }
+
+    fn required_output_size(&self) -> Option<usize> {
+        self.app.borrow().required_output_size()
+    }
+}
+
+/// The app shared by all the sessions, together with the open sessions
+struct Sessions<T> {
+    app: Option<Rc<RefCell<T>>>,
+    open: Vec<Session<T>>,
+    last_id: SessionId,
+}
+
+impl<T> Sessions<T> {
+    const fn new() -> Self {
+        Self {
+            app: None,
+            open: Vec::new(),
+            last_id: 0,
+        }
+    }
+
+    fn open(&mut self) -> Option<SessionId> {
+        let app = self.app.clone()?;
+
+        self.last_id = self.last_id.checked_add(1)?;
+        let id = self.last_id;
+        self.open.push(Session { id, app });
+
+        Some(id)
+    }
+
+    fn close(&mut self, id: SessionId) {
+        self.open.retain(|session| session.id != id);
+    }
+
+    fn position(&self, id: SessionId) -> Option<usize> {
+        self.open.iter().position(|session| session.id == id)
+    }
+}
 
 impl<'r> HandleTaCommand for TaApp<'r> {
     fn process_command(
@@ -50,6 +124,7 @@ impl<'r> HandleTaCommand for TaApp<'r> {
         output: &mut [u8],
     ) -> Result<(), Error> {
         trace!("Processing CMD {:?}", cmd_id);
+        self.required_output = None;
 
         match cmd_id {
             CommandId::GenerateNew => {
@@ -59,25 +134,46 @@ impl<'r> HandleTaCommand for TaApp<'r> {
 
                 let key_type: [u8; 4] =
                     DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
                 trace!("GenerateNew: read key_type: {:x?}", key_type);
 
-                //generate keypair
-                let keypair = Keypair::generate_new(&mut self.rng, algo);
+                //the seed is optional
+                let seed: Option<&str> = if input.is_empty() {
+                    None
+                } else {
+                    Some(Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?)
+                };
+                trace!("GenerateNew: seed present: {}", seed.is_some());
 
-                let public = keypair.public_bytes();
+                //generate keypair
+                let keypair = match seed {
+                    Some(seed) => {
+                        Keypair::from_suri(&mut self.rng, seed, algo).ok_or(Error::BadParameters)?
+                    }
+                    None => Keypair::generate_new(&mut self.rng, algo),
+                };
+
+                let public = keypair.public_bytes().to_vec();
                 trace!("generated keypair");
 
-                //copy into output
+                //check space before storing the key
                 if public.len() > output.len() {
                     return Err(Error::OutOfMemory);
                 }
-                output[..public.len()].copy_from_slice(public);
-                trace!("written public key = {:x?}", &public[..]);
 
                 //insert into own store
-                self.keys.entry(key_type).or_default().push(keypair);
+                if seed.is_none() {
+                    self.insert_key(key_type, keypair)?;
+                } else if self.find_associated_key(&key_type, &public).is_none() {
+                    //same as substrate, keys from a seed are only kept in memory
+                    self.keys.entry(key_type).or_default().push(keypair);
+                }
                 trace!("inserted keypair into own store");
 
+                //copy into output
+                output[..public.len()].copy_from_slice(&public);
+                trace!("written public key = {:x?}", &public[..]);
+
                 Ok(())
             }
             CommandId::GetKeys => {
@@ -109,7 +205,8 @@ impl<'r> HandleTaCommand for TaApp<'r> {
 
                 trace!("keys serialized");
                 if output.len() < keys.len() {
-                    return Err(Error::OutOfMemory);
+                    //let the caller know how big the buffer should be
+                    return Err(self.short_buffer(keys.len()));
                 }
 
                 output[..keys.len()].copy_from_slice(&keys);
@@ -140,12 +237,20 @@ impl<'r> HandleTaCommand for TaApp<'r> {
                 let msg: &[u8] = Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
                 trace!("read msg");
 
-                let pair = self
-                    .find_associated_key(&key_type, public)
-                    .ok_or(Error::BadParameters)?;
+                let pair =
+                    Self::find_key(&self.keys, &key_type, public).ok_or(Error::PairNotFound)?;
                 trace!("got keypair");
 
-                let sig = pair.sign(&mut self.rng, &msg);
+                self.policies
+                    .check(self.clock, key_type, pair.algo(), msg)?;
+
+                if let Some(protection) = self.slashing.as_mut() {
+                    protection.check_sign(&mut *self.storage, key_type, public, msg)?;
+                }
+
+                //only signatures actually made count for the rate limit
+                self.policies.count(self.clock, key_type, pair.algo());
+                let sig = pair.sign(&mut self.rng, msg);
                 trace!("signed!");
 
                 if sig.len() > output.len() {
@@ -159,7 +264,7 @@ impl<'r> HandleTaCommand for TaApp<'r> {
             }
             CommandId::HasKeys => {
                 //check if we have 1 byte available for the bool output
-                if output.len() < 1 {
+                if output.is_empty() {
                     return Err(Error::OutOfMemory);
                 }
 
@@ -198,22 +303,25 @@ impl<'r> HandleTaCommand for TaApp<'r> {
                 util::advance_slice(&mut input, 32).unwrap();
                 trace!("got public key = {:x?}", &public[..]);
 
-                self.iter_all_pkeys().for_each(|(key_type, pkey)| {
-                    trace!("{:?} => {:x?}", key_type, pkey)
+                self.iter_all_pkeys().for_each(|(key_type, algo, pkey)| {
+                    trace!("{:?} => {:?} {:x?}", key_type, algo, pkey)
                 });
 
-                let pair = self
-                    .find_associated_key(&key_type, &public)
-                    .ok_or(Error::BadParameters)?;
+                let pair =
+                    Self::find_key(&self.keys, &key_type, &public).ok_or(Error::PairNotFound)?;
                 trace!("found keypair");
 
                 let data: crypto::VRFData =
                     Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
                 trace!("got vrf data");
 
+                if let Some(protection) = self.slashing.as_mut() {
+                    protection.check_vrf(&mut *self.storage, key_type, &public, &data)?;
+                }
+
                 let vrf = pair
                     .vrf_sign(&mut self.rng, data)
-                    .map_err(|_| Error::BadParameters)?;
+                    .map_err(|_| Error::KeyNotSupported)?;
                 trace!("signed vrf");
 
                 if vrf.len() > output.len() {
@@ -222,72 +330,688 @@ impl<'r> HandleTaCommand for TaApp<'r> {
                 output[..vrf.len()].copy_from_slice(&vrf);
                 trace!("written vrf");
 
+                Ok(())
+            }
+            CommandId::ImportKey => {
+                //check space for the algo of the imported key
+                if output.len() < CryptoAlgo::len() {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("ImportKey: read key_type: {:x?}", key_type);
+
+                let suri: &str = Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 8 + suri.len()).unwrap();
+                trace!("read suri");
+
+                let public: &[u8] =
+                    Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
+                trace!("read public key = {:x?}", public);
+
+                //the algo is not known, so look for the one that gives the same public key
+                let rng = &mut self.rng;
+                let keypair = [CryptoAlgo::Sr25519, CryptoAlgo::Ed25519, CryptoAlgo::Ecdsa]
+                    .iter()
+                    .filter(|algo| algo.pubkey_len() == public.len())
+                    .filter_map(|algo| Keypair::from_suri(rng, suri, *algo))
+                    .find(|keypair| keypair.public_bytes() == public)
+                    .ok_or(Error::BadParameters)?;
+                let algo = keypair.algo();
+                trace!("derived keypair; algo={:?}", algo);
+
+                //don't store the same key twice
+                if self.find_associated_key(&key_type, public).is_none() {
+                    self.insert_key(key_type, keypair)?;
+                    trace!("inserted keypair into own store");
+                }
+
+                algo.serialize_fixed(&mut output[..CryptoAlgo::len()])
+                    .unwrap();
+                trace!("written algo");
+
+                Ok(())
+            }
+            CommandId::Keys => {
+                //check space for n of keys
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                trace!("Keys: read key_type: {:x?}", key_type);
+
+                //all the keys of the key_type, regardless of the curve
+                let keys: Vec<CryptoPublicPair> = self
+                    .iter_all_pkeys()
+                    .filter(|(kt, _, _)| kt == &key_type)
+                    .map(|(_, algo, public)| CryptoPublicPair {
+                        algo,
+                        public_key: public.to_vec(),
+                    })
+                    .collect();
+                trace!("got {} keys", keys.len());
+
+                let keys = keys.serialize().unwrap();
+                if output.len() < keys.len() {
+                    return Err(self.short_buffer(keys.len()));
+                }
+
+                output[..keys.len()].copy_from_slice(&keys);
+                trace!("keys written");
+
+                Ok(())
+            }
+            CommandId::SupportedKeys => {
+                //check space for n of keys
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("SupportedKeys: read key_type: {:x?}", key_type);
+
+                let (_, pairs): (_, Vec<CryptoPublicPair>) =
+                    DeserializeVariable::deserialize_variable(input)
+                        .map_err(|_| Error::BadFormat)?;
+                trace!("read CryptoPublicPair");
+
+                //keep only the keys we have
+                let keys: Vec<CryptoPublicPair> = pairs
+                    .into_iter()
+                    .filter(|pair| {
+                        self.iter_all_pkeys().any(|(kt, algo, public)| {
+                            kt == key_type && algo == pair.algo && public == pair.public_key
+                        })
+                    })
+                    .collect();
+                trace!("supported {} keys", keys.len());
+
+                let keys = keys.serialize().unwrap();
+                if output.len() < keys.len() {
+                    return Err(self.short_buffer(keys.len()));
+                }
+
+                output[..keys.len()].copy_from_slice(&keys);
+                trace!("keys written");
+
+                Ok(())
+            }
+            CommandId::SignWithAny => {
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("SignWithAny: read key_type: {:x?}", key_type);
+
+                let (size, pairs): (_, Vec<CryptoPublicPair>) =
+                    DeserializeVariable::deserialize_variable(input)
+                        .map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, size).unwrap();
+                trace!("read CryptoPublicPair");
+
+                let msg: &[u8] = Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
+                trace!("read msg");
+
+                //check the space before signing, as any of our keys could be the one signing
+                let required = pairs
+                    .iter()
+                    .filter_map(|pair| {
+                        let sig_len = self.signature_len(&key_type, pair)?;
+                        Some(CryptoAlgo::len() + 8 + pair.public_key.len() + 8 + sig_len)
+                    })
+                    .max()
+                    .unwrap_or(0);
+                if output.len() < required {
+                    return Err(self.short_buffer(required));
+                }
+
+                //sign with the first key we have that is allowed to,
+                // reporting why the last one wasn't otherwise
+                let mut signed = Err(Error::PairNotFound);
+                for pair in pairs {
+                    match self.sign_with(&key_type, &pair, msg) {
+                        Ok(sig) => {
+                            signed = Ok((pair, sig));
+                            break;
+                        }
+                        Err(Error::PairNotFound) => {}
+                        Err(err) => signed = Err(err),
+                    }
+                }
+                let (pair, sig) = signed?;
+                trace!("signed!");
+
+                let mut out = pair.serialize().unwrap();
+                out.append(&mut (&sig.as_slice()).serialize().unwrap());
+
+                output[..out.len()].copy_from_slice(&out);
+                trace!("pair and signature written");
+
+                Ok(())
+            }
+            CommandId::SignWithAll => {
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("SignWithAll: read key_type: {:x?}", key_type);
+
+                let (size, pairs): (_, Vec<CryptoPublicPair>) =
+                    DeserializeVariable::deserialize_variable(input)
+                        .map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, size).unwrap();
+                trace!("read CryptoPublicPair");
+
+                let msg: &[u8] = Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
+                trace!("read msg");
+
+                //check the space before signing, as if all our keys were allowed to
+                let required = 8 + pairs
+                    .iter()
+                    .map(|pair| 4 + 8 + self.signature_len(&key_type, pair).unwrap_or(0))
+                    .sum::<usize>();
+                if output.len() < required {
+                    return Err(self.short_buffer(required));
+                }
+
+                //n of results, then for each key the result code and the signature
+                // (empty if the code is not 0)
+                let mut out = (pairs.len() as u64).to_le_bytes().to_vec();
+                for pair in pairs.iter() {
+                    let (code, sig) = match self.sign_with(&key_type, pair, msg) {
+                        Ok(sig) => (0, sig),
+                        Err(err) => (err as u32, Vec::new()),
+                    };
+
+                    out.extend_from_slice(&code.to_le_bytes()[..]);
+                    out.append(&mut (&sig.as_slice()).serialize().unwrap());
+                }
+                trace!("signed with all keys");
+
+                output[..out.len()].copy_from_slice(&out);
+                trace!("signatures written");
+
+                Ok(())
+            }
+            CommandId::DeleteKey => {
+                //check space for n of deleted keys
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("DeleteKey: read key_type: {:x?}", key_type);
+
+                //without a pair all the keys of the key type are deleted
+                let pair: Option<CryptoPublicPair> = if input.is_empty() {
+                    None
+                } else {
+                    Some(
+                        DeserializeVariable::deserialize_variable(input)
+                            .map_err(|_| Error::BadFormat)?
+                            .1,
+                    )
+                };
+                trace!("DeleteKey: pair present: {}", pair.is_some());
+
+                let deleted = self.remove_keys(key_type, pair.as_ref());
+                if pair.is_some() && deleted == 0 {
+                    return Err(Error::PairNotFound);
+                }
+                trace!("deleted {} keys", deleted);
+
+                output[..8].copy_from_slice(&(deleted as u64).to_le_bytes()[..]);
+                Ok(())
+            }
+            CommandId::RotateKey => {
+                //check space for n of keys
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("RotateKey: read key_type: {:x?}", key_type);
+
+                //without a pair all the keys of the key type are rotated
+                let pair: Option<CryptoPublicPair> = if input.is_empty() {
+                    None
+                } else {
+                    Some(
+                        DeserializeVariable::deserialize_variable(input)
+                            .map_err(|_| Error::BadFormat)?
+                            .1,
+                    )
+                };
+                trace!("RotateKey: pair present: {}", pair.is_some());
+
+                let algos: Vec<CryptoAlgo> = self
+                    .keys
+                    .get(&key_type)
+                    .map(|keys| {
+                        keys.iter()
+                            .filter(|keypair| Self::key_matches(keypair, pair.as_ref()))
+                            .map(Keypair::algo)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if pair.is_some() && algos.is_empty() {
+                    return Err(Error::PairNotFound);
+                }
+
+                //check the space before rotating, so the caller can safely retry
+                let required = 8 + algos
+                    .iter()
+                    .map(|algo| CryptoAlgo::len() + 8 + algo.pubkey_len())
+                    .sum::<usize>();
+                if output.len() < required {
+                    return Err(self.short_buffer(required));
+                }
+
+                //persist the new keys first, so we don't lose the old ones on failure
+                let mut new_keys = Vec::with_capacity(algos.len());
+                for algo in algos {
+                    let keypair = Keypair::generate_new(&mut self.rng, algo);
+                    if !self.storage.store_key(key_type, &keypair) {
+                        error!("unable to persist keypair");
+                        for keypair in new_keys.iter() {
+                            self.storage.delete_key(key_type, keypair);
+                        }
+                        return Err(Error::Unavailable);
+                    }
+
+                    new_keys.push(keypair);
+                }
+                trace!("generated {} new keys", new_keys.len());
+
+                self.remove_keys(key_type, pair.as_ref());
+                trace!("removed old keys");
+
+                let pairs: Vec<CryptoPublicPair> = new_keys
+                    .iter()
+                    .map(|keypair| CryptoPublicPair {
+                        algo: keypair.algo(),
+                        public_key: keypair.public_bytes().to_vec(),
+                    })
+                    .collect();
+                self.keys.entry(key_type).or_default().extend(new_keys);
+
+                let pairs = pairs.serialize().unwrap();
+                output[..pairs.len()].copy_from_slice(&pairs);
+                trace!("new keys written");
+
+                Ok(())
+            }
+            CommandId::ExportSealed => {
+                //check space for the blob length
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                //without a key the whole key set is exported
+                let key: Option<([u8; 4], CryptoPublicPair)> = if input.is_empty() {
+                    None
+                } else {
+                    let key_type: [u8; 4] =
+                        DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                    util::advance_slice(&mut input, 4).unwrap();
+
+                    let (_, pair) = DeserializeVariable::deserialize_variable(input)
+                        .map_err(|_| Error::BadFormat)?;
+                    Some((key_type, pair))
+                };
+                trace!("ExportSealed: key present: {}", key.is_some());
+
+                let keys: Vec<storage::StoredKey> = match &key {
+                    Some((key_type, pair)) => {
+                        let keypair = Self::find_key(&self.keys, key_type, &pair.public_key)
+                            .filter(|keypair| **keypair == pair.algo)
+                            .ok_or(Error::PairNotFound)?;
+
+                        vec![storage::StoredKey::new(*key_type, keypair)]
+                    }
+                    None => self
+                        .keys
+                        .iter()
+                        .flat_map(|(key_type, keys)| {
+                            keys.iter()
+                                .map(move |keypair| storage::StoredKey::new(*key_type, keypair))
+                        })
+                        .collect(),
+                };
+                trace!("got {} keys to export", keys.len());
+
+                let required = 8 + crypto::sealing::sealed_len(keys.len());
+                if output.len() < required {
+                    return Err(self.short_buffer(required));
+                }
+
+                let sealing = self.sealing.as_ref().ok_or(Error::NotSupported)?;
+                let blob = crypto::sealing::seal(sealing, &mut self.rng, &keys);
+                trace!("keys sealed");
+
+                let blob = (&blob.as_slice()).serialize().unwrap();
+                output[..blob.len()].copy_from_slice(&blob);
+                trace!("sealed blob written");
+
+                Ok(())
+            }
+            CommandId::ImportSealed => {
+                //check space for n of imported keys
+                if output.len() < 8 {
+                    return Err(Error::OutOfMemory);
+                }
+
+                let blob: &[u8] = Deserialize::deserialize(input).map_err(|_| Error::BadFormat)?;
+                trace!("ImportSealed: read blob");
+
+                let sealing = self.sealing.as_ref().ok_or(Error::NotSupported)?;
+                let keys = crypto::sealing::unseal(sealing, blob).ok_or(Error::Security)?;
+                trace!("unsealed {} keys", keys.len());
+
+                let mut imported = 0u64;
+                for key in keys {
+                    let (key_type, keypair) = key.into_keypair().ok_or(Error::BadFormat)?;
+
+                    //don't duplicate keys we already have
+                    if self
+                        .find_associated_key(&key_type, keypair.public_bytes())
+                        .is_none()
+                    {
+                        self.insert_key(key_type, keypair)?;
+                        imported += 1;
+                    }
+                }
+                trace!("imported {} keys", imported);
+
+                output[..8].copy_from_slice(&imported.to_le_bytes()[..]);
+                Ok(())
+            }
+            CommandId::SetPolicy => {
+                let key_type: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, 4).unwrap();
+                trace!("SetPolicy: read key_type: {:x?}", key_type);
+
+                let algo = CryptoAlgo::deserialize_owned(input).map_err(|_| Error::BadFormat)?;
+                util::advance_slice(&mut input, CryptoAlgo::len()).unwrap();
+                trace!("SetPolicy: read algo: {:?}", algo);
+
+                //no policy, only accepted if there is none already
+                let policy = if input.is_empty() {
+                    None
+                } else {
+                    let (_, policy) =
+                        MessagePolicy::deserialize_variable(input).map_err(|_| Error::BadFormat)?;
+                    Some(policy)
+                };
+                trace!("read policy");
+
+                self.policies
+                    .set(&mut *self.storage, key_type, algo, policy)
+            }
+            CommandId::ProvisionSealing => {
+                let secret = crypto::sealing::SealingSecret::new(input).ok_or(Error::BadFormat)?;
+                trace!("ProvisionSealing: read secret");
+
+                //provisioning the same secret again is fine, but it can't be replaced:
+                // whoever can reach the TA could then export the keys with a secret they know
+                if let Some(stored) = self.storage.load_sealing_secret() {
+                    return if stored.matches(&secret) {
+                        Ok(())
+                    } else {
+                        Err(Error::BadState)
+                    };
+                }
+
+                if !self.storage.store_sealing_secret(&secret) {
+                    error!("unable to persist sealing secret");
+                    return Err(Error::Unavailable);
+                }
+                self.sealing = Some(secret.sealing_key());
+                trace!("sealing secret provisioned");
+
                 Ok(())
             }
         }
     }
+
+    fn required_output_size(&self) -> Option<usize> {
+        self.required_output
+    }
 }
 
 impl<'r> TaApp<'r> {
-    pub fn with_rng<R: CryptoRng + RngCore + 'r>(rng: &'r mut R) -> Self {
+    /// Create a new app, loading all the keys already present in `storage`
+    pub fn new<R, S>(rng: &'r mut R, storage: &'r mut S) -> Self
+    where
+        R: CryptoRng + RngCore + 'r,
+        S: Storage + 'r,
+    {
+        let mut keys = crypto::default_set();
+
+        for (key_type, keypair) in storage.load_keys() {
+            keys.entry(key_type).or_default().push(keypair);
+        }
+        trace!("loaded keys from storage");
+
+        let policies = policy::Policies::new(storage);
+        trace!("loaded policies from storage");
+
+        let sealing = storage
+            .load_sealing_secret()
+            .map(|secret| secret.sealing_key());
+        trace!("sealing secret provisioned: {}", sealing.is_some());
+
         Self {
             rng: rng as _,
-            keys: crypto::default_set(),
+            storage: storage as _,
+            keys,
+            clock: None,
+            policies,
+            slashing: None,
+            sealing,
+            required_output: None,
         }
     }
 
-    fn find_associated_key(&self, key_type: &[u8; 4], public_key: &[u8]) -> Option<Keypair> {
-        self.keys
-            .get(key_type)
+    /// Use the clock to enforce the rate limits of the policies
+    pub fn with_clock(mut self, clock: &'r dyn Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Enable the slashing protection for the consensus key types,
+    /// loading the marks already present in the storage
+    ///
+    /// Only for chains using BABE/GRANDPA: messages of those key types that can't be decoded
+    /// are refused. The TA enables it when built with the `slashing-protection` feature
+    pub fn with_slashing_protection(mut self) -> Self {
+        self.slashing = Some(slashing::Protection::new(&mut *self.storage));
+        self
+    }
+
+    /// Signal that the output is too small to hold `required` bytes
+    ///
+    /// The required size is reported with `required_output_size`,
+    /// so the caller can retry with a big enough buffer
+    fn short_buffer(&mut self, required: usize) -> Error {
+        self.required_output = Some(required);
+        Error::ShortBuffer
+    }
+
+    /// Persist the keypair and add it to the in-memory set
+    fn insert_key(&mut self, key_type: [u8; 4], keypair: Keypair) -> Result<(), Error> {
+        if !self.storage.store_key(key_type, &keypair) {
+            error!("unable to persist keypair");
+            return Err(Error::Unavailable);
+        }
+
+        self.keys.entry(key_type).or_default().push(keypair);
+        Ok(())
+    }
+
+    fn find_associated_key(&self, key_type: &[u8; 4], public_key: &[u8]) -> Option<&Keypair> {
+        Self::find_key(&self.keys, key_type, public_key)
+    }
+
+    /// Same as `find_associated_key` but only borrows the keys,
+    /// so the rng can be used with the found keypair
+    fn find_key<'k>(
+        keys: &'k HashMap<[u8; 4], Vec<Keypair>, util::hasher::Builder>,
+        key_type: &[u8; 4],
+        public_key: &[u8],
+    ) -> Option<&'k Keypair> {
+        keys.get(key_type)
             .and_then(|keys| keys.iter().find(|k| k.public_bytes() == public_key))
-            .cloned()
     }
 
-    fn iter_all_pkeys<'s>(&'s self) -> impl Iterator<Item = ([u8; 4], &'s [u8])> + '_ {
+    /// Sign the message with the keypair of the given pair
+    fn sign_with(
+        &mut self,
+        key_type: &[u8; 4],
+        pair: &CryptoPublicPair,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let keypair = Self::find_key(&self.keys, key_type, &pair.public_key)
+            .filter(|keypair| **keypair == pair.algo)
+            .ok_or(Error::PairNotFound)?;
+
+        self.policies.check(self.clock, *key_type, pair.algo, msg)?;
+
+        if let Some(protection) = self.slashing.as_mut() {
+            protection.check_sign(&mut *self.storage, *key_type, &pair.public_key, msg)?;
+        }
+
+        //only signatures actually made count for the rate limit
+        self.policies.count(self.clock, *key_type, pair.algo);
+        Ok(keypair.sign(&mut self.rng, msg))
+    }
+
+    /// Length of the signatures made by the pair, if we have it
+    fn signature_len(&self, key_type: &[u8; 4], pair: &CryptoPublicPair) -> Option<usize> {
+        Self::find_key(&self.keys, key_type, &pair.public_key)
+            .filter(|keypair| **keypair == pair.algo)
+            .map(|_| pair.algo.signature_len())
+    }
+
+    /// Whether the keypair is the given pair, any keypair matches if there's no pair
+    fn key_matches(keypair: &Keypair, pair: Option<&CryptoPublicPair>) -> bool {
+        pair.is_none_or(|pair| {
+            *keypair == pair.algo && keypair.public_bytes() == pair.public_key.as_slice()
+        })
+    }
+
+    /// Remove the keys of the key type matching the pair (or all if no pair), also from storage
+    ///
+    /// The removed keypairs are dropped, which zeroizes their secrets
+    fn remove_keys(&mut self, key_type: [u8; 4], pair: Option<&CryptoPublicPair>) -> usize {
+        let keys = match self.keys.get_mut(&key_type) {
+            Some(keys) => keys,
+            None => return 0,
+        };
+
+        let (removed, kept): (Vec<_>, Vec<_>) = core::mem::take(keys)
+            .into_iter()
+            .partition(|keypair| Self::key_matches(keypair, pair));
+        *keys = kept;
+
+        for keypair in removed.iter() {
+            //keys from a seed or the default set are not persisted
+            if !self.storage.delete_key(key_type, keypair) {
+                debug!("removed key was not in storage");
+            }
+        }
+
+        removed.len()
+    }
+
+    fn iter_all_pkeys(&self) -> impl Iterator<Item = ([u8; 4], CryptoAlgo, &[u8])> + '_ {
         self.keys
             .iter()
             //iterate the hashmap and get (KEY_TYPE, Vec<Keypair>)
-            .map(|(key_type, keys)| {
+            .flat_map(|(key_type, keys)| {
                 //iter thru all the keypairs
                 keys.iter()
-                    //convert keypair to pubkey and associate KEY_TYPE
-                    .map(move |key| (*key_type, key.public_bytes()))
+                    //convert keypair to pubkey and associate KEY_TYPE and algo
+                    .map(move |key| (*key_type, key.algo(), key.public_bytes()))
             })
-            .flatten()
     }
 }
 
-pub fn open_session<R: CryptoRng + RngCore + 'static>(rng: &'static mut R) -> Result<(), ()> {
-    // At this point no handler should have been created
-    // Only one instance is allowed, so we create our command handler on each new session.
-    TA_HANDLER.0.borrow_mut().replace(TaApp::with_rng(rng));
+pub fn create_app<R, S, C>(
+    rng: &'static mut R,
+    storage: &'static mut S,
+    clock: &'static C,
+) -> Result<(), Error>
+where
+    R: CryptoRng + RngCore + 'static,
+    S: Storage + 'static,
+    C: Clock + 'static,
+{
+    // Only one instance is allowed, it's shared by all the sessions
+    let mut sessions = TA_HANDLER.0.borrow_mut();
+    if sessions.app.is_some() {
+        return Err(Error::BadState);
+    }
+
+    let app = TaApp::new(rng, storage).with_clock(clock);
+    #[cfg(feature = "slashing-protection")]
+    let app = app.with_slashing_protection();
+
+    sessions.app = Some(Rc::new(RefCell::new(app)));
     Ok(())
 }
 
-pub fn close_session() {
-    // Once the client is done, the TA session is closed, dropping our handler
-    let _ = TA_HANDLER.0.borrow_mut().take();
+pub fn destroy_app() {
+    // Dropping the sessions too, they can't be used without the app
+    let mut sessions = TA_HANDLER.0.borrow_mut();
+    sessions.open.clear();
+    sessions.app = None;
 }
 
-pub fn borrow_mut_app<'a>() -> RefMut<'a, Option<impl HandleTaCommand + 'static>> {
-    trace!("Getting TA_app mut handler");
-    TA_HANDLER.0.borrow_mut()
+pub fn open_session() -> Result<SessionId, Error> {
+    // Every session gets its own handler, the keys are shared thru the app
+    TA_HANDLER.0.borrow_mut().open().ok_or(Error::BadState)
 }
 
-pub fn borrow_app<'a>() -> Ref<'a, Option<impl HandleTaCommand + 'static>> {
-    trace!("Getting TA_app handler");
-    TA_HANDLER.0.borrow()
+pub fn close_session(session: SessionId) {
+    // Once the client is done, the TA session is closed, dropping its handler
+    TA_HANDLER.0.borrow_mut().close(session)
+}
+
+pub fn borrow_mut_app<'a>(
+    session: SessionId,
+) -> Option<RefMut<'a, impl HandleTaCommand + 'static>> {
+    trace!("Getting TA_app mut handler of session {}", session);
+    let sessions = TA_HANDLER.0.borrow_mut();
+    let idx = sessions.position(session)?;
+
+    Some(RefMut::map(sessions, |sessions| &mut sessions.open[idx]))
+}
+
+pub fn borrow_app<'a>(session: SessionId) -> Option<Ref<'a, impl HandleTaCommand + 'static>> {
+    trace!("Getting TA_app handler of session {}", session);
+    let sessions = TA_HANDLER.0.borrow();
+    let idx = sessions.position(session)?;
+
+    Some(Ref::map(sessions, |sessions| &sessions.open[idx]))
 }
 
 #[cfg(any(test, fuzzing))]
 impl Default for TaApp<'static> {
     fn default() -> Self {
         let rng = Box::new(rand::thread_rng());
+        let storage = Box::new(ta_common::MemoryStorage::new());
 
-        Self {
-            rng: Box::leak(rng),
-            keys: crypto::default_set(),
-        }
+        Self::new(Box::leak(rng), Box::leak(storage))
     }
 }
 
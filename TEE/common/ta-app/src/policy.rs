@@ -0,0 +1,464 @@
+//! Restrictions on the messages that can be signed, per key type and algorithm
+//!
+//! Policies are provisioned with `CommandId::SetPolicy` and persisted,
+//! the rate limit counters instead only live in memory.
+//! Anyone able to invoke the TA can set them, so once set a policy can only be made stricter,
+//! never relaxed nor removed.
+//!
+//! Policies only apply to signed messages: VRF outputs are not restricted by them,
+//! see the slashing protection for those
+
+use super::*;
+
+use optee_common::{MessagePolicy, RateLimit};
+use ta_common::{Clock, Object};
+
+const MAX_RANGES: usize = 4;
+const MAX_PREFIXES: usize = 4;
+const PREFIX_MAX_LEN: usize = 32;
+
+//used to distinguish stored policies from other objects in the same storage
+const TAG: u8 = b'P';
+
+/// Storage ID of a policy: tag + key_type + algo
+pub type PolicyId = [u8; 1 + 4 + 1];
+
+/// Persisted policy of a key type and algorithm
+pub struct StoredPolicy {
+    key_type: [u8; 4],
+    algo: CryptoAlgo,
+    policy: MessagePolicy,
+}
+
+impl StoredPolicy {
+    fn fits(policy: &MessagePolicy) -> bool {
+        policy.lengths.len() <= MAX_RANGES
+            && policy.prefixes.len() <= MAX_PREFIXES
+            && policy.prefixes.iter().all(|p| p.len() <= PREFIX_MAX_LEN)
+    }
+}
+
+/// Whether `new` allows at most what `current` allows
+fn is_stricter(new: &MessagePolicy, current: &MessagePolicy) -> bool {
+    //every length allowed by a range of `new` must be in a range of `current`
+    let lengths = current.lengths.is_empty()
+        || (!new.lengths.is_empty()
+            && new.lengths.iter().all(|&(min, max)| {
+                min > max
+                    || current
+                        .lengths
+                        .iter()
+                        .any(|&(cur_min, cur_max)| cur_min <= min && max <= cur_max)
+            }));
+
+    //every message starting with a prefix of `new` must start with a prefix of `current`
+    let prefixes = current.prefixes.is_empty()
+        || (!new.prefixes.is_empty()
+            && new
+                .prefixes
+                .iter()
+                .all(|p| current.prefixes.iter().any(|cur| p.starts_with(cur))));
+
+    let rate_limit = match (current.rate_limit, new.rate_limit) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(current), Some(new)) => new.max <= current.max && new.period_ms >= current.period_ms,
+    };
+
+    lengths && prefixes && rate_limit
+}
+
+impl SerializeFixed for StoredPolicy {
+    type ErrorFixed = usize;
+
+    fn len() -> usize {
+        //tag + key_type + algo + n_ranges + ranges + n_prefixes + prefixes + rate limit
+        1 + 4
+            + CryptoAlgo::len()
+            + 1
+            + MAX_RANGES * 16
+            + 1
+            + MAX_PREFIXES * (1 + PREFIX_MAX_LEN)
+            + 1
+            + 4
+            + 8
+    }
+
+    fn serialize_fixed(&self, dest: &mut [u8]) -> Result<(), Self::ErrorFixed> {
+        if dest.len() < Self::len() || !Self::fits(&self.policy) {
+            return Err(Self::len());
+        }
+
+        let (header, dest) = dest.split_at_mut(1 + 4);
+        header[0] = TAG;
+        header[1..].copy_from_slice(&self.key_type);
+
+        let (algo, dest) = dest.split_at_mut(CryptoAlgo::len());
+        self.algo.serialize_fixed(algo).unwrap();
+
+        let (ranges, dest) = dest.split_at_mut(1 + MAX_RANGES * 16);
+        ranges[0] = self.policy.lengths.len() as u8;
+        for (range, (min, max)) in ranges[1..]
+            .chunks_exact_mut(16)
+            .zip(self.policy.lengths.iter())
+        {
+            range[..8].copy_from_slice(&min.to_le_bytes());
+            range[8..].copy_from_slice(&max.to_le_bytes());
+        }
+
+        let (prefixes, dest) = dest.split_at_mut(1 + MAX_PREFIXES * (1 + PREFIX_MAX_LEN));
+        prefixes[0] = self.policy.prefixes.len() as u8;
+        for (slot, prefix) in prefixes[1..]
+            .chunks_exact_mut(1 + PREFIX_MAX_LEN)
+            .zip(self.policy.prefixes.iter())
+        {
+            slot[0] = prefix.len() as u8;
+            slot[1..1 + prefix.len()].copy_from_slice(prefix);
+        }
+
+        let rate = &mut dest[..1 + 4 + 8];
+        if let Some(RateLimit { max, period_ms }) = self.policy.rate_limit {
+            rate[0] = 1;
+            rate[1..5].copy_from_slice(&max.to_le_bytes());
+            rate[5..].copy_from_slice(&period_ms.to_le_bytes());
+        }
+
+        Ok(())
+    }
+}
+
+impl DeserializeOwned for StoredPolicy {
+    type ErrorOwned = usize;
+
+    fn deserialize_owned(mut input: &[u8]) -> Result<Self, Self::ErrorOwned> {
+        if input.len() < Self::len() || input[0] != TAG {
+            return Err(Self::len());
+        }
+        util::advance_slice(&mut input, 1).unwrap();
+
+        let key_type: [u8; 4] =
+            DeserializeOwned::deserialize_owned(input).map_err(|_| Self::len())?;
+        util::advance_slice(&mut input, 4).unwrap();
+
+        let algo = CryptoAlgo::deserialize_owned(input).map_err(|_| Self::len())?;
+        util::advance_slice(&mut input, CryptoAlgo::len()).unwrap();
+
+        let ranges = util::read_and_advance(&mut input, 1 + MAX_RANGES * 16).unwrap();
+        let lengths = ranges[1..]
+            .chunks_exact(16)
+            .take(ranges[0] as usize)
+            .map(|mut range| {
+                let min = util::read_and_advance_u64(&mut range).unwrap();
+                let max = util::read_and_advance_u64(&mut range).unwrap();
+                (min, max)
+            })
+            .collect();
+
+        let prefixes =
+            util::read_and_advance(&mut input, 1 + MAX_PREFIXES * (1 + PREFIX_MAX_LEN)).unwrap();
+        let prefixes = prefixes[1..]
+            .chunks_exact(1 + PREFIX_MAX_LEN)
+            .take(prefixes[0] as usize)
+            .map(|slot| slot.get(1..1 + slot[0] as usize).map(|p| p.to_vec()))
+            .collect::<Option<_>>()
+            .ok_or(Self::len())?;
+
+        let rate = util::read_and_advance(&mut input, 1 + 4 + 8).unwrap();
+        let rate_limit = match rate[0] {
+            0 => None,
+            _ => {
+                let mut max = [0; 4];
+                max.copy_from_slice(&rate[1..5]);
+                let mut period_ms = &rate[5..];
+
+                Some(RateLimit {
+                    max: u32::from_le_bytes(max),
+                    period_ms: util::read_and_advance_u64(&mut period_ms).unwrap(),
+                })
+            }
+        };
+
+        Ok(Self {
+            key_type,
+            algo,
+            policy: MessagePolicy {
+                lengths,
+                prefixes,
+                rate_limit,
+            },
+        })
+    }
+}
+
+impl Serialize for StoredPolicy {
+    type Error = usize;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut v = vec![0; Self::len()];
+        self.serialize_fixed(&mut v)?;
+
+        Ok(v)
+    }
+}
+
+impl Object for StoredPolicy {
+    type ID = PolicyId;
+
+    fn id(&self) -> Self::ID {
+        policy_id(self.key_type, self.algo)
+    }
+}
+
+/// Compute the storage ID for the policy of the given key type and algorithm
+pub fn policy_id(key_type: [u8; 4], algo: CryptoAlgo) -> PolicyId {
+    let mut id = [TAG, 0, 0, 0, 0, algo.into()];
+    id[1..5].copy_from_slice(&key_type);
+
+    id
+}
+
+/// A policy together with its rate limit counter
+struct Rule {
+    stored: StoredPolicy,
+    window_start: u64,
+    count: u32,
+}
+
+impl From<StoredPolicy> for Rule {
+    fn from(stored: StoredPolicy) -> Self {
+        Self {
+            stored,
+            window_start: 0,
+            count: 0,
+        }
+    }
+}
+
+/// The policies of all key types, messages for key types without a policy are always allowed
+#[derive(Default)]
+pub struct Policies {
+    rules: Vec<Rule>,
+}
+
+impl Policies {
+    /// Load the policies already present in `storage`
+    pub fn new(storage: &mut dyn KeyStorage) -> Self {
+        Self {
+            rules: storage
+                .load_policies()
+                .into_iter()
+                .map(Rule::from)
+                .collect(),
+        }
+    }
+
+    fn position(&self, key_type: [u8; 4], algo: CryptoAlgo) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|rule| rule.stored.key_type == key_type && rule.stored.algo == algo)
+    }
+
+    /// Set the policy of the key type and algorithm, persisting it
+    ///
+    /// An existing policy can only be replaced by a stricter one, and `None` is only
+    /// accepted if there's no policy to begin with
+    pub fn set(
+        &mut self,
+        storage: &mut dyn KeyStorage,
+        key_type: [u8; 4],
+        algo: CryptoAlgo,
+        policy: Option<MessagePolicy>,
+    ) -> Result<(), Error> {
+        let idx = self.position(key_type, algo);
+
+        let policy = match (policy, idx) {
+            (None, None) => return Ok(()),
+            (None, Some(_)) => {
+                warn!("denied removing a policy");
+                return Err(Error::AccessDenied);
+            }
+            (Some(policy), _) => policy,
+        };
+
+        if !StoredPolicy::fits(&policy) {
+            return Err(Error::BadParameters);
+        }
+
+        if let Some(idx) = idx {
+            if !is_stricter(&policy, &self.rules[idx].stored.policy) {
+                warn!("denied relaxing a policy");
+                return Err(Error::AccessDenied);
+            }
+        }
+
+        let stored = StoredPolicy {
+            key_type,
+            algo,
+            policy,
+        };
+        if !storage.store_policy(&stored) {
+            error!("unable to persist policy");
+            return Err(Error::Unavailable);
+        }
+
+        //keep counting the signatures of the current period, so they don't start over
+        match idx {
+            Some(idx) => self.rules[idx].stored = stored,
+            None => self.rules.push(stored.into()),
+        }
+
+        Ok(())
+    }
+
+    /// Check that the policy of the key type and algorithm allows signing `msg`
+    ///
+    /// Rate limited policies deny everything if there's no clock.
+    /// The signature is only counted for the rate limit with [`Policies::count`]
+    pub fn check(
+        &self,
+        clock: Option<&dyn Clock>,
+        key_type: [u8; 4],
+        algo: CryptoAlgo,
+        msg: &[u8],
+    ) -> Result<(), Error> {
+        let rule = match self.position(key_type, algo) {
+            Some(idx) => &self.rules[idx],
+            None => return Ok(()),
+        };
+        let policy = &rule.stored.policy;
+
+        let len = msg.len() as u64;
+        if !policy.lengths.is_empty()
+            && !policy
+                .lengths
+                .iter()
+                .any(|&(min, max)| min <= len && len <= max)
+        {
+            warn!("policy denied message of length {}", len);
+            return Err(Error::AccessDenied);
+        }
+
+        if !policy.prefixes.is_empty() && !policy.prefixes.iter().any(|p| msg.starts_with(p)) {
+            warn!("policy denied message without an allowed prefix");
+            return Err(Error::AccessDenied);
+        }
+
+        if let Some(RateLimit { max, period_ms }) = policy.rate_limit {
+            let now = match clock {
+                Some(clock) => clock.now_ms(),
+                None => {
+                    warn!("policy denied message, no clock for the rate limit");
+                    return Err(Error::AccessDenied);
+                }
+            };
+
+            if now.saturating_sub(rule.window_start) < period_ms && rule.count >= max {
+                warn!("policy denied message, rate limit reached");
+                return Err(Error::AccessDenied);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count a signature made with the keys of the key type and algorithm for the rate limit
+    pub fn count(&mut self, clock: Option<&dyn Clock>, key_type: [u8; 4], algo: CryptoAlgo) {
+        let rule = match self.position(key_type, algo) {
+            Some(idx) => &mut self.rules[idx],
+            None => return,
+        };
+
+        if let (Some(RateLimit { period_ms, .. }), Some(clock)) =
+            (rule.stored.policy.rate_limit, clock)
+        {
+            let now = clock.now_ms();
+            if now.saturating_sub(rule.window_start) >= period_ms {
+                rule.window_start = now;
+                rule.count = 0;
+            }
+            rule.count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_policy_roundtrip() {
+        let stored = StoredPolicy {
+            key_type: *b"dumm",
+            algo: CryptoAlgo::Ed25519,
+            policy: MessagePolicy {
+                lengths: vec![(1, 32), (64, 64)],
+                prefixes: vec![vec![0x04, 0x00], vec![]],
+                rate_limit: Some(RateLimit {
+                    max: 10,
+                    period_ms: 6000,
+                }),
+            },
+        };
+
+        let restored =
+            StoredPolicy::deserialize_owned(&stored.serialize().unwrap()).expect("valid policy");
+
+        assert_eq!(restored.id(), stored.id());
+        assert_eq!(restored.policy.lengths, stored.policy.lengths);
+        assert_eq!(restored.policy.prefixes, stored.policy.prefixes);
+        assert_eq!(restored.policy.rate_limit, stored.policy.rate_limit);
+    }
+
+    #[test]
+    fn stricter_policies() {
+        let current = MessagePolicy {
+            lengths: vec![(1, 32)],
+            prefixes: vec![vec![0x04]],
+            rate_limit: Some(RateLimit {
+                max: 10,
+                period_ms: 6000,
+            }),
+        };
+        assert!(is_stricter(&current, &current));
+
+        let stricter = MessagePolicy {
+            lengths: vec![(2, 4), (8, 8)],
+            prefixes: vec![vec![0x04, 0x00]],
+            rate_limit: Some(RateLimit {
+                max: 5,
+                period_ms: 6000,
+            }),
+        };
+        assert!(is_stricter(&stricter, &current));
+        assert!(!is_stricter(&current, &stricter));
+        //anything is stricter than no restrictions at all
+        assert!(is_stricter(&current, &MessagePolicy::default()));
+
+        let wider_length = MessagePolicy {
+            lengths: vec![(1, 64)],
+            ..current.clone()
+        };
+        assert!(!is_stricter(&wider_length, &current));
+
+        let other_prefix = MessagePolicy {
+            prefixes: vec![vec![0x05]],
+            ..current.clone()
+        };
+        assert!(!is_stricter(&other_prefix, &current));
+
+        let no_rate_limit = MessagePolicy {
+            rate_limit: None,
+            ..current.clone()
+        };
+        assert!(!is_stricter(&no_rate_limit, &current));
+
+        let shorter_period = MessagePolicy {
+            rate_limit: Some(RateLimit {
+                max: 10,
+                period_ms: 1000,
+            }),
+            ..current.clone()
+        };
+        assert!(!is_stricter(&shorter_period, &current));
+    }
+}
@@ -0,0 +1,373 @@
+//! Protection against signing conflicting consensus messages (equivocations)
+//!
+//! Messages signed with keys of a protected key type are decoded to find the slot/round they're for.
+//! The highest one signed by each key is persisted, and messages for an older slot/round,
+//! or a different message for the same one, are refused with `Error::Equivocation`
+
+use super::*;
+
+use core::cmp::Ordering;
+
+use crypto::VRFData;
+use sha2::{Digest, Sha256};
+use storage::PUBLIC_MAX_LEN;
+use ta_common::Object;
+
+pub const BABE: [u8; 4] = *b"babe";
+pub const GRANDPA: [u8; 4] = *b"gran";
+
+//grandpa stages are the variant of the signed message: prevote, precommit, primary propose
+const GRANDPA_STAGES: u8 = 3;
+const BABE_CLAIM: u8 = 0x10;
+const BABE_SEAL: u8 = 0x11;
+
+/// Length of a GRANDPA vote: variant + target hash + target number (u32) + round + set id
+const GRANDPA_VOTE_LEN: usize = 1 + 32 + 4 + 8 + 8;
+
+//used to distinguish marks from other objects in the same storage
+const TAG: u8 = b'M';
+
+/// Storage ID of a mark: tag + key_type + stage + public key (zero padded)
+pub type MarkId = [u8; 1 + 4 + 1 + PUBLIC_MAX_LEN];
+
+/// Highest slot/round signed by a key at a given stage of the protocol
+pub struct Mark {
+    key_type: [u8; 4],
+    stage: u8,
+    public: Vec<u8>,
+    /// (epoch or set id, slot or round)
+    position: (u64, u64),
+    /// Hash of the message signed at `position`, `None` if any message is allowed
+    digest: Option<[u8; 32]>,
+}
+
+impl Mark {
+    fn is(&self, key_type: [u8; 4], stage: u8, public: &[u8]) -> bool {
+        self.key_type == key_type && self.stage == stage && self.public.as_slice() == public
+    }
+}
+
+impl SerializeFixed for Mark {
+    type ErrorFixed = usize;
+
+    fn len() -> usize {
+        //tag + key_type + stage + public_len + public + position + digest flag + digest
+        1 + 4 + 1 + 1 + PUBLIC_MAX_LEN + 8 + 8 + 1 + 32
+    }
+
+    fn serialize_fixed(&self, dest: &mut [u8]) -> Result<(), Self::ErrorFixed> {
+        if dest.len() < Self::len() || self.public.len() > PUBLIC_MAX_LEN {
+            return Err(Self::len());
+        }
+
+        let (header, dest) = dest.split_at_mut(1 + 4 + 1);
+        header[0] = TAG;
+        header[1..5].copy_from_slice(&self.key_type);
+        header[5] = self.stage;
+
+        let (public, dest) = dest.split_at_mut(1 + PUBLIC_MAX_LEN);
+        public[0] = self.public.len() as u8;
+        public[1..1 + self.public.len()].copy_from_slice(&self.public);
+
+        let (position, dest) = dest.split_at_mut(16);
+        position[..8].copy_from_slice(&self.position.0.to_le_bytes());
+        position[8..].copy_from_slice(&self.position.1.to_le_bytes());
+
+        let digest = &mut dest[..1 + 32];
+        if let Some(hash) = self.digest {
+            digest[0] = 1;
+            digest[1..].copy_from_slice(&hash);
+        }
+
+        Ok(())
+    }
+}
+
+impl DeserializeOwned for Mark {
+    type ErrorOwned = usize;
+
+    fn deserialize_owned(mut input: &[u8]) -> Result<Self, Self::ErrorOwned> {
+        if input.len() < Self::len() || input[0] != TAG {
+            return Err(Self::len());
+        }
+        util::advance_slice(&mut input, 1).unwrap();
+
+        let key_type: [u8; 4] =
+            DeserializeOwned::deserialize_owned(input).map_err(|_| Self::len())?;
+        util::advance_slice(&mut input, 4).unwrap();
+
+        let stage = util::read_and_advance(&mut input, 1).unwrap()[0];
+
+        let public = util::read_and_advance(&mut input, 1 + PUBLIC_MAX_LEN).unwrap();
+        let public = public
+            .get(1..1 + public[0] as usize)
+            .ok_or(Self::len())?
+            .to_vec();
+
+        let epoch = util::read_and_advance_u64(&mut input).unwrap();
+        let slot = util::read_and_advance_u64(&mut input).unwrap();
+
+        let digest = util::read_and_advance(&mut input, 1 + 32).unwrap();
+        let digest = match digest[0] {
+            0 => None,
+            _ => {
+                let mut hash = [0; 32];
+                hash.copy_from_slice(&digest[1..]);
+                Some(hash)
+            }
+        };
+
+        Ok(Self {
+            key_type,
+            stage,
+            public,
+            position: (epoch, slot),
+            digest,
+        })
+    }
+}
+
+impl Serialize for Mark {
+    type Error = usize;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut v = vec![0; Self::len()];
+        self.serialize_fixed(&mut v)?;
+
+        Ok(v)
+    }
+}
+
+impl Object for Mark {
+    type ID = MarkId;
+
+    fn id(&self) -> Self::ID {
+        let mut id = [0; 1 + 4 + 1 + PUBLIC_MAX_LEN];
+        id[0] = TAG;
+        id[1..5].copy_from_slice(&self.key_type);
+        id[5] = self.stage;
+
+        let len = self.public.len().min(PUBLIC_MAX_LEN);
+        id[6..6 + len].copy_from_slice(&self.public[..len]);
+
+        id
+    }
+}
+
+/// Slashing protection layer, keeping the marks of all the keys of the protected key types
+#[derive(Default)]
+pub struct Protection {
+    marks: Vec<Mark>,
+}
+
+impl Protection {
+    /// Create the layer, loading the marks already present in `storage`
+    pub fn new(storage: &mut dyn KeyStorage) -> Self {
+        Self {
+            marks: storage.load_marks(),
+        }
+    }
+
+    /// Check that signing `msg` with the given key can't result in an equivocation,
+    /// recording the message as signed
+    ///
+    /// Messages for key types without protection are always allowed
+    pub fn check_sign(
+        &mut self,
+        storage: &mut dyn KeyStorage,
+        key_type: [u8; 4],
+        public: &[u8],
+        msg: &[u8],
+    ) -> Result<(), Error> {
+        match key_type {
+            GRANDPA => {
+                let (stage, position) = decode_grandpa(msg).ok_or(Error::BadFormat)?;
+
+                self.advance(
+                    storage,
+                    key_type,
+                    stage,
+                    public,
+                    position,
+                    Some(digest(msg)),
+                )
+            }
+            BABE => {
+                //the block seal is only over the header hash, so the slot is taken from
+                // the last slot claim of the key: BABE evaluates the primary VRF of every slot
+                // before authoring in it, secondary plain slots included, and seals the block
+                // before moving on to the next slot
+                if msg.len() != 32 {
+                    return Err(Error::BadFormat);
+                }
+
+                let position = match self.find(key_type, BABE_CLAIM, public) {
+                    Some(mark) => mark.position,
+                    None => {
+                        //no slot to check the seal against
+                        debug!("sealing with key_type {:x?} without a slot claim", key_type);
+                        return Ok(());
+                    }
+                };
+
+                self.advance(
+                    storage,
+                    key_type,
+                    BABE_SEAL,
+                    public,
+                    position,
+                    Some(digest(msg)),
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Same as `check_sign` but for VRF signatures, used to claim BABE slots
+    pub fn check_vrf(
+        &mut self,
+        storage: &mut dyn KeyStorage,
+        key_type: [u8; 4],
+        public: &[u8],
+        data: &VRFData<'_>,
+    ) -> Result<(), Error> {
+        match key_type {
+            BABE => {
+                if data.label() != b"BABE" {
+                    return Err(Error::BadFormat);
+                }
+                let slot = data.get_u64(b"slot number").ok_or(Error::BadFormat)?;
+
+                //VRF outputs are deterministic, so claiming the same slot again is fine
+                self.advance(storage, key_type, BABE_CLAIM, public, (0, slot), None)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn find(&self, key_type: [u8; 4], stage: u8, public: &[u8]) -> Option<&Mark> {
+        self.marks
+            .iter()
+            .find(|mark| mark.is(key_type, stage, public))
+    }
+
+    /// Move the mark of the key at the given stage to `position`, persisting it
+    ///
+    /// Fails if the position is older than the current mark,
+    /// or if it's the same but the message is different
+    fn advance(
+        &mut self,
+        storage: &mut dyn KeyStorage,
+        key_type: [u8; 4],
+        stage: u8,
+        public: &[u8],
+        position: (u64, u64),
+        digest: Option<[u8; 32]>,
+    ) -> Result<(), Error> {
+        let idx = self
+            .marks
+            .iter()
+            .position(|mark| mark.is(key_type, stage, public));
+
+        if let Some(mark) = idx.map(|idx| &self.marks[idx]) {
+            let conflict = match position.cmp(&mark.position) {
+                Ordering::Less => true,
+                Ordering::Equal => digest.is_some() && digest != mark.digest,
+                Ordering::Greater => false,
+            };
+
+            if conflict {
+                warn!(
+                    "refusing to sign for {:?} with key_type {:x?}, last signed {:?}",
+                    position, key_type, mark.position
+                );
+                return Err(Error::Equivocation);
+            }
+        }
+
+        let mark = Mark {
+            key_type,
+            stage,
+            public: public.to_vec(),
+            position,
+            digest,
+        };
+
+        //persist before signing, so a restart can't be used to sign again
+        if !storage.store_mark(&mark) {
+            error!("unable to persist slashing protection mark");
+            return Err(Error::Unavailable);
+        }
+
+        match idx {
+            Some(idx) => self.marks[idx] = mark,
+            None => self.marks.push(mark),
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a GRANDPA localized payload: SCALE encoded `(Message, RoundNumber, SetId)`
+///
+/// Returns the message variant and the (set id, round) the message is for
+fn decode_grandpa(msg: &[u8]) -> Option<(u8, (u64, u64))> {
+    //the target number can also be an u64
+    if msg.len() != GRANDPA_VOTE_LEN && msg.len() != GRANDPA_VOTE_LEN + 4 {
+        return None;
+    }
+
+    let stage = msg[0];
+    if stage >= GRANDPA_STAGES {
+        return None;
+    }
+
+    let mut tail = &msg[msg.len() - 16..];
+    let round = util::read_and_advance_u64(&mut tail).ok()?;
+    let set_id = util::read_and_advance_u64(&mut tail).ok()?;
+
+    Some((stage, (set_id, round)))
+}
+
+fn digest(msg: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&Sha256::digest(msg));
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mark_roundtrip() {
+        let mark = Mark {
+            key_type: GRANDPA,
+            stage: 1,
+            public: vec![42; 32],
+            position: (3, 7),
+            digest: Some(digest(b"vote")),
+        };
+
+        let restored = Mark::deserialize_owned(&mark.serialize().unwrap()).expect("valid mark");
+
+        assert!(restored.is(GRANDPA, 1, &[42; 32]));
+        assert_eq!(restored.position, mark.position);
+        assert_eq!(restored.digest, mark.digest);
+    }
+
+    #[test]
+    fn decode_grandpa_vote() {
+        let mut vote = vec![1];
+        vote.extend_from_slice(&[0xAA; 32]);
+        vote.extend_from_slice(&100u32.to_le_bytes());
+        vote.extend_from_slice(&9u64.to_le_bytes());
+        vote.extend_from_slice(&2u64.to_le_bytes());
+
+        assert_eq!(decode_grandpa(&vote), Some((1, (2, 9))));
+
+        vote[0] = GRANDPA_STAGES;
+        assert!(decode_grandpa(&vote).is_none());
+        assert!(decode_grandpa(&vote[1..]).is_none());
+    }
+}
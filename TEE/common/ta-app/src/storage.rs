@@ -0,0 +1,280 @@
+//! Persistence of the TA keys on top of any `ta_common::Storage`
+
+use super::*;
+
+use crypto::sealing::{SealingSecret, SECRET_ID};
+use policy::StoredPolicy;
+use slashing::Mark;
+use ta_common::{Object, Storage, StorageEnumerator};
+use zeroize::Zeroizing;
+
+const SECRET_MAX_LEN: usize = 64;
+pub const PUBLIC_MAX_LEN: usize = 33;
+
+//used to distinguish stored keys from other objects in the same storage
+const TAG: u8 = b'K';
+
+/// Storage ID of a key: key_type + algo + public key (zero padded)
+pub type KeyId = [u8; 4 + 1 + PUBLIC_MAX_LEN];
+
+/// Persisted representation of a keypair associated with a key type
+pub struct StoredKey {
+    key_type: [u8; 4],
+    algo: CryptoAlgo,
+    secret: Zeroizing<Vec<u8>>,
+    public: Vec<u8>,
+}
+
+impl StoredKey {
+    pub fn new(key_type: [u8; 4], keypair: &Keypair) -> Self {
+        Self {
+            key_type,
+            algo: keypair.algo(),
+            secret: keypair.secret_bytes(),
+            public: keypair.public_bytes().to_vec(),
+        }
+    }
+
+    /// Restore the keypair, `None` if the stored secret is not valid
+    pub fn into_keypair(self) -> Option<([u8; 4], Keypair)> {
+        let keypair = Keypair::from_bytes(&self.secret, self.algo)?;
+
+        if keypair.public_bytes() != self.public.as_slice() {
+            return None;
+        }
+
+        Some((self.key_type, keypair))
+    }
+}
+
+impl SerializeFixed for StoredKey {
+    type ErrorFixed = usize;
+
+    fn len() -> usize {
+        //tag + algo + key_type + secret_len + secret + public_len + public
+        1 + CryptoAlgo::len() + 4 + 1 + SECRET_MAX_LEN + 1 + PUBLIC_MAX_LEN
+    }
+
+    fn serialize_fixed(&self, dest: &mut [u8]) -> Result<(), Self::ErrorFixed> {
+        if dest.len() < Self::len()
+            || self.secret.len() > SECRET_MAX_LEN
+            || self.public.len() > PUBLIC_MAX_LEN
+        {
+            return Err(Self::len());
+        }
+
+        let (tag, dest) = dest.split_at_mut(1);
+        tag[0] = TAG;
+
+        let (algo, dest) = dest.split_at_mut(CryptoAlgo::len());
+        self.algo.serialize_fixed(algo).unwrap();
+
+        let (key_type, dest) = dest.split_at_mut(4);
+        key_type.copy_from_slice(&self.key_type);
+
+        let (secret, dest) = dest.split_at_mut(1 + SECRET_MAX_LEN);
+        secret[0] = self.secret.len() as u8;
+        secret[1..1 + self.secret.len()].copy_from_slice(&self.secret);
+
+        let public = &mut dest[..1 + PUBLIC_MAX_LEN];
+        public[0] = self.public.len() as u8;
+        public[1..1 + self.public.len()].copy_from_slice(&self.public);
+
+        Ok(())
+    }
+}
+
+impl DeserializeOwned for StoredKey {
+    type ErrorOwned = usize;
+
+    fn deserialize_owned(mut input: &[u8]) -> Result<Self, Self::ErrorOwned> {
+        if input.len() < Self::len() || input[0] != TAG {
+            return Err(Self::len());
+        }
+        util::advance_slice(&mut input, 1).unwrap();
+
+        let algo = CryptoAlgo::deserialize_owned(input).map_err(|_| Self::len())?;
+        util::advance_slice(&mut input, CryptoAlgo::len()).unwrap();
+
+        let key_type: [u8; 4] =
+            DeserializeOwned::deserialize_owned(input).map_err(|_| Self::len())?;
+        util::advance_slice(&mut input, 4).unwrap();
+
+        let secret = util::read_and_advance(&mut input, 1 + SECRET_MAX_LEN).unwrap();
+        let secret = secret
+            .get(1..1 + secret[0] as usize)
+            .ok_or(Self::len())?
+            .to_vec();
+        let secret = Zeroizing::new(secret);
+
+        let public = util::read_and_advance(&mut input, 1 + PUBLIC_MAX_LEN).unwrap();
+        let public = public
+            .get(1..1 + public[0] as usize)
+            .ok_or(Self::len())?
+            .to_vec();
+
+        Ok(Self {
+            key_type,
+            algo,
+            secret,
+            public,
+        })
+    }
+}
+
+/// The returned buffer holds the secret, so it should be zeroized once used
+impl Serialize for StoredKey {
+    type Error = usize;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        let mut v = vec![0; Self::len()];
+        self.serialize_fixed(&mut v)?;
+
+        Ok(v)
+    }
+}
+
+impl Object for StoredKey {
+    type ID = KeyId;
+
+    fn id(&self) -> Self::ID {
+        key_id(self.key_type, self.algo, &self.public)
+    }
+}
+
+/// Compute the storage ID for the given key
+pub fn key_id(key_type: [u8; 4], algo: CryptoAlgo, public: &[u8]) -> KeyId {
+    let mut id = [0; 4 + 1 + PUBLIC_MAX_LEN];
+    id[..4].copy_from_slice(&key_type);
+    id[4] = algo.into();
+
+    let len = public.len().min(PUBLIC_MAX_LEN);
+    id[5..5 + len].copy_from_slice(&public[..len]);
+
+    id
+}
+
+/// Object-safe subset of `ta_common::Storage` used by `TaApp` to persist keys and their metadata
+pub trait KeyStorage {
+    /// Retrieve all the stored keys
+    fn load_keys(&mut self) -> Vec<([u8; 4], Keypair)>;
+
+    /// Persist the given keypair for the given key type
+    ///
+    /// Returns `false` if the key couldn't be persisted
+    fn store_key(&mut self, key_type: [u8; 4], keypair: &Keypair) -> bool;
+
+    /// Remove the given keypair of the given key type
+    ///
+    /// Returns `false` if the key wasn't persisted or couldn't be removed
+    fn delete_key(&mut self, key_type: [u8; 4], keypair: &Keypair) -> bool;
+
+    /// Retrieve all the stored slashing protection marks
+    fn load_marks(&mut self) -> Vec<Mark>;
+
+    /// Persist the given mark, overwriting the previous one for the same key and stage
+    ///
+    /// Returns `false` if the mark couldn't be persisted
+    fn store_mark(&mut self, mark: &Mark) -> bool;
+
+    /// Retrieve all the stored message policies
+    fn load_policies(&mut self) -> Vec<StoredPolicy>;
+
+    /// Persist the given policy, overwriting the previous one for the same key type and algo
+    ///
+    /// Returns `false` if the policy couldn't be persisted
+    fn store_policy(&mut self, policy: &StoredPolicy) -> bool;
+
+    /// Retrieve the sealing secret, if it was provisioned
+    fn load_sealing_secret(&mut self) -> Option<SealingSecret>;
+
+    /// Persist the sealing secret
+    ///
+    /// Returns `false` if the secret couldn't be persisted
+    fn store_sealing_secret(&mut self, secret: &SealingSecret) -> bool;
+}
+
+impl<S: Storage> KeyStorage for S {
+    fn load_keys(&mut self) -> Vec<([u8; 4], Keypair)> {
+        let mut iter = self.iter();
+        let mut keys = Vec::new();
+
+        while let Some((_, key)) = iter.next::<StoredKey>() {
+            match key.into_keypair() {
+                Some(pair) => keys.push(pair),
+                None => warn!("skipping invalid stored key"),
+            }
+        }
+
+        keys
+    }
+
+    fn store_key(&mut self, key_type: [u8; 4], keypair: &Keypair) -> bool {
+        self.store(&StoredKey::new(key_type, keypair)).is_some()
+    }
+
+    fn delete_key(&mut self, key_type: [u8; 4], keypair: &Keypair) -> bool {
+        let id = key_id(key_type, keypair.algo(), keypair.public_bytes());
+
+        self.delete::<StoredKey>(id)
+    }
+
+    fn load_marks(&mut self) -> Vec<Mark> {
+        let mut iter = self.iter();
+        let mut marks = Vec::new();
+
+        while let Some((_, mark)) = iter.next::<Mark>() {
+            marks.push(mark);
+        }
+
+        marks
+    }
+
+    fn store_mark(&mut self, mark: &Mark) -> bool {
+        self.store(mark).is_some()
+    }
+
+    fn load_policies(&mut self) -> Vec<StoredPolicy> {
+        let mut iter = self.iter();
+        let mut policies = Vec::new();
+
+        while let Some((_, policy)) = iter.next::<StoredPolicy>() {
+            policies.push(policy);
+        }
+
+        policies
+    }
+
+    fn store_policy(&mut self, policy: &StoredPolicy) -> bool {
+        self.store(policy).is_some()
+    }
+
+    fn load_sealing_secret(&mut self) -> Option<SealingSecret> {
+        self.retrieve::<SealingSecret>(SECRET_ID)
+    }
+
+    fn store_sealing_secret(&mut self, secret: &SealingSecret) -> bool {
+        self.store(secret).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_key_roundtrip() {
+        for algo in [CryptoAlgo::Sr25519, CryptoAlgo::Ed25519, CryptoAlgo::Ecdsa] {
+            let keypair = Keypair::generate_new(&mut rand::thread_rng(), algo);
+
+            let stored = StoredKey::new(*b"dumm", &keypair).serialize().unwrap();
+            let (key_type, restored) = StoredKey::deserialize_owned(&stored)
+                .expect("shouldn't fail")
+                .into_keypair()
+                .expect("not a valid keypair");
+
+            assert_eq!(&key_type, b"dumm");
+            assert_eq!(restored.public_bytes(), keypair.public_bytes());
+        }
+    }
+}
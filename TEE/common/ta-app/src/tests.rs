@@ -2,18 +2,33 @@ use super::*;
 
 use crypto::{PublicKey, VRFData};
 use merlin::Transcript;
-use optee_common::Serialize;
+use optee_common::{MessagePolicy, Serialize};
 use sp_keystore::vrf::{VRFSignature, VRFTranscriptData};
 
 impl<'r> TaApp<'r> {
     fn set_keys(&mut self, keypairs: &[&Keypair]) {
-        let keys: Vec<_> = keypairs.iter().map(|k| (*k).clone()).collect();
+        //keypairs are not `Clone`, so copy them thru their secret
+        let keys: Vec<_> = keypairs
+            .iter()
+            .map(|k| Keypair::from_bytes(&k.secret_bytes(), k.algo()).unwrap())
+            .collect();
 
         let mut map = HashMap::with_hasher(Default::default());
         map.insert(KEY_TYPE, keys);
 
         self.keys = map;
     }
+
+    fn add_key(&mut self, key_type: [u8; 4], keypair: &Keypair) {
+        let keypair = Keypair::from_bytes(&keypair.secret_bytes(), keypair.algo()).unwrap();
+
+        self.keys.entry(key_type).or_default().push(keypair);
+    }
+
+    fn with_sealing_secret(mut self, secret: &[u8]) -> Self {
+        provision_sealing(&mut self, secret).expect("shouldn't fail");
+        self
+    }
 }
 
 fn keypair(algo: CryptoAlgo) -> Keypair {
@@ -24,16 +39,22 @@ fn init_logging() {
     let _ = env_logger::try_init();
 }
 
+/// Open an app on the storage, as a new session (or instance) of the TA would
+fn reopen(storage: &mut ta_common::MemoryStorage) -> TaApp<'_> {
+    TaApp::new(Box::leak(Box::new(rand::thread_rng())), storage)
+}
+
 const KEY_TYPE: [u8; 4] = *b"dumm";
 
+const SEALING_SECRET: [u8; 32] = [0x4e; 32];
+
 fn generate_new(algo: CryptoAlgo) {
     let mut app = TaApp::default();
 
     let mut input = algo.serialize().unwrap();
     input.append(&mut KEY_TYPE.serialize().unwrap());
 
-    let mut output = Vec::new();
-    output.resize(algo.pubkey_len(), 0);
+    let mut output = vec![0; algo.pubkey_len()];
 
     app.process_command(CommandId::GenerateNew, &input[..], &mut output)
         .expect("shouldn't fail");
@@ -49,6 +70,44 @@ fn verify_generate_new() {
     generate_new(CryptoAlgo::Ecdsa);
 }
 
+fn generate_from_seed(algo: CryptoAlgo, public: &str) {
+    let mut storage = ta_common::MemoryStorage::new();
+    let mut app = reopen(&mut storage);
+
+    let mut input = algo.serialize().unwrap();
+    input.append(&mut KEY_TYPE.serialize().unwrap());
+    input.append(&mut (&"//Alice").serialize().unwrap());
+
+    let mut output = vec![0u8; algo.pubkey_len()];
+
+    app.process_command(CommandId::GenerateNew, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    assert_eq!(hex::encode(&output), public);
+    assert!(app.find_associated_key(&KEY_TYPE, &output).is_some());
+
+    //keys from a seed are not persisted
+    drop(app);
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn verify_generate_from_seed() {
+    init_logging();
+    generate_from_seed(
+        CryptoAlgo::Sr25519,
+        "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d",
+    );
+    generate_from_seed(
+        CryptoAlgo::Ed25519,
+        "88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee",
+    );
+    generate_from_seed(
+        CryptoAlgo::Ecdsa,
+        "020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1",
+    );
+}
+
 fn sign_something(algo: CryptoAlgo) {
     let mut app = TaApp::default();
 
@@ -80,11 +139,32 @@ fn verify_sign() {
     sign_something(CryptoAlgo::Ecdsa);
 }
 
+#[test]
+fn sign_unknown_key() {
+    init_logging();
+    let mut app = TaApp::default();
+
+    let sk = keypair(CryptoAlgo::Ed25519);
+    app.set_keys(&[&sk]);
+
+    let other = keypair(CryptoAlgo::Ed25519);
+    assert_eq!(
+        sign_message(&mut app, KEY_TYPE, &other, b"support@zondax.ch"),
+        Err(Error::PairNotFound)
+    );
+
+    //the public key is only known for another key type
+    assert_eq!(
+        sign_message(&mut app, *b"othr", &sk, b"support@zondax.ch"),
+        Err(Error::PairNotFound)
+    );
+}
+
 fn get_public_keys<const GEN_KEYS: usize, const OUT_KEYS: usize>(algo: CryptoAlgo) {
     let mut app = TaApp::default();
 
     //fill with keys
-    let set = vec![keypair(algo); GEN_KEYS];
+    let set: Vec<_> = (0..GEN_KEYS).map(|_| keypair(algo)).collect();
     app.set_keys(set.iter().collect::<Vec<_>>().as_slice());
 
     let mut input = algo.serialize().unwrap();
@@ -95,7 +175,11 @@ fn get_public_keys<const GEN_KEYS: usize, const OUT_KEYS: usize>(algo: CryptoAlg
     let result = app.process_command(CommandId::GetKeys, &input[..], &mut output);
 
     if GEN_KEYS > OUT_KEYS {
-        result.expect_err("not out of memory");
+        assert_eq!(result, Err(Error::ShortBuffer));
+
+        //the TA reports the required size
+        let required = app.required_output_size().unwrap();
+        assert_eq!(required, 8 + (8 + algo.pubkey_len()) * GEN_KEYS);
         return;
     } else {
         result.expect("shouldn't fail");
@@ -238,3 +322,871 @@ fn verify_vrf_sign() {
 
     assert!(vrf_verify);
 }
+
+fn vrf_sign(app: &mut TaApp, public: &[u8]) -> Result<(), Error> {
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.extend_from_slice(public);
+    input.append(&mut get_vrf().1.serialize().unwrap());
+
+    let mut output = vec![0; VRFSignature::len()];
+    app.process_command(CommandId::VrfSign, &input[..], &mut output)
+}
+
+#[test]
+fn vrf_sign_unknown_key() {
+    init_logging();
+    let mut app = TaApp::default();
+    app.set_keys(&[&keypair(CryptoAlgo::Sr25519)]);
+
+    let other = keypair(CryptoAlgo::Sr25519);
+    assert_eq!(
+        vrf_sign(&mut app, other.public_bytes()),
+        Err(Error::PairNotFound)
+    );
+}
+
+#[test]
+fn vrf_sign_not_sr25519() {
+    init_logging();
+    let mut app = TaApp::default();
+
+    let ed = keypair(CryptoAlgo::Ed25519);
+    app.set_keys(&[&ed]);
+
+    assert_eq!(
+        vrf_sign(&mut app, ed.public_bytes()),
+        Err(Error::KeyNotSupported)
+    );
+}
+
+fn keys_persist(algo: CryptoAlgo) {
+    let mut storage = ta_common::MemoryStorage::new();
+
+    let mut input = algo.serialize().unwrap();
+    input.append(&mut KEY_TYPE.serialize().unwrap());
+
+    let mut public = vec![0u8; algo.pubkey_len()];
+
+    {
+        let mut app = reopen(&mut storage);
+        app.process_command(CommandId::GenerateNew, &input[..], &mut public)
+            .expect("shouldn't fail");
+    }
+    assert_eq!(storage.len(), 1);
+
+    let app = reopen(&mut storage);
+    assert!(app.find_associated_key(&KEY_TYPE, &public).is_some());
+}
+
+#[test]
+fn verify_keys_persist() {
+    init_logging();
+    keys_persist(CryptoAlgo::Sr25519);
+    keys_persist(CryptoAlgo::Ed25519);
+    keys_persist(CryptoAlgo::Ecdsa);
+}
+
+fn import_key(suri: &str, public: &str, algo: CryptoAlgo) {
+    let mut app = TaApp::default();
+
+    let public = hex::decode(public).unwrap();
+
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut (&suri).serialize().unwrap());
+    input.append(&mut public.serialize().unwrap());
+
+    let mut output = [0u8; 1];
+    app.process_command(CommandId::ImportKey, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    assert_eq!(output[0], algo.into());
+    assert!(app.find_associated_key(&KEY_TYPE, &public).is_some());
+}
+
+#[test]
+fn verify_import_key() {
+    init_logging();
+    import_key(
+        "//Alice",
+        "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d",
+        CryptoAlgo::Sr25519,
+    );
+    import_key(
+        "//Alice//stash",
+        "be5ddb1579b72e84524fc29e78609e3caf42e85aa118ebfe0b0ad404b5bdd25f",
+        CryptoAlgo::Sr25519,
+    );
+    import_key(
+        "//Alice",
+        "88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee",
+        CryptoAlgo::Ed25519,
+    );
+    import_key(
+        "//Alice",
+        "020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1",
+        CryptoAlgo::Ecdsa,
+    );
+}
+
+#[test]
+fn import_key_wrong_public() {
+    let mut app = TaApp::default();
+
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut (&"//Bob").serialize().unwrap());
+    input.append(&mut vec![0u8; 32].serialize().unwrap());
+
+    let mut output = [0u8; 1];
+    let result = app.process_command(CommandId::ImportKey, &input[..], &mut output);
+
+    assert!(matches!(result, Err(Error::BadParameters)));
+}
+
+#[test]
+fn verify_keys() {
+    init_logging();
+    let mut app = TaApp::default();
+
+    let set = [
+        keypair(CryptoAlgo::Sr25519),
+        keypair(CryptoAlgo::Ed25519),
+        keypair(CryptoAlgo::Ecdsa),
+    ];
+    app.set_keys(set.iter().collect::<Vec<_>>().as_slice());
+
+    let input = KEY_TYPE.serialize().unwrap();
+    let mut output = vec![0u8; 1024];
+
+    app.process_command(CommandId::Keys, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    let (_, keys): (_, Vec<CryptoPublicPair>) =
+        DeserializeVariable::deserialize_variable(&output).expect("can't deserialize keys");
+
+    assert_eq!(keys.len(), set.len());
+    for key in set.iter() {
+        assert!(keys
+            .iter()
+            .any(|pair| pair.algo == key.algo() && pair.public_key == key.public_bytes()));
+    }
+}
+
+#[test]
+fn verify_supported_keys() {
+    init_logging();
+    let mut app = TaApp::default();
+
+    let known = keypair(CryptoAlgo::Ed25519);
+    app.set_keys(&[&known]);
+
+    let query = vec![
+        CryptoPublicPair {
+            algo: CryptoAlgo::Ed25519,
+            public_key: known.public_bytes().to_vec(),
+        },
+        //same public key but different algo
+        CryptoPublicPair {
+            algo: CryptoAlgo::Sr25519,
+            public_key: known.public_bytes().to_vec(),
+        },
+        CryptoPublicPair {
+            algo: CryptoAlgo::Ecdsa,
+            public_key: vec![0; 33],
+        },
+    ];
+
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut query.serialize().unwrap());
+    let mut output = vec![0u8; input.len()];
+
+    app.process_command(CommandId::SupportedKeys, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    let (_, keys): (_, Vec<CryptoPublicPair>) =
+        DeserializeVariable::deserialize_variable(&output).expect("can't deserialize keys");
+
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].algo, CryptoAlgo::Ed25519);
+    assert_eq!(keys[0].public_key, known.public_bytes());
+}
+
+fn sign_with_many(cmd: CommandId, known: &Keypair, pairs: &[CryptoPublicPair]) -> Vec<u8> {
+    let mut app = TaApp::default();
+    app.set_keys(&[known]);
+
+    let msg = &b"support@zondax.ch"[..];
+
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut pairs.to_vec().serialize().unwrap());
+    input.append(&mut (&msg).serialize().unwrap());
+
+    let mut output = vec![0u8; 1024];
+    app.process_command(cmd, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    output
+}
+
+#[test]
+fn verify_sign_with_any() {
+    init_logging();
+    let known = keypair(CryptoAlgo::Ecdsa);
+
+    let unknown = CryptoPublicPair {
+        algo: CryptoAlgo::Sr25519,
+        public_key: vec![0; 32],
+    };
+    let pair = CryptoPublicPair {
+        algo: CryptoAlgo::Ecdsa,
+        public_key: known.public_bytes().to_vec(),
+    };
+
+    let output = sign_with_many(CommandId::SignWithAny, &known, &[unknown, pair]);
+
+    let (size, signer) = CryptoPublicPair::deserialize_variable(&output).unwrap();
+    assert_eq!(signer.public_key, known.public_bytes());
+
+    let sig: &[u8] = Deserialize::deserialize(&output[size..]).unwrap();
+    assert!(known.to_public_key().verify(&b"support@zondax.ch"[..], sig));
+}
+
+#[test]
+fn verify_sign_with_all() {
+    init_logging();
+    let known = keypair(CryptoAlgo::Sr25519);
+
+    let pair = CryptoPublicPair {
+        algo: CryptoAlgo::Sr25519,
+        public_key: known.public_bytes().to_vec(),
+    };
+    //same public key, but wrong algo
+    let wrong_algo = CryptoPublicPair {
+        algo: CryptoAlgo::Ed25519,
+        public_key: known.public_bytes().to_vec(),
+    };
+
+    let output = sign_with_many(CommandId::SignWithAll, &known, &[pair, wrong_algo]);
+    let mut output = &output[..];
+
+    assert_eq!(util::read_and_advance_u64(&mut output).unwrap(), 2);
+
+    let code = util::read_and_advance(&mut output, 4).unwrap();
+    assert_eq!(code, &[0; 4]);
+    let sig: &[u8] = Deserialize::deserialize(output).unwrap();
+    util::advance_slice(&mut output, 8 + sig.len()).unwrap();
+    assert!(known.to_public_key().verify(&b"support@zondax.ch"[..], sig));
+
+    let code = util::read_and_advance(&mut output, 4).unwrap();
+    assert_eq!(code, &(Error::PairNotFound as u32).to_le_bytes()[..]);
+    let sig: &[u8] = Deserialize::deserialize(output).unwrap();
+    assert!(sig.is_empty());
+}
+
+fn generate_persisted(app: &mut TaApp, algo: CryptoAlgo) -> CryptoPublicPair {
+    let mut input = algo.serialize().unwrap();
+    input.append(&mut KEY_TYPE.serialize().unwrap());
+
+    let mut public = vec![0u8; algo.pubkey_len()];
+    app.process_command(CommandId::GenerateNew, &input[..], &mut public)
+        .expect("shouldn't fail");
+
+    CryptoPublicPair {
+        algo,
+        public_key: public,
+    }
+}
+
+fn key_type_with_pair(pair: Option<&CryptoPublicPair>) -> Vec<u8> {
+    let mut input = KEY_TYPE.serialize().unwrap();
+    if let Some(pair) = pair {
+        input.append(&mut pair.serialize().unwrap());
+    }
+
+    input
+}
+
+#[test]
+fn verify_delete_key() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+
+    {
+        let mut app = reopen(&mut storage);
+        let sr = generate_persisted(&mut app, CryptoAlgo::Sr25519);
+        let _ = generate_persisted(&mut app, CryptoAlgo::Ecdsa);
+
+        let mut output = [0u8; 8];
+        let input = key_type_with_pair(Some(&sr));
+        app.process_command(CommandId::DeleteKey, &input, &mut output)
+            .expect("shouldn't fail");
+        assert_eq!(u64::from_le_bytes(output), 1);
+        assert!(app.find_associated_key(&KEY_TYPE, &sr.public_key).is_none());
+
+        //already deleted
+        let result = app.process_command(CommandId::DeleteKey, &input, &mut output);
+        assert_eq!(result, Err(Error::PairNotFound));
+    }
+    assert_eq!(storage.len(), 1);
+
+    let mut app = reopen(&mut storage);
+    let mut output = [0u8; 8];
+    app.process_command(CommandId::DeleteKey, &key_type_with_pair(None), &mut output)
+        .expect("shouldn't fail");
+    assert_eq!(u64::from_le_bytes(output), 1);
+
+    drop(app);
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn verify_rotate_key() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+
+    let (old, new) = {
+        let mut app = reopen(&mut storage);
+        let old = vec![
+            generate_persisted(&mut app, CryptoAlgo::Ed25519),
+            generate_persisted(&mut app, CryptoAlgo::Ecdsa),
+        ];
+
+        //not enough space, nothing should be rotated
+        let mut output = vec![0u8; 8];
+        let input = key_type_with_pair(None);
+        let result = app.process_command(CommandId::RotateKey, &input, &mut output);
+        assert_eq!(result, Err(Error::ShortBuffer));
+        let required = app.required_output_size().unwrap();
+        assert!(app
+            .find_associated_key(&KEY_TYPE, &old[0].public_key)
+            .is_some());
+
+        let mut output = vec![0u8; required];
+        app.process_command(CommandId::RotateKey, &input, &mut output)
+            .expect("shouldn't fail");
+
+        let (_, new): (_, Vec<CryptoPublicPair>) =
+            DeserializeVariable::deserialize_variable(&output).unwrap();
+        (old, new)
+    };
+
+    assert_eq!(new.len(), 2);
+    assert_eq!(storage.len(), 2);
+
+    let app = reopen(&mut storage);
+    for (old, new) in old.iter().zip(new.iter()) {
+        assert_eq!(old.algo, new.algo);
+        assert!(app
+            .find_associated_key(&KEY_TYPE, &old.public_key)
+            .is_none());
+        assert!(app
+            .find_associated_key(&KEY_TYPE, &new.public_key)
+            .is_some());
+    }
+}
+
+fn provision_sealing(app: &mut TaApp, secret: &[u8]) -> Result<(), Error> {
+    app.process_command(CommandId::ProvisionSealing, secret, &mut [])
+}
+
+fn export_sealed(app: &mut TaApp, key: Option<&CryptoPublicPair>) -> Vec<u8> {
+    let input = match key {
+        Some(pair) => key_type_with_pair(Some(pair)),
+        None => Vec::new(),
+    };
+
+    //not enough space, the TA tells us how much is needed
+    let mut output = vec![0u8; 8];
+    let result = app.process_command(CommandId::ExportSealed, &input, &mut output);
+    assert_eq!(result, Err(Error::ShortBuffer));
+
+    let required = app.required_output_size().unwrap();
+    let mut output = vec![0u8; required];
+    app.process_command(CommandId::ExportSealed, &input, &mut output)
+        .expect("shouldn't fail");
+
+    let blob: &[u8] = Deserialize::deserialize(&output).unwrap();
+    blob.to_vec()
+}
+
+fn import_sealed(app: &mut TaApp, blob: &[u8]) -> Result<u64, Error> {
+    let input = (&blob).serialize().unwrap();
+
+    let mut output = [0u8; 8];
+    app.process_command(CommandId::ImportSealed, &input, &mut output)?;
+
+    Ok(u64::from_le_bytes(output))
+}
+
+#[test]
+fn verify_sealed_roundtrip() {
+    init_logging();
+    let mut source = TaApp::default().with_sealing_secret(&SEALING_SECRET);
+    let sr = generate_persisted(&mut source, CryptoAlgo::Sr25519);
+    let ec = generate_persisted(&mut source, CryptoAlgo::Ecdsa);
+
+    let single = export_sealed(&mut source, Some(&ec));
+    let all = export_sealed(&mut source, None);
+
+    //a different instance, with a different storage
+    let mut storage = ta_common::MemoryStorage::new();
+    {
+        let mut target = reopen(&mut storage).with_sealing_secret(&SEALING_SECRET);
+
+        assert_eq!(import_sealed(&mut target, &single), Ok(1));
+        assert!(target
+            .find_associated_key(&KEY_TYPE, &ec.public_key)
+            .is_some());
+        assert!(target
+            .find_associated_key(&KEY_TYPE, &sr.public_key)
+            .is_none());
+
+        //the already imported key is not duplicated
+        assert_eq!(import_sealed(&mut target, &all), Ok(1));
+        assert!(target
+            .find_associated_key(&KEY_TYPE, &sr.public_key)
+            .is_some());
+    }
+
+    //imported keys are persisted, together with the secret
+    assert_eq!(storage.len(), 3);
+}
+
+#[test]
+fn import_sealed_tampered() {
+    init_logging();
+    let mut app = TaApp::default().with_sealing_secret(&SEALING_SECRET);
+    let ed = generate_persisted(&mut app, CryptoAlgo::Ed25519);
+
+    let mut blob = export_sealed(&mut app, Some(&ed));
+    blob[20] ^= 0xFF;
+
+    assert_eq!(import_sealed(&mut app, &blob), Err(Error::Security));
+}
+
+#[test]
+fn sealing_not_provisioned() {
+    init_logging();
+    let mut source = TaApp::default().with_sealing_secret(&SEALING_SECRET);
+    let ed = generate_persisted(&mut source, CryptoAlgo::Ed25519);
+    let blob = export_sealed(&mut source, Some(&ed));
+
+    let mut app = TaApp::default();
+    generate_persisted(&mut app, CryptoAlgo::Ed25519);
+
+    let mut output = vec![0u8; 1024];
+    let result = app.process_command(CommandId::ExportSealed, &[], &mut output);
+    assert_eq!(result, Err(Error::NotSupported));
+
+    assert_eq!(import_sealed(&mut app, &blob), Err(Error::NotSupported));
+}
+
+#[test]
+fn provision_sealing_once() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+    let blob = {
+        let mut app = reopen(&mut storage);
+        assert_eq!(
+            provision_sealing(&mut app, &SEALING_SECRET[1..]),
+            Err(Error::BadFormat)
+        );
+        assert_eq!(provision_sealing(&mut app, &SEALING_SECRET), Ok(()));
+
+        //the same secret again is fine, it can't be replaced though
+        assert_eq!(provision_sealing(&mut app, &SEALING_SECRET), Ok(()));
+        assert_eq!(provision_sealing(&mut app, &[0; 32]), Err(Error::BadState));
+
+        let ed = generate_persisted(&mut app, CryptoAlgo::Ed25519);
+        export_sealed(&mut app, Some(&ed))
+    };
+
+    //the secret is persisted
+    let mut app = reopen(&mut storage);
+    assert_eq!(import_sealed(&mut app, &blob), Ok(0));
+}
+
+fn sign_message(
+    app: &mut TaApp,
+    key_type: [u8; 4],
+    keypair: &Keypair,
+    msg: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let algo = keypair.algo();
+
+    let mut input = algo.serialize().unwrap();
+    input.append(&mut key_type.serialize().unwrap());
+    input.append(&mut (&keypair.public_bytes()).serialize().unwrap());
+    input.append(&mut (&msg).serialize().unwrap());
+
+    let mut output = vec![0u8; algo.signature_len()];
+    app.process_command(CommandId::SignMessage, &input[..], &mut output)?;
+
+    Ok(output)
+}
+
+fn grandpa_vote(stage: u8, target: u8, round: u64, set_id: u64) -> Vec<u8> {
+    let mut vote = vec![stage];
+    vote.extend_from_slice(&[target; 32]);
+    vote.extend_from_slice(&42u32.to_le_bytes());
+    vote.extend_from_slice(&round.to_le_bytes());
+    vote.extend_from_slice(&set_id.to_le_bytes());
+
+    vote
+}
+
+#[test]
+fn verify_grandpa_slashing_protection() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+    let ed = keypair(CryptoAlgo::Ed25519);
+    let gran = slashing::GRANDPA;
+
+    {
+        let mut app = reopen(&mut storage).with_slashing_protection();
+        app.add_key(gran, &ed);
+
+        assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 1, 0)).is_ok());
+        //signing the same vote again is fine
+        assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 1, 0)).is_ok());
+        //a different prevote in the same round
+        assert_eq!(
+            sign_message(&mut app, gran, &ed, &grandpa_vote(0, 2, 1, 0)),
+            Err(Error::Equivocation)
+        );
+        //precommits are tracked separately
+        assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(1, 2, 1, 0)).is_ok());
+
+        assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 2, 0)).is_ok());
+        assert_eq!(
+            sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 1, 0)),
+            Err(Error::Equivocation)
+        );
+        //a new set restarts the rounds
+        assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 1, 1)).is_ok());
+
+        assert_eq!(
+            sign_message(&mut app, gran, &ed, b"not a vote"),
+            Err(Error::BadFormat)
+        );
+    }
+
+    //the marks are persisted
+    let mut app = reopen(&mut storage).with_slashing_protection();
+    app.add_key(gran, &ed);
+    assert_eq!(
+        sign_message(&mut app, gran, &ed, &grandpa_vote(0, 2, 1, 1)),
+        Err(Error::Equivocation)
+    );
+}
+
+fn babe_claim(app: &mut TaApp, sr: &Keypair, slot: u64) -> Result<(), Error> {
+    use sp_keystore::vrf::VRFTranscriptValue;
+    use std::borrow::Cow;
+
+    let vrf = VRFTranscriptData {
+        label: Cow::from(&b"BABE"[..]),
+        items: vec![
+            (
+                Cow::from(&b"slot number"[..]),
+                VRFTranscriptValue::U64(slot),
+            ),
+            (Cow::from(&b"current epoch"[..]), VRFTranscriptValue::U64(0)),
+            (
+                Cow::from(&b"chain randomness"[..]),
+                VRFTranscriptValue::Bytes(vec![0; 32]),
+            ),
+        ],
+    };
+
+    let mut input = slashing::BABE.serialize().unwrap();
+    input.extend_from_slice(sr.public_bytes());
+    input.append(&mut vrf.serialize().unwrap());
+
+    let mut output = vec![0; VRFSignature::len()];
+    app.process_command(CommandId::VrfSign, &input[..], &mut output)
+}
+
+#[test]
+fn verify_babe_slashing_protection() {
+    init_logging();
+    let mut app = TaApp::default().with_slashing_protection();
+    let sr = keypair(CryptoAlgo::Sr25519);
+    let babe = slashing::BABE;
+    app.add_key(babe, &sr);
+
+    //nothing to check a seal against before the first claim
+    assert!(sign_message(&mut app, babe, &sr, &[9; 32]).is_ok());
+
+    assert_eq!(babe_claim(&mut app, &sr, 10), Ok(()));
+    assert!(sign_message(&mut app, babe, &sr, &[1; 32]).is_ok());
+    //a different block in the same slot
+    assert_eq!(
+        sign_message(&mut app, babe, &sr, &[2; 32]),
+        Err(Error::Equivocation)
+    );
+
+    //the same slot can be claimed again, but not an older one
+    assert_eq!(babe_claim(&mut app, &sr, 10), Ok(()));
+    assert_eq!(babe_claim(&mut app, &sr, 9), Err(Error::Equivocation));
+
+    assert_eq!(babe_claim(&mut app, &sr, 11), Ok(()));
+    assert!(sign_message(&mut app, babe, &sr, &[2; 32]).is_ok());
+}
+
+struct TestClock(std::cell::Cell<u64>);
+
+impl ta_common::Clock for TestClock {
+    fn now_ms(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+fn set_policy(
+    app: &mut TaApp,
+    algo: CryptoAlgo,
+    policy: Option<&MessagePolicy>,
+) -> Result<(), Error> {
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut algo.serialize().unwrap());
+    if let Some(policy) = policy {
+        input.append(&mut policy.serialize().unwrap());
+    }
+
+    app.process_command(CommandId::SetPolicy, &input[..], &mut [])
+}
+
+#[test]
+fn verify_policy() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+    let clock = TestClock(std::cell::Cell::new(1000));
+    let sr = keypair(CryptoAlgo::Sr25519);
+    let ed = keypair(CryptoAlgo::Ed25519);
+
+    let policy = MessagePolicy {
+        lengths: vec![(2, 4), (8, 8)],
+        prefixes: vec![vec![0x04], vec![0x05, 0x00]],
+        rate_limit: Some(optee_common::RateLimit {
+            max: 2,
+            period_ms: 100,
+        }),
+    };
+
+    {
+        let mut app = reopen(&mut storage).with_clock(&clock);
+        app.set_keys(&[&sr, &ed]);
+        assert_eq!(
+            set_policy(&mut app, CryptoAlgo::Sr25519, Some(&policy)),
+            Ok(())
+        );
+
+        assert_eq!(
+            sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 5]),
+            Err(Error::AccessDenied)
+        );
+        assert_eq!(
+            sign_message(&mut app, KEY_TYPE, &sr, &[0x05, 0x01]),
+            Err(Error::AccessDenied)
+        );
+        assert!(sign_message(&mut app, KEY_TYPE, &sr, &[0x05, 0x00, 0x01]).is_ok());
+        assert!(sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 8]).is_ok());
+        //rate limit reached, until the period is over
+        assert_eq!(
+            sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 2]),
+            Err(Error::AccessDenied)
+        );
+        clock.0.set(1100);
+        assert!(sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 2]).is_ok());
+
+        //other algorithms are not restricted
+        assert!(sign_message(&mut app, KEY_TYPE, &ed, b"support@zondax.ch").is_ok());
+    }
+
+    //the policy is persisted, but rate limits can't be checked without a clock
+    let mut app = reopen(&mut storage);
+    app.set_keys(&[&sr]);
+    assert_eq!(
+        sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 2]),
+        Err(Error::AccessDenied)
+    );
+
+    //the policy can't be removed nor relaxed
+    assert_eq!(
+        set_policy(&mut app, CryptoAlgo::Sr25519, None),
+        Err(Error::AccessDenied)
+    );
+    let relaxed = MessagePolicy {
+        rate_limit: None,
+        ..policy.clone()
+    };
+    assert_eq!(
+        set_policy(&mut app, CryptoAlgo::Sr25519, Some(&relaxed)),
+        Err(Error::AccessDenied)
+    );
+    assert_eq!(
+        sign_message(&mut app, KEY_TYPE, &sr, &[0x04; 2]),
+        Err(Error::AccessDenied)
+    );
+
+    //but it can be made stricter
+    let stricter = MessagePolicy {
+        prefixes: vec![vec![0x04]],
+        ..policy
+    };
+    assert_eq!(
+        set_policy(&mut app, CryptoAlgo::Sr25519, Some(&stricter)),
+        Ok(())
+    );
+}
+
+#[test]
+fn denied_signatures_not_counted() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+    let clock = TestClock(std::cell::Cell::new(1000));
+    let ed = keypair(CryptoAlgo::Ed25519);
+    let gran = slashing::GRANDPA;
+
+    let mut app = reopen(&mut storage)
+        .with_clock(&clock)
+        .with_slashing_protection();
+    app.add_key(gran, &ed);
+
+    let policy = MessagePolicy {
+        rate_limit: Some(optee_common::RateLimit {
+            max: 2,
+            period_ms: 100,
+        }),
+        ..Default::default()
+    };
+    let mut input = gran.serialize().unwrap();
+    input.append(&mut CryptoAlgo::Ed25519.serialize().unwrap());
+    input.append(&mut policy.serialize().unwrap());
+    app.process_command(CommandId::SetPolicy, &input[..], &mut [])
+        .expect("shouldn't fail");
+
+    assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 1, 0)).is_ok());
+    //refused by the slashing protection
+    assert_eq!(
+        sign_message(&mut app, gran, &ed, &grandpa_vote(0, 2, 1, 0)),
+        Err(Error::Equivocation)
+    );
+    //so the quota isn't used up yet
+    assert!(sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 2, 0)).is_ok());
+    assert_eq!(
+        sign_message(&mut app, gran, &ed, &grandpa_vote(0, 1, 3, 0)),
+        Err(Error::AccessDenied)
+    );
+}
+
+#[test]
+fn sign_with_many_short_buffer() {
+    init_logging();
+    let mut storage = ta_common::MemoryStorage::new();
+    let clock = TestClock(std::cell::Cell::new(1000));
+    let ed = keypair(CryptoAlgo::Ed25519);
+    let pairs = vec![CryptoPublicPair {
+        algo: CryptoAlgo::Ed25519,
+        public_key: ed.public_bytes().to_vec(),
+    }];
+
+    let mut app = reopen(&mut storage).with_clock(&clock);
+    app.set_keys(&[&ed]);
+    let policy = MessagePolicy {
+        rate_limit: Some(optee_common::RateLimit {
+            max: 2,
+            period_ms: 100,
+        }),
+        ..Default::default()
+    };
+    assert_eq!(
+        set_policy(&mut app, CryptoAlgo::Ed25519, Some(&policy)),
+        Ok(())
+    );
+
+    let mut input = KEY_TYPE.serialize().unwrap();
+    input.append(&mut pairs.serialize().unwrap());
+    input.append(&mut (&&b"support@zondax.ch"[..]).serialize().unwrap());
+
+    for cmd in [CommandId::SignWithAny, CommandId::SignWithAll].iter() {
+        let mut output = vec![0u8; 8];
+        let result = app.process_command(*cmd, &input[..], &mut output);
+        assert_eq!(result, Err(Error::ShortBuffer));
+
+        //nothing was signed, so the rate limit isn't used up
+        let required = app.required_output_size().unwrap();
+        let mut output = vec![0u8; required];
+        app.process_command(*cmd, &input[..], &mut output)
+            .expect("shouldn't fail");
+    }
+    assert_eq!(
+        sign_message(&mut app, KEY_TYPE, &ed, b"support@zondax.ch"),
+        Err(Error::AccessDenied)
+    );
+}
+
+fn session_has_key(session: SessionId, public: &[u8]) -> bool {
+    let pairs = vec![HasKeysPair {
+        key_type: KEY_TYPE,
+        public_key: public.to_vec(),
+    }];
+    let input = pairs.serialize().unwrap();
+    let mut output = [0];
+
+    borrow_mut_app(session)
+        .expect("session is open")
+        .process_command(CommandId::HasKeys, &input[..], &mut output)
+        .expect("shouldn't fail");
+
+    output[0] == 1
+}
+
+#[test]
+fn verify_sessions() {
+    init_logging();
+
+    //no app to open a session with
+    assert!(open_session().is_err());
+
+    create_app(
+        Box::leak(Box::new(rand::thread_rng())),
+        Box::leak(Box::new(ta_common::MemoryStorage::new())),
+        Box::leak(Box::new(TestClock(Default::default()))),
+    )
+    .expect("first app");
+    assert!(create_app(
+        Box::leak(Box::new(rand::thread_rng())),
+        Box::leak(Box::new(ta_common::MemoryStorage::new())),
+        Box::leak(Box::new(TestClock(Default::default()))),
+    )
+    .is_err());
+
+    let first = open_session().expect("app was created");
+    let second = open_session().expect("app was created");
+    assert_ne!(first, second);
+
+    let algo = CryptoAlgo::Ed25519;
+    let mut input = algo.serialize().unwrap();
+    input.append(&mut KEY_TYPE.serialize().unwrap());
+    let mut public = vec![0; algo.pubkey_len()];
+
+    borrow_mut_app(first)
+        .expect("session is open")
+        .process_command(CommandId::GenerateNew, &input[..], &mut public)
+        .expect("shouldn't fail");
+
+    //the key is shared, and closing a session doesn't affect the others
+    assert!(session_has_key(second, &public));
+    close_session(first);
+    assert!(borrow_mut_app(first).is_none());
+    assert!(session_has_key(second, &public));
+
+    //new sessions still see the keys
+    let third = open_session().expect("app is still there");
+    assert!(third != first && third != second);
+    assert!(session_has_key(third, &public));
+
+    destroy_app();
+    assert!(borrow_mut_app(second).is_none());
+    assert!(open_session().is_err());
+}
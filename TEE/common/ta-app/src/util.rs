@@ -4,6 +4,7 @@ use optee_common::TeeErrorCode as Error;
 use rand_core::{CryptoRng, RngCore};
 
 //used in TaApp to specify multiple traits for the trait object
+#[allow(clippy::upper_case_acronyms)]
 pub trait CSPRNG: CryptoRng + RngCore {}
 impl<R: CryptoRng + RngCore> CSPRNG for R {}
 
@@ -94,10 +95,10 @@ mod tests {
 
     #[test]
     fn read_amt() {
-        let input = vec![42; 42];
+        let input = [42; 42];
 
         let read = read_and_advance(&mut &input[..], 42).expect("shouldn't error");
 
-        assert_eq!(&read[..], &input[..])
+        assert_eq!(read, &input[..])
     }
 }
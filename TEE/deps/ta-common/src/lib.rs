@@ -1,18 +1,31 @@
+//! Common traits definitions for the TA and its dependencies
+#![no_std]
+
+extern crate no_std_compat as std;
+
 use optee_common::{DeserializeOwned, Serialize};
 
 ///Trait that marks a type as a storage object
 pub trait Object: Serialize + DeserializeOwned {
     /// The object ID
     type ID: ObjID;
+
+    /// Retrieve the ID this object should be stored with
+    fn id(&self) -> Self::ID;
 }
 
 ///Marker trait for types usable as ObjID
 pub trait ObjID: Serialize + DeserializeOwned {}
 
+impl<const N: usize> ObjID for [u8; N] {}
+
 pub trait Storage: Sized {
     type Iter: StorageEnumerator<Store = Self>;
 
-    fn store<T: Object>(&mut self, val: &T) -> T::ID;
+    /// Store the object, overwriting any previous object with the same ID
+    ///
+    /// Returns `None` if the object couldn't be persisted
+    fn store<T: Object>(&mut self, val: &T) -> Option<T::ID>;
 
     fn retrieve<T: Object>(&mut self, id: T::ID) -> Option<T>;
 
@@ -26,5 +39,17 @@ pub trait Storage: Sized {
 pub trait StorageEnumerator {
     type Store: Storage;
 
+    /// Retrieve the next object in the storage
+    ///
+    /// Objects which can't be deserialized as `T` are skipped
     fn next<T: Object>(&mut self) -> Option<(T::ID, T)>;
 }
+
+/// Source of time for the TA
+pub trait Clock {
+    /// Milliseconds elapsed since an arbitrary point in time, never decreasing
+    fn now_ms(&self) -> u64;
+}
+
+mod memory;
+pub use memory::MemoryStorage;
@@ -0,0 +1,119 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use super::*;
+use optee_common::SerializeFixed;
+use zeroize::Zeroizing;
+
+/// Volatile storage backed by a map
+///
+/// Meant to be used where no persistent storage is available (ie: tests),
+/// the contents survive as long as the instance is kept alive.
+/// Objects can hold secrets, so they are wiped once removed or replaced
+#[derive(Default, Clone)]
+pub struct MemoryStorage {
+    objects: BTreeMap<Vec<u8>, Zeroizing<Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of objects currently stored
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl Storage for MemoryStorage {
+    type Iter = MemoryEnumerator;
+
+    fn store<T: Object>(&mut self, val: &T) -> Option<T::ID> {
+        let id = val.id();
+
+        let key = id.serialize().ok()?;
+        let data = Zeroizing::new(val.serialize().ok()?);
+        self.objects.insert(key, data);
+
+        Some(id)
+    }
+
+    fn retrieve<T: Object>(&mut self, id: T::ID) -> Option<T> {
+        let key = id.serialize().ok()?;
+
+        self.objects
+            .get(&key)
+            .and_then(|data| T::deserialize_owned(data).ok())
+    }
+
+    fn delete<T: Object>(&mut self, id: T::ID) -> bool {
+        match id.serialize() {
+            Ok(key) => self.objects.remove(&key).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    fn rename<T: Object>(&mut self, old_id: T::ID, new_id: T::ID) -> bool {
+        let (old_key, new_key) = match (old_id.serialize(), new_id.serialize()) {
+            (Ok(old), Ok(new)) => (old, new),
+            _ => return false,
+        };
+
+        //same as the GP spec, renaming to an existing ID is a conflict
+        if self.objects.contains_key(&new_key) {
+            return false;
+        }
+
+        match self.objects.remove(&old_key) {
+            Some(data) => {
+                self.objects.insert(new_key, data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn iter(&self) -> Self::Iter {
+        //take a snapshot, so the storage can be modified while enumerating
+        let objects: Vec<_> = self
+            .objects
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        MemoryEnumerator {
+            objects: objects.into_iter(),
+        }
+    }
+}
+
+/// Enumerator over a snapshot of a [`MemoryStorage`]
+pub struct MemoryEnumerator {
+    objects: std::vec::IntoIter<(Vec<u8>, Zeroizing<Vec<u8>>)>,
+}
+
+impl StorageEnumerator for MemoryEnumerator {
+    type Store = MemoryStorage;
+
+    fn next<T: Object>(&mut self) -> Option<(T::ID, T)> {
+        for (key, data) in &mut self.objects {
+            //skip objects which aren't a `T`
+            let id = match T::ID::deserialize_owned(&key) {
+                Ok(id) if T::ID::len() == key.len() => id,
+                _ => continue,
+            };
+
+            if let Ok(obj) = T::deserialize_owned(&data) {
+                return Some((id, obj));
+            }
+        }
+
+        None
+    }
+}
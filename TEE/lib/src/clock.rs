@@ -0,0 +1,20 @@
+//! Implementation of `ta_common::Clock` on top of the GlobalPlatform time API
+
+use ta_common::Clock;
+use zondee_utee::wrapper::raw::{TEE_GetSystemTime, TEE_Time};
+
+/// System time of the TEE, which is not affected by the REE
+pub struct OpteeClock;
+
+impl Clock for OpteeClock {
+    fn now_ms(&self) -> u64 {
+        let mut time = TEE_Time {
+            seconds: 0,
+            millis: 0,
+        };
+
+        unsafe { TEE_GetSystemTime(&mut time) };
+
+        time.seconds as u64 * 1000 + time.millis as u64
+    }
+}
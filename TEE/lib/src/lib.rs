@@ -3,7 +3,7 @@
 #![cfg_attr(not(test), feature(alloc_error_handler))]
 
 use optee_common::{CommandId, HandleTaCommand};
-use ta_app::borrow_mut_app;
+use ta_app::{borrow_mut_app, SessionId};
 use zondee_utee::wrapper::{
     raw::{TEE_Param, TEE_PARAM_TYPES},
     ParamType, Parameters, TaErrorCode as Error,
@@ -14,9 +14,17 @@ use zondee_utee::wrapper::{
 /// The signature of the functions are found in the librustee_ta.h file
 mod optee;
 
+/// This module contains the persistent storage used by the TA to keep the keys
+mod storage;
+
+/// This module contains the clock used by the TA to enforce rate limits
+mod clock;
+
 #[macro_use]
 extern crate log;
 
+extern crate alloc;
+
 use core::convert::TryFrom;
 
 #[cfg(not(test))]
@@ -43,7 +51,12 @@ mod lang_items {
     }
 }
 
-pub fn invoke_command(cmd_id: u32, param_types: u32, parameters: &mut [TEE_Param; 4]) -> u32 {
+pub fn invoke_command(
+    session: SessionId,
+    cmd_id: u32,
+    param_types: u32,
+    parameters: &mut [TEE_Param; 4],
+) -> u32 {
     let mut params = Parameters::from_raw(parameters, param_types);
 
     // This check would depend on the opretion defined by cmd_id
@@ -81,14 +94,18 @@ pub fn invoke_command(cmd_id: u32, param_types: u32, parameters: &mut [TEE_Param
     // The inner handler could have persistance data or state that is required along the execution of the program
     // so instead of creating a handler on every command_invocation, we create the handler when the session is opened.
     // Such session remains open until the TEEC closes it, at this point the handler must be already created.
-    borrow_mut_app()
-        .as_mut()
-        .map_or(Error::ItemNotFound as u32, |ta_handler| {
-            if let Err(e) = ta_handler.process_command(cmd, imemref.buffer(), omemref.buffer()) {
-                error!("[ERROR] processing command failure: {:?}", e);
-                e as _
-            } else {
-                0
+    borrow_mut_app(session).map_or(Error::ItemNotFound as u32, |mut ta_handler| {
+        if let Err(e) = ta_handler.process_command(cmd, imemref.buffer(), omemref.buffer()) {
+            error!("[ERROR] processing command failure: {:?}", e);
+
+            // As per the GlobalPlatform spec, the client learns the size the output
+            // should have from the updated size of the memref
+            if let Some(required) = ta_handler.required_output_size() {
+                omemref.set_updated_size(required);
             }
-        })
+            e as _
+        } else {
+            0
+        }
+    })
 }
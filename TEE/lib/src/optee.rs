@@ -1,4 +1,7 @@
-use ta_app::{close_session, open_session};
+use crate::{clock::OpteeClock, storage::OpteeStorage};
+use core::ffi::c_void;
+
+use ta_app::{close_session, create_app, destroy_app, open_session, SessionId};
 use zondee_utee::wrapper::{raw::TEE_Param, TEELogger, TEERng, TaErrorCode as Error};
 
 //The signatures of the following functions are defined in the framework's rustee_ta.h file
@@ -9,12 +12,16 @@ pub extern "C" fn RUSTEE_Create() -> u32 {
 
     trace!("Creating");
 
-    // Only one instance is allowed to run by session
-    if let Err(_) = open_session(TEERng::new_static()) {
+    // Only one instance is allowed, shared by all the sessions
+    if let Err(_) = create_app(
+        TEERng::new_static(),
+        OpteeStorage::new_static(),
+        &OpteeClock,
+    ) {
         error!("[ERROR] can not create inner handler");
         Error::AccessDenied as _
     } else {
-        info!("[INFO] *****Session created");
+        info!("[INFO] *****App created");
         0
     }
 }
@@ -22,33 +29,45 @@ pub extern "C" fn RUSTEE_Create() -> u32 {
 #[no_mangle]
 pub extern "C" fn RUSTEE_Destroy() -> () {
     trace!("Destroying");
+    destroy_app();
 }
 
 #[no_mangle]
 pub extern "C" fn RUSTEE_OpenSession(
     _param_types: u32,
     _params: &mut [TEE_Param; 4],
-    _session_context: *const u8,
+    session_context: *mut *mut c_void,
 ) -> u32 {
     trace!("Opening session");
 
-    0
+    match open_session() {
+        Ok(session) => {
+            // The id is all we need to find the session's handler again
+            unsafe { *session_context = session as *mut c_void };
+            info!("[INFO] *****Session {} opened", session);
+            0
+        }
+        Err(_) => {
+            error!("[ERROR] can not open session");
+            Error::AccessDenied as _
+        }
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn RUSTEE_CloseSession(_session_context: *const u8) -> () {
+pub extern "C" fn RUSTEE_CloseSession(session_context: *mut c_void) -> () {
     trace!("Closing session");
-    close_session();
+    close_session(session_context as SessionId);
 }
 
 #[no_mangle]
 pub extern "C" fn RUSTEE_InvokeCommand(
-    _session_context: *const u8,
+    session_context: *mut c_void,
     cmd_id: u32,
     param_types: u32,
     params: &mut [TEE_Param; 4],
 ) -> u32 {
     trace!("Invoked command");
 
-    super::invoke_command(cmd_id, param_types, params)
+    super::invoke_command(session_context as SessionId, cmd_id, param_types, params)
 }
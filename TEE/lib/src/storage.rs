@@ -0,0 +1,243 @@
+//! Implementation of `ta_common::Storage` on top of the GlobalPlatform persistent object API
+//!
+//! Objects are stored in the TA private storage, using their serialized `Object::ID` as object ID
+
+use alloc::{boxed::Box, vec};
+use core::{ffi::c_void, ptr};
+
+use optee_common::{DeserializeOwned, Serialize, SerializeFixed};
+use ta_common::{Object, Storage, StorageEnumerator};
+use zeroize::Zeroizing;
+use zondee_utee::wrapper::raw::{
+    TEE_AllocatePersistentObjectEnumerator, TEE_CloseAndDeletePersistentObject1, TEE_CloseObject,
+    TEE_CreatePersistentObject, TEE_FreePersistentObjectEnumerator, TEE_GetNextPersistentObject,
+    TEE_ObjectEnumHandle, TEE_ObjectHandle, TEE_ObjectInfo, TEE_OpenPersistentObject,
+    TEE_ReadObjectData, TEE_RenamePersistentObject, TEE_StartPersistentObjectEnumerator,
+    TEE_DATA_FLAG_ACCESS_READ, TEE_DATA_FLAG_ACCESS_WRITE, TEE_DATA_FLAG_ACCESS_WRITE_META,
+    TEE_DATA_FLAG_OVERWRITE, TEE_STORAGE_PRIVATE,
+};
+
+/// Maximum length of an object ID, as defined by the GP spec
+const OBJECT_ID_MAX_LEN: usize = 64;
+
+const TEE_SUCCESS: u32 = 0;
+
+/// Persistent storage private to the TA
+pub struct OpteeStorage;
+
+impl OpteeStorage {
+    pub fn new_static() -> &'static mut Self {
+        Box::leak(Box::new(Self))
+    }
+
+    /// Open the object with the given id and the given access flags
+    fn open(id: &[u8], flags: u32) -> Option<ObjectHandle> {
+        let mut handle: TEE_ObjectHandle = ptr::null_mut();
+
+        let res = unsafe {
+            TEE_OpenPersistentObject(
+                TEE_STORAGE_PRIVATE,
+                id.as_ptr() as *const c_void,
+                id.len() as u32,
+                flags,
+                &mut handle,
+            )
+        };
+
+        if res == TEE_SUCCESS {
+            Some(ObjectHandle(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Read an object of type `T` from the storage
+    fn read<T: Object>(id: &[u8]) -> Option<T> {
+        let object = Self::open(id, TEE_DATA_FLAG_ACCESS_READ)?;
+
+        //objects can hold secrets, wipe the buffer once deserialized
+        let mut data = Zeroizing::new(vec![0u8; T::len()]);
+        let mut count = 0u32;
+
+        let res = unsafe {
+            TEE_ReadObjectData(
+                object.0,
+                data.as_mut_ptr() as *mut c_void,
+                data.len() as u32,
+                &mut count,
+            )
+        };
+
+        if res != TEE_SUCCESS || count as usize != data.len() {
+            return None;
+        }
+
+        T::deserialize_owned(&data).ok()
+    }
+}
+
+/// Wrapper for an open object handle, closed on drop
+struct ObjectHandle(TEE_ObjectHandle);
+
+impl ObjectHandle {
+    /// Close and delete the object
+    fn delete(self) -> bool {
+        let res = unsafe { TEE_CloseAndDeletePersistentObject1(self.0) };
+        core::mem::forget(self);
+
+        res == TEE_SUCCESS
+    }
+}
+
+impl Drop for ObjectHandle {
+    fn drop(&mut self) {
+        unsafe { TEE_CloseObject(self.0) }
+    }
+}
+
+impl Storage for OpteeStorage {
+    type Iter = OpteeEnumerator;
+
+    fn store<T: Object>(&mut self, val: &T) -> Option<T::ID> {
+        let id = val.id();
+
+        let raw_id = id.serialize().ok()?;
+        //wiped once the object is created
+        let data = Zeroizing::new(val.serialize().ok()?);
+
+        let mut handle: TEE_ObjectHandle = ptr::null_mut();
+        let res = unsafe {
+            TEE_CreatePersistentObject(
+                TEE_STORAGE_PRIVATE,
+                raw_id.as_ptr() as *const c_void,
+                raw_id.len() as u32,
+                TEE_DATA_FLAG_ACCESS_READ
+                    | TEE_DATA_FLAG_ACCESS_WRITE
+                    | TEE_DATA_FLAG_ACCESS_WRITE_META
+                    | TEE_DATA_FLAG_OVERWRITE,
+                ptr::null_mut(),
+                data.as_ptr() as *const c_void,
+                data.len() as u32,
+                &mut handle,
+            )
+        };
+
+        if res != TEE_SUCCESS {
+            error!("[ERROR] unable to create persistent object: {:x}", res);
+            return None;
+        }
+        drop(ObjectHandle(handle));
+
+        Some(id)
+    }
+
+    fn retrieve<T: Object>(&mut self, id: T::ID) -> Option<T> {
+        let raw_id = id.serialize().ok()?;
+
+        Self::read(&raw_id)
+    }
+
+    fn delete<T: Object>(&mut self, id: T::ID) -> bool {
+        let raw_id = match id.serialize() {
+            Ok(id) => id,
+            Err(_) => return false,
+        };
+
+        Self::open(&raw_id, TEE_DATA_FLAG_ACCESS_WRITE_META)
+            .map(ObjectHandle::delete)
+            .unwrap_or(false)
+    }
+
+    fn rename<T: Object>(&mut self, old_id: T::ID, new_id: T::ID) -> bool {
+        let (old_id, new_id) = match (old_id.serialize(), new_id.serialize()) {
+            (Ok(old), Ok(new)) => (old, new),
+            _ => return false,
+        };
+
+        let object = match Self::open(&old_id, TEE_DATA_FLAG_ACCESS_WRITE_META) {
+            Some(object) => object,
+            None => return false,
+        };
+
+        let res = unsafe {
+            TEE_RenamePersistentObject(
+                object.0,
+                new_id.as_ptr() as *const c_void,
+                new_id.len() as u32,
+            )
+        };
+
+        res == TEE_SUCCESS
+    }
+
+    fn iter(&self) -> Self::Iter {
+        let mut handle: TEE_ObjectEnumHandle = ptr::null_mut();
+
+        let res = unsafe { TEE_AllocatePersistentObjectEnumerator(&mut handle) };
+        if res != TEE_SUCCESS {
+            error!("[ERROR] unable to allocate enumerator: {:x}", res);
+            return OpteeEnumerator(None);
+        }
+
+        //the handle is freed on drop, even if starting fails
+        let enumerator = OpteeEnumerator(Some(handle));
+
+        let res = unsafe { TEE_StartPersistentObjectEnumerator(handle, TEE_STORAGE_PRIVATE) };
+        if res != TEE_SUCCESS {
+            //no objects in storage yet
+            return OpteeEnumerator(None);
+        }
+
+        enumerator
+    }
+}
+
+/// Enumerator over the objects in the TA private storage
+pub struct OpteeEnumerator(Option<TEE_ObjectEnumHandle>);
+
+impl StorageEnumerator for OpteeEnumerator {
+    type Store = OpteeStorage;
+
+    fn next<T: Object>(&mut self) -> Option<(T::ID, T)> {
+        let handle = self.0?;
+
+        loop {
+            let mut info: TEE_ObjectInfo = unsafe { core::mem::zeroed() };
+            let mut raw_id = [0u8; OBJECT_ID_MAX_LEN];
+            let mut id_len = raw_id.len() as u32;
+
+            let res = unsafe {
+                TEE_GetNextPersistentObject(
+                    handle,
+                    &mut info,
+                    raw_id.as_mut_ptr() as *mut c_void,
+                    &mut id_len,
+                )
+            };
+
+            if res != TEE_SUCCESS {
+                //TEE_ERROR_ITEM_NOT_FOUND: no more objects
+                return None;
+            }
+
+            let raw_id = &raw_id[..id_len as usize];
+            //skip objects which aren't a `T`
+            let id = match T::ID::deserialize_owned(raw_id) {
+                Ok(id) if T::ID::len() == raw_id.len() => id,
+                _ => continue,
+            };
+
+            if let Some(obj) = OpteeStorage::read(raw_id) {
+                return Some((id, obj));
+            }
+        }
+    }
+}
+
+impl Drop for OpteeEnumerator {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            unsafe { TEE_FreePersistentObjectEnumerator(handle) }
+        }
+    }
+}
@@ -19,6 +19,13 @@ pub trait HandleTaCommand {
         input: &[u8],
         output: &mut [u8],
     ) -> Result<(), TeeErrorCode>;
+
+    /// Size the output should have had, after the last command failed with `ShortBuffer`
+    ///
+    /// Reported to the client as the updated size of the output memref
+    fn required_output_size(&self) -> Option<usize> {
+        None
+    }
 }
 
 pub mod serde;
@@ -1,4 +1,4 @@
-use crate::HasKeysPair;
+use crate::{CryptoPublicPair, HasKeysPair, MessagePolicy, RateLimit};
 
 use super::*;
 
@@ -47,7 +47,7 @@ impl Serialize for &str {
     fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
         let slice = self.as_bytes();
         let len = slice.len();
-        slice.serialize().map_err(|_| len)
+        (&slice).serialize().map_err(|_| len)
     }
 }
 
@@ -106,6 +106,19 @@ pub(crate) mod vec {
         }
     }
 
+    impl Serialize for Vec<CryptoPublicPair> {
+        type Error = Infallible;
+
+        fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+            let mut v = (self.len() as u64).to_le_bytes().to_vec();
+            for inner in self.iter() {
+                v.append(&mut inner.serialize()?)
+            }
+
+            Ok(v)
+        }
+    }
+
     impl<T> Serialize for &Vec<T>
     where
         Vec<T>: Serialize,
@@ -213,6 +226,122 @@ impl DeserializeVariable for HasKeysPair {
     }
 }
 
+impl Serialize for CryptoPublicPair {
+    type Error = Infallible;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        //create vec for algo : len : key
+        let mut pair = vec![0; 1];
+        self.algo.serialize_fixed(&mut pair[..1]).unwrap();
+        pair.append(&mut (&self.public_key.as_slice()).serialize().unwrap());
+
+        Ok(pair)
+    }
+}
+
+impl DeserializeVariable for CryptoPublicPair {
+    type ErrorVariable = ();
+
+    fn deserialize_variable(input: &[u8]) -> Result<(usize, Self), Self::ErrorVariable> {
+        //check for algo + len
+        if input.len() < 1 + 8 {
+            return Err(());
+        }
+        let algo = crate::CryptoAlgo::deserialize_owned(&input[..1])?;
+
+        let key_len = {
+            let mut array = [0; 8];
+            array.copy_from_slice(&input[1..9]);
+            u64::from_le_bytes(array) as usize
+        };
+
+        let total_bytes = key_len.checked_add(1 + 8).ok_or(())?;
+        //check for algo + len + key_len
+        if input.len() < total_bytes {
+            return Err(());
+        }
+
+        let key: &[u8] = Deserialize::deserialize(&input[1..]).unwrap();
+        let public_key = key.to_vec();
+
+        Ok((total_bytes, CryptoPublicPair { algo, public_key }))
+    }
+}
+
+impl Serialize for MessagePolicy {
+    type Error = Infallible;
+
+    fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
+        //n of ranges : ranges as (min, max)
+        let mut v = (self.lengths.len() as u64).to_le_bytes().to_vec();
+        for (min, max) in self.lengths.iter() {
+            v.extend_from_slice(&min.to_le_bytes()[..]);
+            v.extend_from_slice(&max.to_le_bytes()[..]);
+        }
+
+        v.append(&mut self.prefixes.serialize().unwrap());
+
+        //flag : max : period
+        match self.rate_limit {
+            Some(RateLimit { max, period_ms }) => {
+                v.push(1);
+                v.extend_from_slice(&max.to_le_bytes()[..]);
+                v.extend_from_slice(&period_ms.to_le_bytes()[..]);
+            }
+            None => v.push(0),
+        }
+
+        Ok(v)
+    }
+}
+
+impl DeserializeVariable for MessagePolicy {
+    type ErrorVariable = ();
+
+    fn deserialize_variable(input: &[u8]) -> Result<(usize, Self), Self::ErrorVariable> {
+        let (mut total_bytes, lengths): (_, Vec<[[u8; 8]; 2]>) =
+            DeserializeVariable::deserialize_variable(input).map_err(|_| ())?;
+        let lengths = lengths
+            .into_iter()
+            .map(|[min, max]| (u64::from_le_bytes(min), u64::from_le_bytes(max)))
+            .collect();
+
+        let input = &input[total_bytes..];
+        let (size, prefixes): (_, Vec<Vec<u8>>) =
+            DeserializeVariable::deserialize_variable(input).map_err(|_| ())?;
+        total_bytes += size;
+
+        let input = &input[size..];
+        let rate_limit = match input.first().ok_or(())? {
+            0 => None,
+            1 => {
+                let max: [u8; 4] =
+                    DeserializeOwned::deserialize_owned(&input[1..]).map_err(|_| ())?;
+                let period: [u8; 8] =
+                    DeserializeOwned::deserialize_owned(input.get(5..).ok_or(())?)
+                        .map_err(|_| ())?;
+                total_bytes += 4 + 8;
+
+                Some(RateLimit {
+                    max: u32::from_le_bytes(max),
+                    period_ms: u64::from_le_bytes(period),
+                })
+            }
+            _ => return Err(()),
+        };
+        total_bytes += 1;
+
+        Ok((
+            total_bytes,
+            MessagePolicy {
+                lengths,
+                prefixes,
+                rate_limit,
+            },
+        ))
+    }
+}
+
 impl Serialize for crate::CryptoAlgo {
     type Error = Infallible;
 
@@ -228,7 +357,7 @@ impl Serialize for crate::CryptoAlgo {
 
 mod cow {
     use super::*;
-    use std::borrow::{Cow, ToOwned};
+    use std::borrow::Cow;
 
     // impl<'c, T> Serialize for Cow<'c, [T]>
     // where
@@ -266,7 +395,7 @@ mod cow {
 
         fn serialize(&self) -> Result<Vec<u8>, Self::Error> {
             match self {
-                Cow::Borrowed(borr) => (&borr).serialize(),
+                Cow::Borrowed(borr) => borr.serialize(),
                 Cow::Owned(own) => (&own).serialize(),
             }
         }
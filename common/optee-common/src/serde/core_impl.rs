@@ -19,7 +19,7 @@ impl<'de> Deserialize<'de> for &'de [u8] {
             return Err(len);
         }
 
-        Ok(&input[..len as usize])
+        Ok(&input[..len])
     }
 }
 
@@ -58,7 +58,7 @@ impl DeserializeOwned for u8 {
     type ErrorOwned = usize;
 
     fn deserialize_owned(input: &[u8]) -> Result<Self, Self::ErrorOwned> {
-        input.get(0).cloned().ok_or(1)
+        input.first().cloned().ok_or(1)
     }
 }
 ///Error enum when dealing with arrays
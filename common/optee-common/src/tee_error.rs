@@ -52,13 +52,15 @@ pub enum TeeErrorCode {
     Unavailable = 0xFFFF3028,
     /// Unknown error.   
     Unknown = 0xFFFF3029,
+    /// Signing would be an equivocation (double signing)
+    Equivocation = 0xFFFF302A,
 }
 
 impl From<u32> for TeeErrorCode {
     fn from(code: u32) -> Self {
         match code {
-            0xFFFF0000..=0xFFFF0011 => unsafe { core::mem::transmute(code) },
-            0xFFFF3024..=0xFFFF3028 => unsafe { core::mem::transmute(code) },
+            0xFFFF0000..=0xFFFF0011 => unsafe { core::mem::transmute::<u32, Self>(code) },
+            0xFFFF3024..=0xFFFF302A => unsafe { core::mem::transmute::<u32, Self>(code) },
             _ => Self::Unknown,
         }
     }
@@ -98,6 +100,7 @@ impl TeeErrorCode {
             TeeErrorCode::ValidationError => "Validation error",
             TeeErrorCode::Unavailable => "Keystore unavailable",
             TeeErrorCode::Unknown => "Unknown error.",
+            TeeErrorCode::Equivocation => "Signing would be an equivocation",
         }
     }
 }
@@ -157,3 +160,20 @@ impl From<TeeErrorCode> for TeeError {
         TeeError { code: kind as u32 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_roundtrip() {
+        let codes = (0xFFFF0000..=0xFFFF0011).chain(0xFFFF3024..=0xFFFF302A);
+        for code in codes {
+            assert_eq!(TeeErrorCode::from(code) as u32, code);
+        }
+
+        assert_eq!(TeeErrorCode::from(0xFFFF0012), TeeErrorCode::Unknown);
+        assert_eq!(TeeErrorCode::from(0xFFFF302B), TeeErrorCode::Unknown);
+        assert_eq!(TeeErrorCode::from(0), TeeErrorCode::Unknown);
+    }
+}
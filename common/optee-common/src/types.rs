@@ -9,6 +9,17 @@ pub enum CommandId {
     SignMessage,
     HasKeys,
     VrfSign,
+    ImportKey,
+    Keys,
+    SupportedKeys,
+    SignWithAny,
+    SignWithAll,
+    DeleteKey,
+    RotateKey,
+    ExportSealed,
+    ImportSealed,
+    SetPolicy,
+    ProvisionSealing,
 }
 
 impl std::convert::TryFrom<u32> for CommandId {
@@ -21,18 +32,29 @@ impl std::convert::TryFrom<u32> for CommandId {
             2 => Ok(CommandId::SignMessage),
             3 => Ok(CommandId::HasKeys),
             4 => Ok(CommandId::VrfSign),
+            5 => Ok(CommandId::ImportKey),
+            6 => Ok(CommandId::Keys),
+            7 => Ok(CommandId::SupportedKeys),
+            8 => Ok(CommandId::SignWithAny),
+            9 => Ok(CommandId::SignWithAll),
+            10 => Ok(CommandId::DeleteKey),
+            11 => Ok(CommandId::RotateKey),
+            12 => Ok(CommandId::ExportSealed),
+            13 => Ok(CommandId::ImportSealed),
+            14 => Ok(CommandId::SetPolicy),
+            15 => Ok(CommandId::ProvisionSealing),
             _ => Err(()),
         }
     }
 }
 
-impl Into<u32> for CommandId {
-    fn into(self) -> u32 {
-        self as _
+impl From<CommandId> for u32 {
+    fn from(cmd: CommandId) -> Self {
+        cmd as _
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 ///Represents the type of algorithm to use for the key
 pub enum CryptoAlgo {
@@ -41,9 +63,9 @@ pub enum CryptoAlgo {
     Ecdsa
 }
 
-impl Into<u8> for CryptoAlgo {
-    fn into(self) -> u8 {
-        self as _
+impl From<CryptoAlgo> for u8 {
+    fn from(algo: CryptoAlgo) -> Self {
+        algo as _
     }
 }
 
@@ -78,3 +100,43 @@ mod haskeyspair {
 
 #[cfg(feature = "alloc")]
 pub use haskeyspair::HasKeysPair;
+
+#[cfg(feature = "alloc")]
+mod cryptopublicpair {
+    use super::*;
+
+    ///Represents a public key together with the algorithm it's for
+    #[derive(Debug, Clone)]
+    pub struct CryptoPublicPair {
+        pub algo: CryptoAlgo,
+        pub public_key: Vec<u8>,
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub use cryptopublicpair::CryptoPublicPair;
+
+#[cfg(feature = "alloc")]
+mod messagepolicy {
+    use super::*;
+
+    ///Restrictions on the messages signed with the keys of a key type and algorithm
+    #[derive(Debug, Clone, Default)]
+    pub struct MessagePolicy {
+        ///Allowed (inclusive) ranges of the message length, any length if empty
+        pub lengths: Vec<(u64, u64)>,
+        ///The message must start with one of these (SCALE encoded) prefixes, any if empty
+        pub prefixes: Vec<Vec<u8>>,
+        pub rate_limit: Option<RateLimit>,
+    }
+
+    ///Maximum number of signatures in a period of time
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RateLimit {
+        pub max: u32,
+        pub period_ms: u64,
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub use messagepolicy::{MessagePolicy, RateLimit};
@@ -0,0 +1,165 @@
+//! Connection to the server, shared by all the concurrent requests
+//!
+//! Requests are queued to a writer task, and a reader task hands each response
+//! to the request with the same ID, so callers only ever wait on a future.
+//! Both tasks are dropped with the connection, closing the socket
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use futures::future::{AbortHandle, Abortable};
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, oneshot};
+use zkms_ductile::{
+    transport::{Receiver, Sender},
+    RemoteKeystore, RemoteKeystoreResponse, Request, Response,
+};
+
+/// Requests waiting for their response, by ID
+#[derive(Default)]
+struct Pending {
+    waiting: HashMap<u64, oneshot::Sender<RemoteKeystoreResponse>>,
+    /// Set once the connection is gone, so no more requests are accepted
+    closed: bool,
+    /// Stops the tasks reading and writing the connection
+    tasks: Option<AbortHandle>,
+}
+
+impl Pending {
+    fn close(&mut self) {
+        self.closed = true;
+        //dropping the senders wakes up the waiting requests
+        self.waiting.clear();
+        //even if they're stuck waiting on the server
+        if let Some(tasks) = self.tasks.take() {
+            tasks.abort();
+        }
+    }
+}
+
+/// A submitted request, which stops waiting for its response when dropped
+pub(crate) struct Waiting {
+    id: u64,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Waiting {
+    /// Give up on the connection the request was sent on, so a new one is opened
+    pub fn close_connection(&self) {
+        self.pending.lock().close();
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        //a late response will be discarded
+        self.pending.lock().waiting.remove(&self.id);
+    }
+}
+
+pub(crate) struct ZKMSClient {
+    outgoing: mpsc::UnboundedSender<Request>,
+    next_id: AtomicU64,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl ZKMSClient {
+    /// Serve the connection on the current runtime
+    pub fn new(tx: Sender<Request>, rx: Receiver<Response>) -> Self {
+        let (outgoing, requests) = mpsc::unbounded_channel();
+        let (tasks, registration) = AbortHandle::new_pair();
+        let pending = Arc::new(Mutex::new(Pending {
+            tasks: Some(tasks),
+            ..Pending::default()
+        }));
+
+        let connection = futures::future::join(
+            Self::dispatch(rx, pending.clone()),
+            Self::write(tx, requests, pending.clone()),
+        );
+        tokio::spawn(Abortable::new(connection, registration));
+
+        Self {
+            outgoing,
+            //0 is used by the handshake
+            next_id: AtomicU64::new(1),
+            pending,
+        }
+    }
+
+    /// Hand each response to its request, until the connection is closed
+    async fn dispatch(mut rx: Receiver<Response>, pending: Arc<Mutex<Pending>>) {
+        while let Ok(Response { id, response }) = rx.recv().await {
+            match pending.lock().waiting.remove(&id) {
+                //the request might have given up already
+                Some(waiter) => {
+                    let _ = waiter.send(response);
+                }
+                None => warn!(id, "response to unknown request"),
+            }
+        }
+
+        debug!("connection to TEE keystore closed");
+        pending.lock().close();
+    }
+
+    /// Send the queued requests, until the client is dropped or the connection is closed
+    async fn write(
+        mut tx: Sender<Request>,
+        mut requests: mpsc::UnboundedReceiver<Request>,
+        pending: Arc<Mutex<Pending>>,
+    ) {
+        while let Some(request) = requests.recv().await {
+            if let Err(e) = tx.send(&request).await {
+                warn!("unable to send request: {:?}", e);
+                break;
+            }
+        }
+
+        pending.lock().close();
+    }
+
+    /// If the connection is gone, so it has to be opened again
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().closed
+    }
+
+    /// Queue the request, returning where its response will be received
+    pub fn submit(
+        &self,
+        request: RemoteKeystore,
+    ) -> Result<(oneshot::Receiver<RemoteKeystoreResponse>, Waiting), String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (waiter, response) = oneshot::channel();
+
+        {
+            let mut pending = self.pending.lock();
+            if pending.closed {
+                return Err("connection closed".to_string());
+            }
+            pending.waiting.insert(id, waiter);
+        }
+        let waiting = Waiting {
+            id,
+            pending: self.pending.clone(),
+        };
+
+        self.outgoing
+            .send(Request { id, request })
+            .map_err(|_| "connection closed".to_string())?;
+
+        Ok((response, waiting))
+    }
+}
+
+impl Drop for ZKMSClient {
+    fn drop(&mut self) {
+        //a replaced connection is closed right away, not when the server notices
+        self.pending.lock().close();
+    }
+}
@@ -4,7 +4,7 @@ use async_trait::async_trait;
 #[async_trait]
 impl sp_keystore::CryptoStore for TEEKeystore {
     async fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
-        self.sr25519_public_keys(id)
+        self.sr25519_public_keys(id).await
     }
 
     async fn sr25519_generate_new(
@@ -12,11 +12,11 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<sr25519::Public, sp_keystore::Error> {
-        self.sr25519_generate_new(id, seed)
+        self.sr25519_generate_new(id, seed).await
     }
 
     async fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
-        self.ed25519_public_keys(id)
+        self.ed25519_public_keys(id).await
     }
 
     async fn ed25519_generate_new(
@@ -24,11 +24,11 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ed25519::Public, sp_keystore::Error> {
-        self.ed25519_generate_new(id, seed)
+        self.ed25519_generate_new(id, seed).await
     }
 
     async fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
-        self.ecdsa_public_keys(id)
+        self.ecdsa_public_keys(id).await
     }
 
     async fn ecdsa_generate_new(
@@ -36,11 +36,11 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ecdsa::Public, sp_keystore::Error> {
-        self.ecdsa_generate_new(id, seed)
+        self.ecdsa_generate_new(id, seed).await
     }
 
     async fn insert_unknown(&self, id: KeyTypeId, suri: &str, public: &[u8]) -> Result<(), ()> {
-        self.insert_unknown(id, suri, public)
+        self.insert_unknown(id, suri, public).await
     }
 
     async fn supported_keys(
@@ -48,15 +48,15 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         id: KeyTypeId,
         keys: Vec<CryptoTypePublicPair>,
     ) -> Result<Vec<CryptoTypePublicPair>, sp_keystore::Error> {
-        self.supported_keys(id, keys)
+        self.supported_keys(id, keys).await
     }
 
     async fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, sp_keystore::Error> {
-        self.keys(id)
+        self.keys(id).await
     }
 
     async fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
-        self.has_keys(public_keys)
+        self.has_keys(public_keys).await
     }
 
     async fn sign_with(
@@ -65,7 +65,25 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         key: &CryptoTypePublicPair,
         msg: &[u8],
     ) -> Result<Vec<u8>, sp_keystore::Error> {
-        self.sign_with(id, key, msg)
+        self.sign_with(id, key, msg).await
+    }
+
+    async fn sign_with_any(
+        &self,
+        id: KeyTypeId,
+        keys: Vec<CryptoTypePublicPair>,
+        msg: &[u8],
+    ) -> Result<(CryptoTypePublicPair, Vec<u8>), sp_keystore::Error> {
+        self.sign_with_any(id, keys, msg).await
+    }
+
+    async fn sign_with_all(
+        &self,
+        id: KeyTypeId,
+        keys: Vec<CryptoTypePublicPair>,
+        msg: &[u8],
+    ) -> Result<Vec<Result<Vec<u8>, sp_keystore::Error>>, ()> {
+        self.sign_with_all(id, keys, msg).await
     }
 
     async fn sr25519_vrf_sign(
@@ -75,6 +93,7 @@ impl sp_keystore::CryptoStore for TEEKeystore {
         transcript_data: sp_keystore::vrf::VRFTranscriptData,
     ) -> Result<sp_keystore::vrf::VRFSignature, sp_keystore::Error> {
         self.sr25519_vrf_sign(key_type, public, transcript_data)
+            .await
         // Err(Error::Unavailable)
     }
 }
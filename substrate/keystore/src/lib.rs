@@ -1,19 +1,79 @@
-use parking_lot::RwLock;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
 use sp_core::{
     crypto::{CryptoTypePublicPair, KeyTypeId},
     ecdsa, ed25519, sr25519,
 };
 use sp_keystore::Error;
-use tokio::runtime::Handle;
+use tokio::{
+    runtime::Handle,
+    sync::{oneshot, RwLock, RwLockReadGuard},
+    time::{delay_for, timeout},
+};
 use url::Url;
-use zkms_ductile::{RemoteKeystore, RemoteKeystoreResponse};
+use zkms_ductile::{
+    auth,
+    transport::{self, Channel},
+    RemoteKeystore, RemoteKeystoreResponse, Request, Response,
+};
 
 #[macro_use]
 extern crate tracing;
 
-struct ZKMSClient {
-    pub tx: ductile::ChannelSender<RemoteKeystore>,
-    pub rx: ductile::ChannelReceiver<RemoteKeystoreResponse>,
+mod client;
+use client::{Waiting, ZKMSClient};
+
+/// How long to wait for a response when not configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Attempts of a read only request, which can be safely repeated
+const READ_ATTEMPTS: usize = 3;
+/// Wait after the first failed connection attempt, doubled after each failure
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// When the next connection attempt is allowed, after some failed
+#[derive(Debug, Default)]
+struct Backoff {
+    delay: Option<Duration>,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    /// Time left before the next attempt
+    fn remaining(&self) -> Option<Duration> {
+        self.next_attempt
+            .and_then(|next| next.checked_duration_since(Instant::now()))
+    }
+
+    fn failed(&mut self) {
+        let delay = match self.delay {
+            Some(delay) => std::cmp::min(delay * 2, MAX_BACKOFF),
+            None => MIN_BACKOFF,
+        };
+        self.delay = Some(delay);
+        self.next_attempt = Some(Instant::now() + delay);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// If the request can be sent again without side effects
+fn is_read_only(request: &RemoteKeystore) -> bool {
+    matches!(
+        request,
+        RemoteKeystore::Sr25519PublicKeys(_)
+            | RemoteKeystore::Ed25519PublicKeys(_)
+            | RemoteKeystore::EcdsaPublicKeys(_)
+            | RemoteKeystore::Keys(_)
+            | RemoteKeystore::SupportedKeys { .. }
+            | RemoteKeystore::HasKeys(_)
+    )
 }
 
 /// A remote keystore
@@ -24,6 +84,16 @@ pub struct TEEKeystore {
 
     url: Url,
 
+    /// Key to encrypt the channel with
+    enc_key: Option<[u8; 32]>,
+    /// Name and key to authenticate with
+    auth: Option<(String, auth::Key)>,
+
+    /// How long to wait for each response
+    timeout: Duration,
+    /// Delay of the reconnection attempts
+    backoff: Mutex<Backoff>,
+
     /// Handle to the tokio runtime
     runtime: Handle,
 }
@@ -35,19 +105,82 @@ impl std::fmt::Debug for TEEKeystore {
 }
 
 impl TEEKeystore {
-    pub fn connect(&self) -> ductile::Result<()> {
-        if self.client.read().as_ref().is_none() {
-            debug!("creating new connection to TEE keystore");
-            let host = self.url.host_str().expect("Invalid ip address");
-            let port = self.url.port().expect("Invalid valid port");
-            let (tx, rx) = ductile::connect_channel(format!("{}:{}", host, port))?;
-
-            let handle = ZKMSClient { tx, rx };
-            self.client.write().replace(handle);
-            Ok(())
-        } else {
-            Ok(())
+    /// Connect to the server, unless connected already
+    ///
+    /// A closed connection is opened again, but after a failed attempt
+    /// the next one is only made once the backoff delay has passed
+    ///
+    /// Requests wait for the connection without blocking their thread
+    pub async fn connect(&self) -> Result<(), String> {
+        if let Some(client) = self.client.read().await.as_ref() {
+            if !client.is_closed() {
+                return Ok(());
+            }
+        }
+
+        let mut client = self.client.write().await;
+        //someone else might have connected meanwhile
+        if let Some(client) = client.as_ref() {
+            if !client.is_closed() {
+                return Ok(());
+            }
+            warn!("connection to TEE keystore lost");
+        }
+
+        //only one task connects at a time, since it holds the client
+        if let Some(remaining) = self.backoff.lock().remaining() {
+            return Err(format!("not reconnecting for {:?}", remaining));
+        }
+
+        *client = None;
+        match self.open().await {
+            Ok(handle) => {
+                self.backoff.lock().reset();
+                *client = Some(handle);
+                Ok(())
+            }
+            Err(e) => {
+                self.backoff.lock().failed();
+                Err(e)
+            }
+        }
+    }
+
+    /// Open a new connection, giving up if it's not established within the timeout
+    async fn open(&self) -> Result<ZKMSClient, String> {
+        debug!("creating new connection to TEE keystore");
+        let host = self.url.host_str().expect("Invalid ip address");
+        let port = self.url.port().expect("Invalid valid port");
+        let addr = format!("{}:{}", host, port);
+
+        //dropping the handshake closes its socket
+        let handshake = Self::handshake(&addr, self.enc_key.as_ref(), self.auth.as_ref());
+        let (tx, rx) = match timeout(self.timeout, handshake).await {
+            Ok(channel) => channel?,
+            Err(_) => return Err(format!("unable to connect within {:?}", self.timeout)),
+        };
+
+        Ok(ZKMSClient::new(tx, rx))
+    }
+
+    /// Connect to `addr`, and authenticate if there's a client configured
+    async fn handshake(
+        addr: &str,
+        enc_key: Option<&[u8; 32]>,
+        auth: Option<&(String, auth::Key)>,
+    ) -> Result<Channel<Request, Response>, String> {
+        let (mut tx, mut rx) = transport::connect(addr, enc_key)
+            .await
+            .map_err(|e| format!("unable to connect: {:?}", e))?;
+
+        //the server must prove to know our key too, before we send anything else
+        if let Some((client, key)) = auth {
+            auth::client_handshake(client, key, &mut tx, &mut rx)
+                .await
+                .map_err(|e| format!("unable to authenticate: {:?}", e))?;
         }
+
+        Ok((tx, rx))
     }
 
     #[instrument]
@@ -68,398 +201,316 @@ impl TEEKeystore {
         Ok(Self {
             client: RwLock::default(),
             url,
+            enc_key: None,
+            auth: None,
+            timeout: DEFAULT_TIMEOUT,
+            backoff: Mutex::default(),
             runtime,
         })
     }
+
+    /// Encrypt the channel with the given key, which must be the one of the server
+    pub fn with_encryption(mut self, key: [u8; 32]) -> Self {
+        self.enc_key = Some(key);
+        self
+    }
+
+    /// Authenticate with the server as `client`, see [`auth`]
+    pub fn with_auth(mut self, client: String, key: auth::Key) -> Self {
+        self.auth = Some((client, key));
+        self
+    }
+
+    /// Give up on a request if there's no response after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
 }
 
 impl TEEKeystore {
-    fn client(&self) -> parking_lot::RwLockReadGuard<'_, Option<ZKMSClient>> {
-        let _ = self.connect();
-        self.client.read()
+    async fn client(&self) -> RwLockReadGuard<'_, Option<ZKMSClient>> {
+        if let Err(e) = self.connect().await {
+            warn!("{}", e);
+        }
+        self.client.read().await
     }
 
-    #[instrument]
-    fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
-        let client = self.client();
-        let client = match client.as_ref() {
-            Some(client) => client,
-            None => return vec![],
+    /// Queue the request on the shared connection, connecting first if needed
+    ///
+    /// Connecting is only done once or after the connection is lost
+    async fn submit(
+        &self,
+        request: RemoteKeystore,
+    ) -> Result<(oneshot::Receiver<RemoteKeystoreResponse>, Waiting), String> {
+        match self.client().await.as_ref() {
+            Some(client) => client.submit(request),
+            None => Err("not connected".to_string()),
+        }
+    }
+
+    /// Send the request over the shared connection and wait for its response
+    ///
+    /// Other requests can be made meanwhile, from other tasks.
+    /// Read only requests are attempted again if they fail, after reconnecting if needed:
+    /// a request without response closes the connection, failing the others waiting on it
+    async fn request(&self, request: RemoteKeystore) -> Result<RemoteKeystoreResponse, ()> {
+        let attempts = if is_read_only(&request) {
+            READ_ATTEMPTS
+        } else {
+            1
         };
 
-        match client.tx.send(RemoteKeystore::Sr25519PublicKeys(id)) {
-            Err(_) => vec![],
-            Ok(_) => client
-                .rx
-                .recv()
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Sr25519PublicKeys(resp) = resp {
-                        Ok(resp)
-                    } else {
-                        //unreachable!()
-                        Ok(vec![])
+        for attempt in 1..=attempts {
+            let result = match self.submit(request.clone()).await {
+                Ok((response, waiting)) => match timeout(self.timeout, response).await {
+                    Ok(Ok(response)) => Ok(response),
+                    Ok(Err(_)) => Err("connection closed".to_string()),
+                    Err(_) => {
+                        //the server is stuck or the connection silently dropped,
+                        // either way the next request should use a new one
+                        waiting.close_connection();
+                        Err(format!("no response after {:?}", self.timeout))
                     }
-                })
-                .or_else::<(), _>(|_| Ok(vec![]))
-                .unwrap(),
+                },
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(response) => return Ok(response),
+                Err(e) => warn!(attempt, attempts, "request failed: {}", e),
+            }
+
+            //give the server some time to come back, but not more than a response
+            let remaining = self.backoff.lock().remaining();
+            match remaining {
+                Some(remaining) if remaining > self.timeout => break,
+                Some(remaining) if attempt < attempts => delay_for(remaining).await,
+                _ => {}
+            }
+        }
+
+        Err(())
+    }
+
+    /// Wait for the future in the current thread, for `SyncCryptoStore`
+    ///
+    /// When called from a worker of the runtime, its other tasks are handed to another thread
+    /// meanwhile, so the runtime keeps driving the timers and the connection the future waits on.
+    /// That's only possible on the threaded runtime, like Substrate's
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        tokio::task::block_in_place(|| self.runtime.enter(|| futures::executor::block_on(future)))
+    }
+
+    #[instrument]
+    async fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
+        match self.request(RemoteKeystore::Sr25519PublicKeys(id)).await {
+            Ok(RemoteKeystoreResponse::Sr25519PublicKeys(resp)) => resp,
+            _ => vec![],
         }
     }
 
     #[instrument]
-    fn sr25519_generate_new(
+    async fn sr25519_generate_new(
         &self,
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<sr25519::Public, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::Sr25519GenerateNew {
-            id,
-            seed: seed.map(|s| s.to_string()),
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Sr25519GenerateNew(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::Sr25519GenerateNew {
+                id,
+                seed: seed.map(|s| s.to_string()),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::Sr25519GenerateNew(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
-        let client = self.client();
-        let client = match client.as_ref() {
-            Some(client) => client,
-            None => return vec![],
-        };
-
-        match client.tx.send(RemoteKeystore::Ed25519PublicKeys(id)) {
-            Err(_) => vec![],
-            Ok(_) => client
-                .rx
-                .recv()
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Ed25519PublicKeys(resp) = resp {
-                        Ok(resp)
-                    } else {
-                        //unreachable!()
-                        Ok(vec![])
-                    }
-                })
-                .or_else::<(), _>(|_| Ok(vec![]))
-                .unwrap(),
+    async fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
+        match self.request(RemoteKeystore::Ed25519PublicKeys(id)).await {
+            Ok(RemoteKeystoreResponse::Ed25519PublicKeys(resp)) => resp,
+            _ => vec![],
         }
     }
 
     #[instrument]
-    fn ed25519_generate_new(
+    async fn ed25519_generate_new(
         &self,
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ed25519::Public, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::Ed25519GenerateNew {
-            id,
-            seed: seed.map(|s| s.to_string()),
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Ed25519GenerateNew(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::Ed25519GenerateNew {
+                id,
+                seed: seed.map(|s| s.to_string()),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::Ed25519GenerateNew(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
-        let client = self.client();
-        let client = match client.as_ref() {
-            Some(client) => client,
-            None => return vec![],
-        };
-
-        match client.tx.send(RemoteKeystore::EcdsaPublicKeys(id)) {
-            Err(_) => vec![],
-            Ok(_) => client
-                .rx
-                .recv()
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::EcdsaPublicKeys(resp) = resp {
-                        Ok(resp)
-                    } else {
-                        //unreachable!()
-                        Ok(vec![])
-                    }
-                })
-                .or_else::<(), _>(|_| Ok(vec![]))
-                .unwrap(),
+    async fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
+        match self.request(RemoteKeystore::EcdsaPublicKeys(id)).await {
+            Ok(RemoteKeystoreResponse::EcdsaPublicKeys(resp)) => resp,
+            _ => vec![],
         }
     }
 
     #[instrument]
-    fn ecdsa_generate_new(
+    async fn ecdsa_generate_new(
         &self,
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ecdsa::Public, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::EcdsaGenerateNew {
-            id,
-            seed: seed.map(|s| s.to_string()),
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::EcdsaGenerateNew(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::EcdsaGenerateNew {
+                id,
+                seed: seed.map(|s| s.to_string()),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::EcdsaGenerateNew(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn insert_unknown(&self, key_type: KeyTypeId, suri: &str, public: &[u8]) -> Result<(), ()> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(())?;
-
-        match client.tx.send(RemoteKeystore::InsertUnknown {
-            id: key_type,
-            suri: suri.to_string(),
-            public: Vec::from(public),
-        }) {
-            Err(_) => Err(()),
-            Ok(_) => client.rx.recv().map_err(|_| ()).and_then(|resp| {
-                if let RemoteKeystoreResponse::InsertUnknown(resp) = resp {
-                    resp
-                } else {
-                    //unreachable!()
-                    Err(())
-                }
-            }),
+    async fn insert_unknown(
+        &self,
+        key_type: KeyTypeId,
+        suri: &str,
+        public: &[u8],
+    ) -> Result<(), ()> {
+        match self
+            .request(RemoteKeystore::InsertUnknown {
+                id: key_type,
+                suri: suri.to_string(),
+                public: Vec::from(public),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::InsertUnknown(resp)) => resp,
+            _ => Err(()),
         }
     }
 
     #[instrument]
-    fn supported_keys(
+    async fn supported_keys(
         &self,
         id: KeyTypeId,
         keys: Vec<CryptoTypePublicPair>,
     ) -> Result<Vec<CryptoTypePublicPair>, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::SupportedKeys { id, keys }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::SupportedKeys(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::SupportedKeys { id, keys })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::SupportedKeys(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::Keys(id)) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Keys(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+    async fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, Error> {
+        match self.request(RemoteKeystore::Keys(id)).await {
+            Ok(RemoteKeystoreResponse::Keys(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
-        let client = self.client();
-        let client = match client.as_ref() {
-            Some(c) => c,
-            None => return false,
-        };
-
-        match client
-            .tx
-            .send(RemoteKeystore::HasKeys(public_keys.to_vec()))
+    async fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
+        match self
+            .request(RemoteKeystore::HasKeys(public_keys.to_vec()))
+            .await
         {
-            Err(_) => false,
-            Ok(_) => client
-                .rx
-                .recv()
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::HasKeys(resp) = resp {
-                        Ok(resp)
-                    } else {
-                        //unreachable!()
-                        Ok(false)
-                    }
-                })
-                .or_else::<(), _>(|_| Ok(false))
-                .unwrap(),
+            Ok(RemoteKeystoreResponse::HasKeys(resp)) => resp,
+            _ => false,
         }
     }
 
     #[instrument]
-    fn sign_with(
+    async fn sign_with(
         &self,
         id: KeyTypeId,
         key: &CryptoTypePublicPair,
         msg: &[u8],
     ) -> Result<Vec<u8>, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::SignWith {
-            id,
-            key: key.clone(),
-            msg: msg.to_vec(),
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::SignWith(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::SignWith {
+                id,
+                key: key.clone(),
+                msg: msg.to_vec(),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::SignWith(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn sign_with_any(
+    async fn sign_with_any(
         &self,
         id: KeyTypeId,
         keys: Vec<CryptoTypePublicPair>,
         msg: &[u8],
     ) -> Result<(CryptoTypePublicPair, Vec<u8>), Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::SignWithAny {
-            id,
-            keys,
-            msg: msg.to_vec(),
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::SignWithAny(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::SignWithAny {
+                id,
+                keys,
+                msg: msg.to_vec(),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::SignWithAny(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 
     #[instrument]
-    fn sign_with_all(
+    async fn sign_with_all(
         &self,
         id: KeyTypeId,
         keys: Vec<CryptoTypePublicPair>,
         msg: &[u8],
     ) -> Result<Vec<Result<Vec<u8>, Error>>, ()> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(())?;
-
-        match client.tx.send(RemoteKeystore::SignWithAll {
-            id,
-            keys,
-            msg: msg.to_vec(),
-        }) {
-            Err(_) => Err(()),
-            Ok(_) => client.rx.recv().map_err(|_| ()).and_then(|resp| {
-                if let RemoteKeystoreResponse::SignWithAll(resp) = resp {
-                    resp
-                } else {
-                    //unreachable!()
-                    Err(())
-                }
-            }),
+        match self
+            .request(RemoteKeystore::SignWithAll {
+                id,
+                keys,
+                msg: msg.to_vec(),
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::SignWithAll(resp)) => resp,
+            _ => Err(()),
         }
     }
 
     #[instrument]
-    fn sr25519_vrf_sign(
+    async fn sr25519_vrf_sign(
         &self,
         key_type: KeyTypeId,
         public: &sr25519::Public,
         transcript_data: sp_keystore::vrf::VRFTranscriptData,
     ) -> Result<sp_keystore::vrf::VRFSignature, Error> {
-        let client = self.client();
-        let client = client.as_ref().ok_or(Error::Unavailable)?;
-
-        match client.tx.send(RemoteKeystore::Sr25519VrfSign {
-            key_type,
-            public: *public,
-            transcript_data,
-        }) {
-            Err(_) => Err(Error::Unavailable),
-            Ok(_) => client
-                .rx
-                .recv()
-                .map_err(|_| Error::Unavailable)
-                .and_then(|resp| {
-                    if let RemoteKeystoreResponse::Sr25519VrfSign(resp) = resp {
-                        resp
-                    } else {
-                        //unreachable!()
-                        Err(Error::Unavailable)
-                    }
-                }),
+        match self
+            .request(RemoteKeystore::Sr25519VrfSign {
+                key_type,
+                public: *public,
+                transcript_data,
+            })
+            .await
+        {
+            Ok(RemoteKeystoreResponse::Sr25519VrfSign(resp)) => resp,
+            _ => Err(Error::Unavailable),
         }
     }
 }
@@ -2,7 +2,7 @@ use super::*;
 
 impl sp_keystore::SyncCryptoStore for TEEKeystore {
     fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
-        self.sr25519_public_keys(id)
+        self.block_on(self.sr25519_public_keys(id))
     }
 
     fn sr25519_generate_new(
@@ -10,11 +10,11 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<sr25519::Public, sp_keystore::Error> {
-        self.sr25519_generate_new(id, seed)
+        self.block_on(self.sr25519_generate_new(id, seed))
     }
 
     fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
-        self.ed25519_public_keys(id)
+        self.block_on(self.ed25519_public_keys(id))
     }
 
     fn ed25519_generate_new(
@@ -22,11 +22,11 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ed25519::Public, sp_keystore::Error> {
-        self.ed25519_generate_new(id, seed)
+        self.block_on(self.ed25519_generate_new(id, seed))
     }
 
     fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
-        self.ecdsa_public_keys(id)
+        self.block_on(self.ecdsa_public_keys(id))
     }
 
     fn ecdsa_generate_new(
@@ -34,11 +34,11 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         id: KeyTypeId,
         seed: Option<&str>,
     ) -> Result<ecdsa::Public, sp_keystore::Error> {
-        self.ecdsa_generate_new(id, seed)
+        self.block_on(self.ecdsa_generate_new(id, seed))
     }
 
     fn insert_unknown(&self, id: KeyTypeId, suri: &str, public: &[u8]) -> Result<(), ()> {
-        self.insert_unknown(id, suri, public)
+        self.block_on(self.insert_unknown(id, suri, public))
     }
 
     fn supported_keys(
@@ -46,15 +46,15 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         id: KeyTypeId,
         keys: Vec<CryptoTypePublicPair>,
     ) -> Result<Vec<CryptoTypePublicPair>, sp_keystore::Error> {
-        self.supported_keys(id, keys)
+        self.block_on(self.supported_keys(id, keys))
     }
 
     fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, sp_keystore::Error> {
-        self.keys(id)
+        self.block_on(self.keys(id))
     }
 
     fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
-        self.has_keys(public_keys)
+        self.block_on(self.has_keys(public_keys))
     }
 
     fn sign_with(
@@ -63,7 +63,25 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         key: &CryptoTypePublicPair,
         msg: &[u8],
     ) -> Result<Vec<u8>, sp_keystore::Error> {
-        self.sign_with(id, key, msg)
+        self.block_on(self.sign_with(id, key, msg))
+    }
+
+    fn sign_with_any(
+        &self,
+        id: KeyTypeId,
+        keys: Vec<CryptoTypePublicPair>,
+        msg: &[u8],
+    ) -> Result<(CryptoTypePublicPair, Vec<u8>), sp_keystore::Error> {
+        self.block_on(self.sign_with_any(id, keys, msg))
+    }
+
+    fn sign_with_all(
+        &self,
+        id: KeyTypeId,
+        keys: Vec<CryptoTypePublicPair>,
+        msg: &[u8],
+    ) -> Result<Vec<Result<Vec<u8>, sp_keystore::Error>>, ()> {
+        self.block_on(self.sign_with_all(id, keys, msg))
     }
 
     fn sr25519_vrf_sign(
@@ -72,7 +90,7 @@ impl sp_keystore::SyncCryptoStore for TEEKeystore {
         public: &sr25519::Public,
         transcript_data: sp_keystore::vrf::VRFTranscriptData,
     ) -> Result<sp_keystore::vrf::VRFSignature, sp_keystore::Error> {
-        self.sr25519_vrf_sign(key_type, public, transcript_data)
+        self.block_on(self.sr25519_vrf_sign(key_type, public, transcript_data))
         // Err(Error::Unavailable)
     }
 }